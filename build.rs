@@ -4,8 +4,25 @@ use std::io::Read;
 
 fn main() {
     let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
-    let mut config = cbindgen::Config::default();
-    config.language = cbindgen::Language::C;
+    let version = |part| std::env::var(format!("CARGO_PKG_VERSION_{}", part)).unwrap();
+    // C callers can check these against lather_version() to catch a mismatched library
+    let header = format!(
+        "#define LATHER_VERSION_MAJOR {}\n#define LATHER_VERSION_MINOR {}\n\
+         #define LATHER_VERSION_PATCH {}\n#define LATHER_VERSION \"{}\"",
+        version("MAJOR"),
+        version("MINOR"),
+        version("PATCH"),
+        std::env::var("CARGO_PKG_VERSION").unwrap()
+    );
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        header: Some(header),
+        include_guard: Some("LATHER_H".to_string()),
+        autogen_warning: Some(
+            "/* Generated by cbindgen from the lather crate, do not edit */".to_string(),
+        ),
+        ..Default::default()
+    };
     cbindgen::generate_with_config(&crate_dir, config)
         .unwrap()
         .write_to_file("target/lather.h");
@@ -33,7 +50,7 @@ fn main() {
 
     let _ = writeln!(output, "pub const CCF_LEN: usize = {};\n", rv.len());
 
-    for &(ref name, ref array) in &[("RV", rv), ("CCF_QUIET", ccf_quiet), ("CCF_SPOT", ccf_spot)] {
+    for (name, array) in &[("RV", rv), ("CCF_QUIET", ccf_quiet), ("CCF_SPOT", ccf_spot)] {
         let _ = write!(output, "pub static {}: [f64; {}] = [", name, array.len());
         for val in array {
             let _ = write!(output, "{:.6e}, ", val);
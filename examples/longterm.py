@@ -12,11 +12,14 @@ print(time)
 sim = lather.Simulation('sun.toml')
 print(sim)
 
-flux_b = sim.observe_flux(time, 4000e-10, 5000e-10)
-flux_r = sim.observe_flux(time, 7000e-10, 8000e-10)
+wave_starts = [4000e-10, 7000e-10]
+wave_ends = [5000e-10, 8000e-10]
 
-rv_b, bisectors_b = sim.observe_rv(time, 4000e-10, 5000e-10)
-rv_r, bisectors_r = sim.observe_rv(time, 7000e-10, 8000e-10)
+flux = sim.observe_flux_bands(time, wave_starts, wave_ends)
+flux_b, flux_r = flux[:, 0], flux[:, 1]
+
+rv, ccfs = sim.observe_rv_bands(time, wave_starts, wave_ends)
+rv_b, rv_r = rv[:, 0], rv[:, 1]
 
 
 plt.subplot(211)
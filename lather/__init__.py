@@ -1,8 +1,19 @@
+import contextlib
+import threading
+
 import numpy as np
 import scipy.interpolate
 import scipy.signal
 import toml
 from lather._native import ffi, lib
+from lather.errors import (
+    ConfigParseError,
+    LatherError,
+    LatherIOError,
+    NumericalError,
+    SpotGenerationError,
+    ValidationError,
+)
 
 __version__ = "0.1.0"
 
@@ -13,18 +24,154 @@ RV_FOR_CCFS = np.frombuffer(
 ).copy()
 
 
+_ERRORS = {
+    lib.LatherErrorCode_Io: LatherIOError,
+    lib.LatherErrorCode_Parse: ConfigParseError,
+    lib.LatherErrorCode_Validation: ValidationError,
+    lib.LatherErrorCode_Numerical: NumericalError,
+}
+
+
+def _take_string(ptr):
+    """Decode and free a string owned by the native library"""
+    try:
+        return ffi.string(ptr).decode("utf-8")
+    finally:
+        lib.lather_string_free(ptr)
+
+
+def _raise(error_ptr, diagnostics=None):
+    """Raise the error of the native call that failed last on this thread"""
+    message = _take_string(error_ptr[0])
+    code = lib.lather_last_error_code()
+    if code == lib.LatherErrorCode_Generation and diagnostics is not None:
+        raise SpotGenerationError(
+            message,
+            failure="size_draws"
+            if diagnostics.failure == lib.SizeDraws
+            else "placement",
+            time=diagnostics.time,
+            target_fill_factor=diagnostics.target_fill_factor,
+            fill_factor=diagnostics.fill_factor,
+            spots=diagnostics.spots,
+            size_draws=diagnostics.size_draws,
+            rejected_sizes=diagnostics.rejected_sizes,
+            collisions=diagnostics.collisions,
+        )
+    raise _ERRORS.get(code, LatherError)(message)
+
+
+class _ReadWriteLock:
+    """A lock held by any number of readers at once, or by a single writer"""
+
+    def __init__(self):
+        self._condition = threading.Condition()
+        self._readers = 0
+
+    @contextlib.contextmanager
+    def reading(self):
+        with self._condition:
+            self._readers += 1
+        try:
+            yield
+        finally:
+            self._stop_reading()
+
+    @contextlib.contextmanager
+    def writing(self):
+        with self._condition:
+            self._condition.wait_for(lambda: self._readers == 0)
+            yield
+
+    @contextlib.contextmanager
+    def reading_after(self, write):
+        """Call `write` as the writer, then read without letting another writer in"""
+        with self._condition:
+            self._condition.wait_for(lambda: self._readers == 0)
+            write()
+            self._readers += 1
+        try:
+            yield
+        finally:
+            self._stop_reading()
+
+    def _stop_reading(self):
+        with self._condition:
+            self._readers -= 1
+            if self._readers == 0:
+                self._condition.notify_all()
+
+
 class Simulation:
+    """A star with spots that can be observed
+
+    Observations release the GIL and only read the simulation, so one
+    simulation can be observed from several threads at once. Random spots are
+    generated before observing the first time they are needed, and methods that
+    change the simulation wait for observations in progress to finish.
+    Observing before any time observed so far generates every random spot again
+    from there, so call `generate_spots` first to keep the same spots throughout.
+    """
+
     def __init__(self, filename):
         error_ptr = ffi.new("char **")
-        self._native = lib.simulation_new(filename.encode(), error_ptr)
-
-        if self._native == ffi.NULL:
-            error = ffi.string(error_ptr[0]).decode("utf-8")
-            raise RuntimeError(error)
+        native = lib.simulation_new(filename.encode(), error_ptr)
+        if native == ffi.NULL:
+            _raise(error_ptr)
 
         config = toml.load(filename)
+        self._setup(
+            native, "schedule" in config, config.get("instrument_resolution", None)
+        )
+
+    @classmethod
+    def load_state(cls, filename, instrument_resolution=None):
+        """Resume a simulation from a file written by `save_state`
+
+        The resumed simulation draws the same random spots and noise as the saved
+        one would have. The instrument resolution isn't part of the state, so it
+        has to be given again.
+        """
+        error_ptr = ffi.new("char **")
+        native = lib.simulation_load_state(filename.encode(), error_ptr)
+        if native == ffi.NULL:
+            _raise(error_ptr)
+
+        sim = cls.__new__(cls)
+        sim._setup(
+            native, lib.simulation_schedule_len(native) > 0, instrument_resolution
+        )
+        return sim
+
+    @property
+    def seed(self):
+        """The seed random spots and noise are drawn from
+
+        A config with `seed = "entropy"` draws it from the system, and this is
+        how to find out what it was.
+        """
+        with self._lock.reading():
+            return lib.simulation_seed(self._native)
+
+    def save_state(self, filename):
+        """Save everything about the simulation to a TOML file
+
+        This includes the random spots and how far the random number generators
+        have got, so `load_state` can resume a long run exactly where it was.
+        """
+        error_ptr = ffi.new("char **")
+        with self._lock.reading():
+            saved = lib.simulation_save_state(
+                self._native, filename.encode(), error_ptr
+            )
+        if not saved:
+            _raise(error_ptr)
+
+    def _setup(self, native, has_schedule, instrument_resolution):
+        self._native = native
+        self._lock = _ReadWriteLock()
+        self._has_schedule = has_schedule
         self.instrument_profile = None
-        instrument_resolution = config.get("instrument_resolution", None)
         if instrument_resolution is not None:
             c = 299792458.0  # speed of light in m/s
             instrument_profile_fwhm = c / instrument_resolution
@@ -35,7 +182,7 @@ class Simulation:
                 -RV_FOR_CCFS ** 2 / (2 * (instrument_profile_sigma) ** 2)
             )
 
-        quiet_ccf = np.empty(lib.ccf_len(), dtype=np.float64)
+        quiet_ccf = np.empty(lib.simulation_ccf_len(self._native), dtype=np.float64)
         quiet_ccf_ptr = ffi.cast("double *", quiet_ccf.ctypes.data)
         lib.simulation_get_quiet_ccf(self._native, quiet_ccf_ptr)
 
@@ -43,32 +190,193 @@ class Simulation:
         self.zero_rv = compute_rv(quiet_ccf)
 
     def __repr__(self):
-        return ffi.string(lib.simulation_tostring(self._native)).decode("utf-8")
+        with self._lock.reading():
+            return _take_string(lib.simulation_tostring(self._native))
 
-    # there is potential to double-free here because I'm not making this pointer null
-    # I think... not sure if the del saves us
     def __del__(self):
-        lib.simulation_free(self._native)
-        del self._native
+        native = getattr(self, "_native", ffi.NULL)
+        self._native = ffi.NULL
+        lib.simulation_free(native)
+
+    def generate_spots(self, start, end):
+        """Generate random spots between the times `start` and `end` in days
+
+        Any previously generated spots are replaced. Observing any time in this
+        window sees the same star, regardless of what else has been observed.
+        """
+        diagnostics = ffi.new("GenerationError *")
+        error_ptr = ffi.new("char **")
+        with self._lock.writing():
+            generated = lib.simulation_generate_spots(
+                self._native, start, end, diagnostics, error_ptr
+            )
+        if not generated:
+            _raise(error_ptr, diagnostics)
+
+    @contextlib.contextmanager
+    def _observing(self, time_ptr, n_times):
+        """Generate any spots needed at these times, then read the simulation
+
+        Nothing can change the simulation until the block ends, so everything
+        done in it, including adding noise, sees the same spots and seed.
+        """
+        diagnostics = ffi.new("GenerationError *")
+        error_ptr = ffi.new("char **")
 
-    def observe_flux(self, time, wave_start, wave_end):
+        def ensure_spots():
+            if not lib.simulation_ensure_spots(
+                self._native, time_ptr, n_times, diagnostics, error_ptr
+            ):
+                _raise(error_ptr, diagnostics)
+
+        with self._lock.reading_after(ensure_spots):
+            yield
+
+    def _observe(self, observe, time_ptr, n_times, *args):
+        """Call a native observation, first generating any spots it needs"""
+        with self._observing(time_ptr, n_times):
+            return observe(self._native, time_ptr, n_times, *args)
+
+    def schedule(self):
+        """Generate observation times in days from the observing schedule in the config
+
+        Returns None if the config has no schedule.
+        """
+        if not self._has_schedule:
+            return None
+        with self._lock.reading():
+            times = np.empty(lib.simulation_schedule_len(self._native))
+            times_ptr = ffi.cast("double *", times.ctypes.data)
+            lib.simulation_schedule_times(self._native, times_ptr)
+        return times
+
+    def set_exposure(self, duration, sub_exposures=None, max_phase_step=1e-3):
+        """Set the length of each exposure in days.
+
+        Each observation is averaged over `sub_exposures` instantaneous
+        observations, or if that is None, over enough that the star rotates by
+        at most `max_phase_step` of a rotation between them.
+        """
+        error_ptr = ffi.new("char **")
+        with self._lock.writing():
+            ok = lib.simulation_set_exposure(
+                self._native, duration, sub_exposures or 0, max_phase_step, error_ptr
+            )
+        if not ok:
+            _raise(error_ptr)
+
+    def set_phase_grid(self, samples=1000):
+        """Precompute immortal spots at `samples` phases of a rotation.
+
+        Observations then interpolate between phases instead of integrating
+        each spot at every time, which makes long time series much cheaper.
+        At least 4 samples are needed. Pass None to integrate at every time
+        again.
+        """
+        error_ptr = ffi.new("char **")
+        with self._lock.writing():
+            ok = lib.simulation_set_phase_grid(self._native, samples or 0, error_ptr)
+        if not ok:
+            _raise(error_ptr)
+
+    def set_tolerance(self, flux=None, rv=None):
+        """Choose each spot's strip spacing to meet discretization error tolerances.
+
+        `flux` is in relative flux and `rv` in m/s. With neither, spots are
+        integrated on the star's grid again.
+        """
+        with self._lock.writing():
+            lib.simulation_set_tolerance(self._native, flux or 0.0, rv or 0.0)
+
+    def estimate_errors(self, time, wave_start, wave_end):
+        """Observe the flux, with estimates of the discretization errors
+
+        Returns the flux at each time, its estimated error, and the estimated
+        error in RV (m/s) of the CCF at each time.
+        """
+        time_ptr = ffi.cast("double *", time.ctypes.data)
+        flux = np.empty_like(time)
+        flux_error = np.empty_like(time)
+        rv_error = np.empty_like(time)
+        error_ptr = ffi.new("char **")
+        if not self._observe(
+            lib.simulation_estimate_errors,
+            time_ptr,
+            time.size,
+            wave_start,
+            wave_end,
+            ffi.cast("double *", flux.ctypes.data),
+            ffi.cast("double *", flux_error.ctypes.data),
+            ffi.cast("double *", rv_error.ctypes.data),
+            error_ptr,
+        ):
+            _raise(error_ptr)
+        return flux, flux_error, rv_error
+
+    def observe_flux(self, time, wave_start, wave_end, instrument=None):
+        """Observe the relative flux at each time
+
+        If `instrument` names an instrument in the config, its noise is added.
+        """
         time_ptr = ffi.cast("double *", time.ctypes.data)
         flux = np.empty_like(time)
         flux_ptr = ffi.cast("double *", flux.ctypes.data)
-        lib.simulation_observe_flux(
-            self._native, time_ptr, time.size, wave_start, wave_end, flux_ptr
-        )
+        error_ptr = ffi.new("char **")
+        with self._observing(time_ptr, time.size):
+            ok = lib.simulation_observe_flux(
+                self._native,
+                time_ptr,
+                time.size,
+                wave_start,
+                wave_end,
+                flux_ptr,
+                error_ptr,
+            )
+            if ok and instrument is not None:
+                ok = lib.simulation_apply_flux_noise(
+                    self._native,
+                    instrument.encode(),
+                    time_ptr,
+                    time.size,
+                    flux_ptr,
+                    error_ptr,
+                )
+        if not ok:
+            _raise(error_ptr)
         return flux
 
-    def observe_rv(self, time, wave_start, wave_end):
+    def observe_rv(self, time, wave_start, wave_end, instrument=None):
+        """Observe the rv and CCF at each time
+
+        If `instrument` names an instrument in the config, its noise is added.
+        """
         time_ptr = ffi.cast("double *", time.ctypes.data)
 
-        ccfs = np.empty((time.size, lib.ccf_len()))
+        ccfs = np.empty((time.size, lib.simulation_ccf_len(self._native)))
         ccfs_ptr = ffi.cast("double *", ccfs.ctypes.data)
 
-        lib.simulation_observe_rv(
-            self._native, time_ptr, time.size, wave_start, wave_end, ccfs_ptr
-        )
+        error_ptr = ffi.new("char **")
+        with self._observing(time_ptr, time.size):
+            ok = lib.simulation_observe_rv(
+                self._native,
+                time_ptr,
+                time.size,
+                wave_start,
+                wave_end,
+                ccfs_ptr,
+                error_ptr,
+            )
+            if ok and instrument is not None:
+                ok = lib.simulation_apply_ccf_noise(
+                    self._native,
+                    instrument.encode(),
+                    time_ptr,
+                    time.size,
+                    ccfs_ptr,
+                    error_ptr,
+                )
+        if not ok:
+            _raise(error_ptr)
 
         rv = np.empty(time.size)
         for i in range(time.size):
@@ -77,6 +385,137 @@ class Simulation:
 
         return rv, ccfs
 
+    def observe_flux_bands(self, time, wave_starts, wave_ends):
+        """Observe the flux in several wavelength bands at once.
+
+        Returns an array of shape (len(time), len(wave_starts)).
+        """
+        wave_starts = np.ascontiguousarray(wave_starts, dtype=np.float64)
+        wave_ends = np.ascontiguousarray(wave_ends, dtype=np.float64)
+        time_ptr = ffi.cast("double *", time.ctypes.data)
+        starts_ptr = ffi.cast("double *", wave_starts.ctypes.data)
+        ends_ptr = ffi.cast("double *", wave_ends.ctypes.data)
+
+        flux = np.empty((time.size, wave_starts.size))
+        flux_ptr = ffi.cast("double *", flux.ctypes.data)
+        error_ptr = ffi.new("char **")
+        if not self._observe(
+            lib.simulation_observe_flux_bands,
+            time_ptr,
+            time.size,
+            starts_ptr,
+            ends_ptr,
+            wave_starts.size,
+            flux_ptr,
+            error_ptr,
+        ):
+            _raise(error_ptr)
+        return flux
+
+    def observe_rv_bands(self, time, wave_starts, wave_ends):
+        """Observe the rv and CCFs in several wavelength bands, such as echelle orders, at once.
+
+        Returns rv of shape (len(time), len(wave_starts)) and the CCFs
+        of shape (len(time), len(wave_starts), ccf_len).
+        """
+        wave_starts = np.ascontiguousarray(wave_starts, dtype=np.float64)
+        wave_ends = np.ascontiguousarray(wave_ends, dtype=np.float64)
+        time_ptr = ffi.cast("double *", time.ctypes.data)
+        starts_ptr = ffi.cast("double *", wave_starts.ctypes.data)
+        ends_ptr = ffi.cast("double *", wave_ends.ctypes.data)
+
+        ccfs = np.empty(
+            (time.size, wave_starts.size, lib.simulation_ccf_len(self._native))
+        )
+        ccfs_ptr = ffi.cast("double *", ccfs.ctypes.data)
+        error_ptr = ffi.new("char **")
+        if not self._observe(
+            lib.simulation_observe_rv_bands,
+            time_ptr,
+            time.size,
+            starts_ptr,
+            ends_ptr,
+            wave_starts.size,
+            ccfs_ptr,
+            error_ptr,
+        ):
+            _raise(error_ptr)
+
+        rv = np.empty((time.size, wave_starts.size))
+        for i in range(time.size):
+            for j in range(wave_starts.size):
+                ccfs[i, j] = self._apply_resolution_correction(ccfs[i, j])
+                rv[i, j] = compute_rv(ccfs[i, j]) - self.zero_rv
+
+        return rv, ccfs
+
+    def observe_line_groups(self, time, wave_start, wave_end):
+        """
+        Compute the CCF of each of the star's line groups at each time
+
+        Returns a dict mapping each group's name to its radial velocities and an array of
+        CCFs, one row per time
+        """
+        names = []
+        rvs = []
+        with self._lock.reading():
+            for group in range(lib.simulation_line_group_count(self._native)):
+                names.append(
+                    _take_string(lib.simulation_line_group_name(self._native, group))
+                )
+                rv = np.empty(lib.simulation_line_group_len(self._native, group))
+                lib.simulation_line_group_rv(
+                    self._native, group, ffi.cast("double *", rv.ctypes.data)
+                )
+                rvs.append(rv)
+
+        time_ptr = ffi.cast("double *", time.ctypes.data)
+        ccfs = np.empty(time.size * sum(rv.size for rv in rvs))
+        ccfs_ptr = ffi.cast("double *", ccfs.ctypes.data)
+        error_ptr = ffi.new("char **")
+        if not self._observe(
+            lib.simulation_observe_line_groups,
+            time_ptr,
+            time.size,
+            wave_start,
+            wave_end,
+            ccfs_ptr,
+            error_ptr,
+        ):
+            _raise(error_ptr)
+
+        ccfs = ccfs.reshape(time.size, -1)
+        output = {}
+        start = 0
+        for name, rv in zip(names, rvs):
+            output[name] = (rv, ccfs[:, start : start + rv.size])
+            start += rv.size
+        return output
+
+    def observe_spectrum(self, time):
+        """
+        Compute disk-integrated spectra from the star's template spectra at each time
+
+        Returns the wavelengths in meters and an array of spectra, one row per time
+        """
+        with self._lock.reading():
+            n_wavelengths = lib.simulation_spectrum_len(self._native)
+            wavelength = np.empty(n_wavelengths)
+            wavelength_ptr = ffi.cast("double *", wavelength.ctypes.data)
+            lib.simulation_spectrum_wavelength(self._native, wavelength_ptr)
+
+        time_ptr = ffi.cast("double *", time.ctypes.data)
+        spectra = np.empty((time.size, n_wavelengths))
+        spectra_ptr = ffi.cast("double *", spectra.ctypes.data)
+        error_ptr = ffi.new("char **")
+
+        if not self._observe(
+            lib.simulation_observe_spectrum, time_ptr, time.size, spectra_ptr, error_ptr
+        ):
+            _raise(error_ptr)
+
+        return wavelength, spectra
+
     # This would be implemented in the Rust part but I can't make the FFT work
     def _apply_resolution_correction(self, ccf):
         ccf = ccf.copy()
@@ -89,18 +528,30 @@ class Simulation:
             return scipy.signal.convolve(ccf, self.instrument_profile, mode="same")
 
     def draw_bgr(self, time, out=None):
+        shape = (lib.lather_image_size(), lib.lather_image_size(), 3)
         if out is None:
-            image = np.empty((1000, 1000, 3), dtype=np.uint8)
-        elif (out.dtype != np.uint8) or (out.shape != (1000, 1000, 3)):
+            image = np.empty(shape, dtype=np.uint8)
+        elif (out.dtype != np.uint8) or (out.shape != shape):
             raise ValueError(
-                "image argument must be an array of np.uint, with shape (1000, 1000, 3)"
+                "image argument must be an array of np.uint8, "
+                "with shape {}".format(shape)
             )
         else:
             image = out
 
         image_ptr = ffi.cast("char *", image.ctypes.data)
 
-        lib.simulation_draw_bgr(self._native, time, image_ptr)
+        time = np.array([time], dtype=np.float64)
+        time_ptr = ffi.cast("double *", time.ctypes.data)
+        error_ptr = ffi.new("char **")
+        if not self._observe(
+            lambda sim, time_ptr, n_times: lib.simulation_draw_bgr(
+                sim, time_ptr[0], image_ptr, error_ptr
+            ),
+            time_ptr,
+            time.size,
+        ):
+            _raise(error_ptr)
         return image
 
 
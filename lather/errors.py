@@ -0,0 +1,54 @@
+"""The errors raised by simulations, from either the cffi or the native bindings"""
+
+
+class LatherError(RuntimeError):
+    """Base class of the errors raised by a simulation"""
+
+
+class LatherIOError(LatherError, OSError):
+    """A config or data file could not be read"""
+
+
+class ConfigParseError(LatherError, ValueError):
+    """A config or data file is not in the expected format"""
+
+
+class ValidationError(LatherError, ValueError):
+    """A parameter is out of range or not valid for the simulation's state"""
+
+
+class NumericalError(LatherError, ArithmeticError):
+    """A calculation produced something unusable, like a star with no flux"""
+
+
+class SpotGenerationError(LatherError):
+    """Random spots could not reach the star's target fill factor
+
+    The attributes describe where generation gave up: `failure` is either
+    "size_draws" or "placement", and `time`, `target_fill_factor`,
+    `fill_factor`, `spots`, `size_draws`, `rejected_sizes`, and `collisions`
+    are as in the Rust `GenerationError`.
+    """
+
+    def __init__(
+        self,
+        message,
+        *,
+        failure,
+        time,
+        target_fill_factor,
+        fill_factor,
+        spots,
+        size_draws,
+        rejected_sizes,
+        collisions
+    ):
+        super().__init__(message)
+        self.failure = failure
+        self.time = time
+        self.target_fill_factor = target_fill_factor
+        self.fill_factor = fill_factor
+        self.spots = spots
+        self.size_draws = size_draws
+        self.rejected_sizes = rejected_sizes
+        self.collisions = collisions
@@ -1,4 +1,5 @@
 from setuptools import setup
+from setuptools_rust import Binding, RustExtension
 import shutil
 import os
 
@@ -27,9 +28,12 @@ setup(
     packages=["lather"],
     zip_safe=False,
     platforms="any",
-    setup_requires=["milksnake"],
+    setup_requires=["milksnake", "setuptools-rust"],
     install_requires=["milksnake", "numpy", "scipy", "opencv-python", "toml"],
     milksnake_tasks=[build_native],
+    rust_extensions=[
+        RustExtension("lather.native", features=["python"], binding=Binding.PyO3)
+    ],
     scripts=scripts,
     project_urls={
         'Source': 'https://github.com/saethlin/rust-lather/',
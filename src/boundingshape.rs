@@ -1,6 +1,7 @@
 use std::f64::consts;
 
 use crate::bounds::Bounds;
+#[cfg(test)]
 use crate::linspace::floatrange;
 use crate::point::Point;
 use crate::spot::{Mortality, Spot};
@@ -14,7 +15,6 @@ pub struct BoundingShape {
     circle_radius: f64,
     max_radius: f64,
     visible: bool,
-    grid_interval: f64,
 }
 
 impl BoundingShape {
@@ -75,7 +75,6 @@ impl BoundingShape {
         let x2 = circle_center.x
             + circle_radius * ((theta_x_min).cos() * a.x + (theta_x_min).sin() * b.x);
 
-        let grid_interval = 2.0 / spot.star.grid_size as f64;
         let visible = x1 > 0.0 || x2 > 0.0;
 
         BoundingShape {
@@ -87,7 +86,6 @@ impl BoundingShape {
             circle_radius,
             max_radius,
             visible,
-            grid_interval,
         }
     }
 
@@ -124,96 +122,87 @@ impl BoundingShape {
         }
     }
 
-    // TODO: There ought to be a way to do something clever here using the last iteration's
-    // bounds, but so far I can't make it work. So we just use the brute force method.
-    #[cfg(feature = "experimental")]
-    pub fn z_bounds(&self, y: f64, guess: &mut Option<Bounds>) -> Option<Bounds> {
+    /// Computes the range of z covered by the spot in the strip of the visible disk at `y`.
+    /// A spot is the set of points on the unit sphere within a chord length of `radius` of
+    /// its center C, so a point P is on it when P·C >= 1 - radius²/2. Along the strip,
+    /// P = (s cos φ, y, s sin φ) with s = sqrt(1 - y²) and φ in [-π/2, π/2] on the visible
+    /// hemisphere, which turns the condition into s R cos(φ - α) >= 1 - radius²/2 - y C.y
+    /// where (R, α) is (C.x, C.z) in polar form. That is an arc of φ centered on α, which is
+    /// clipped to the visible hemisphere and mapped to z = s sin φ.
+    ///
+    /// This is exact, and does not depend on the star's grid size. If the spot covers both
+    /// ends of a strip but not its middle, the bounds span the gap.
+    pub fn z_bounds(&self, y: f64) -> Option<Bounds> {
         if y.abs() >= 1.0 {
             return None;
         }
-
-        let new_bounds = match *guess {
-            Some(ref the_guess) => self.z_bounds_ansatz(y, &the_guess),
-            None => self.z_bounds_brute(y),
+        let s = (1.0 - y * y).sqrt();
+        let threshold = 1.0 - self.radius * self.radius / 2.0 - y * self.center.y;
+        let r = self.center.x.hypot(self.center.z);
+
+        let arc = if s * r <= threshold.abs() * f64::EPSILON {
+            // Every point of the strip is the same distance from the center
+            if threshold <= 0.0 {
+                Some((-consts::FRAC_PI_2, consts::FRAC_PI_2))
+            } else {
+                None
+            }
+        } else {
+            let cosine = threshold / (s * r);
+            if cosine > 1.0 {
+                None
+            } else if cosine <= -1.0 {
+                Some((-consts::FRAC_PI_2, consts::FRAC_PI_2))
+            } else {
+                let alpha = self.center.z.atan2(self.center.x);
+                let half_width = cosine.acos();
+                // The arc may wrap around the back of the star into the visible hemisphere
+                [-2.0 * consts::PI, 0.0, 2.0 * consts::PI]
+                    .iter()
+                    .filter_map(|offset| {
+                        let lower = (alpha - half_width + offset).max(-consts::FRAC_PI_2);
+                        let upper = (alpha + half_width + offset).min(consts::FRAC_PI_2);
+                        if lower <= upper {
+                            Some((lower, upper))
+                        } else {
+                            None
+                        }
+                    })
+                    .fold(None, |hull: Option<(f64, f64)>, (lower, upper)| {
+                        Some(match hull {
+                            Some((l, u)) => (l.min(lower), u.max(upper)),
+                            None => (lower, upper),
+                        })
+                    })
+            }
         };
 
-        *guess = new_bounds;
-        new_bounds
+        arc.map(|(lower, upper)| Bounds::new(s * lower.sin(), s * upper.sin()))
     }
 
-    #[cfg(not(feature = "experimental"))]
-    pub fn z_bounds(&self, y: f64, _guess: &mut Option<Bounds>) -> Option<Bounds> {
+    /// Finds the z bounds by marching in from the edges of the spot in half-steps until
+    /// reaching a point on it. This is how bounds were found before `z_bounds`, and is
+    /// only kept to check it.
+    #[cfg(test)]
+    fn z_bounds_brute(&self, y: f64, grid_interval: f64) -> Option<Bounds> {
         if y.abs() >= 1.0 {
             return None;
         }
-
-        self.z_bounds_brute(y)
-    }
-
-    #[cfg(feature = "experimental")]
-    fn z_bounds_ansatz(&self, y: f64, guess: &Bounds) -> Option<Bounds> {
-        use linspace::floatrange;
-        let z_max = if !self.on_spot(y, guess.upper) {
-            // Guess must be above the edge, walk down until on it
-            floatrange(
-                guess.upper,
-                self.center.z - self.radius,
-                -self.grid_interval / 1.0,
-            )
-            .find(|z| self.on_spot(y, *z))
-        } else {
-            // Guess must be below the edge, walk up until not on it
-            floatrange(
-                guess.upper,
-                self.center.z + self.radius,
-                self.grid_interval / 1.0,
-            )
-            .find(|z| !self.on_spot(y, *z))
-        };
-
-        let z_min = if !self.on_spot(y, guess.lower) {
-            // Guess must be below the edge, walk up until on it
-            floatrange(
-                guess.lower,
-                self.center.z + self.radius,
-                self.grid_interval / 1.0,
-            )
-            .find(|z| self.on_spot(y, *z))
-        } else {
-            // Guess must be above the edge, walk down until not on it
-            floatrange(
-                guess.lower,
-                self.center.z - self.radius,
-                -self.grid_interval / 1.0,
-            )
-            .find(|z| !self.on_spot(y, *z))
-        };
-
-        // NOTE: This function used to have a special case for if the edges of a spot
-        // passed to the far hemisphere. Those checks now appear to be unnecessary.
-        if let (Some(z_min), Some(z_max)) = (z_min, z_max) {
-            Some(Bounds::new(z_min, z_max))
-        } else {
-            None
-        }
-    }
-
-    fn z_bounds_brute(&self, y: f64) -> Option<Bounds> {
         let z_max = floatrange(
             self.center.z + self.radius,
             self.center.z - self.radius,
-            -self.grid_interval / 2.0,
+            -grid_interval / 2.0,
         )
         .find(|z| self.on_spot(y, *z))
-        .map(|z| z + self.grid_interval / 2.0);
+        .map(|z| z + grid_interval / 2.0);
 
         let z_min = floatrange(
             self.center.z - self.radius,
             self.center.z + self.radius,
-            self.grid_interval / 2.0,
+            grid_interval / 2.0,
         )
         .find(|z| self.on_spot(y, *z))
-        .map(|z| z - self.grid_interval / 2.0);
+        .map(|z| z - grid_interval / 2.0);
 
         match (z_min, z_max) {
             (Some(min), Some(max)) => Some(Bounds::new(min, max)),
@@ -221,7 +210,8 @@ impl BoundingShape {
         }
     }
 
-    pub fn on_spot(&self, y: f64, z: f64) -> bool {
+    #[cfg(test)]
+    fn on_spot(&self, y: f64, z: f64) -> bool {
         if !on_star(y, z) {
             return false;
         }
@@ -242,6 +232,71 @@ impl BoundingShape {
     }
 }
 
+#[cfg(test)]
 fn on_star(y: f64, z: f64) -> bool {
     (y * y + z * z) <= 1.0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::spot::SpotConfig;
+    use std::sync::Arc;
+
+    #[test]
+    fn analytic_bounds_match_brute_force() {
+        let sim = Simulation::sun();
+        let step = 1e-4;
+        for &(latitude, longitude, fill_factor) in &[
+            (0.0, 0.0, 0.01),
+            (30.0, 45.0, 0.001),
+            (-60.0, 88.0, 0.02),
+            (10.0, 95.0, 0.01),
+            (80.0, 200.0, 0.05),
+        ] {
+            let spot = Spot::from_config(
+                Arc::clone(&sim.star),
+                &SpotConfig {
+                    latitude,
+                    longitude,
+                    fill_factor,
+                    plage: false,
+                    temperature: None,
+                    lifetime: None,
+                },
+            )
+            .unwrap();
+            let bounds = BoundingShape::new(&spot, 0.0);
+            let y_bounds = match bounds.y_bounds() {
+                Some(y_bounds) => y_bounds,
+                None => continue,
+            };
+            for y in floatrange(
+                y_bounds.lower,
+                y_bounds.upper,
+                (y_bounds.upper - y_bounds.lower) / 17.0,
+            ) {
+                match (bounds.z_bounds(y), bounds.z_bounds_brute(y, step)) {
+                    (Some(exact), Some(brute)) => {
+                        assert!((exact.lower - brute.lower).abs() <= step);
+                        assert!((exact.upper - brute.upper).abs() <= step);
+                        // The edges found are on the edge of the spot
+                        assert!(
+                            bounds.on_spot(y, exact.lower + 1e-9)
+                                || exact.lower + 1e-9 > exact.upper
+                        );
+                        assert!(
+                            !bounds.on_spot(y, exact.lower - 1e-6)
+                                || exact.lower <= -(1.0 - y * y).sqrt() + 1e-6
+                        );
+                    }
+                    (None, None) => {}
+                    // Strips which barely touch the spot can be missed by the march
+                    (Some(exact), None) => assert!(exact.upper - exact.lower < 2.0 * step),
+                    (None, Some(_)) => panic!("the analytic bounds missed a strip at y = {}", y),
+                }
+            }
+        }
+    }
+}
@@ -1,5 +1,7 @@
+use serde::{Deserialize, Serialize};
+
 /// Boundaries or the extent of some quantity
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Bounds {
     pub lower: f64,
     pub upper: f64,
@@ -0,0 +1,331 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::convergence::Tolerance;
+use crate::distributions::DistributionConfig;
+use crate::error::LatherError;
+use crate::exposure::{Exposure, ExposureConfig};
+use crate::noise::{Instrument, InstrumentConfig};
+use crate::phase_grid;
+use crate::profile::Interpolation;
+use crate::simulation::{Simulation, DEFAULT_SEED};
+use crate::spot::SpotConfig;
+use crate::spot_size::SpotSizeConfig;
+use crate::star::{Star, StarConfig};
+
+/// Builds a star from parameters instead of a config file. Every parameter starts out
+/// as the Sun's, with no random spots.
+#[derive(Clone)]
+pub struct StarBuilder {
+    config: StarConfig,
+}
+
+impl Default for StarBuilder {
+    fn default() -> Self {
+        StarBuilder::new()
+    }
+}
+
+impl StarBuilder {
+    pub fn new() -> StarBuilder {
+        StarBuilder::from_config(StarConfig {
+            grid_size: 1000,
+            radius: 1.0,
+            period: 25.05,
+            inclination: 90.0,
+            temperature: 5778.0,
+            spot_temp_diff: 663.0,
+            limb_linear: 0.29,
+            limb_quadratic: 0.34,
+            target_fill_factor: None,
+            minimum_fill_factor: None,
+            max_size_draws: None,
+            max_placement_attempts: None,
+            latitude_distribution: None,
+            longitude_distribution: None,
+            fillfactor_distribution: None,
+            spot_size: None,
+            lifetime_distribution: None,
+            spectral_model: None,
+            synthesis: None,
+            line_groups: None,
+            cache_dir: None,
+            interpolation: None,
+            relativistic_doppler: None,
+        })
+    }
+
+    /// Starts from an existing config, such as the star section of a config file.
+    pub fn from_config(config: StarConfig) -> StarBuilder {
+        StarBuilder { config }
+    }
+
+    /// The config the star will be built from.
+    pub fn config(&self) -> &StarConfig {
+        &self.config
+    }
+
+    /// The number of strips the disk is integrated over.
+    pub fn grid_size(mut self, grid_size: usize) -> Self {
+        self.config.grid_size = grid_size;
+        self
+    }
+
+    /// The radius in solar radii.
+    pub fn radius(mut self, radius: f64) -> Self {
+        self.config.radius = radius;
+        self
+    }
+
+    /// The rotation period in days.
+    pub fn period(mut self, period: f64) -> Self {
+        self.config.period = period;
+        self
+    }
+
+    /// The inclination of the rotation axis in degrees, where 90 is equator-on.
+    pub fn inclination(mut self, inclination: f64) -> Self {
+        self.config.inclination = inclination;
+        self
+    }
+
+    /// The temperature of the quiet photosphere in Kelvin.
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.config.temperature = temperature;
+        self
+    }
+
+    /// How much cooler spots are than the photosphere, in Kelvin.
+    pub fn spot_temp_diff(mut self, spot_temp_diff: f64) -> Self {
+        self.config.spot_temp_diff = spot_temp_diff;
+        self
+    }
+
+    /// The quadratic limb darkening coefficients.
+    pub fn limb_darkening(mut self, linear: f64, quadratic: f64) -> Self {
+        self.config.limb_linear = linear;
+        self.config.limb_quadratic = quadratic;
+        self
+    }
+
+    /// The fraction of the star random spots are generated to cover.
+    pub fn target_fill_factor(mut self, target_fill_factor: f64) -> Self {
+        self.config.target_fill_factor = Some(target_fill_factor);
+        self
+    }
+
+    /// The distribution of sizes of random spots.
+    pub fn spot_size(mut self, spot_size: SpotSizeConfig) -> Self {
+        self.config.spot_size = Some(spot_size);
+        self.config.fillfactor_distribution = None;
+        self
+    }
+
+    /// The distribution of latitudes of random spots, in degrees.
+    pub fn latitude_distribution(mut self, distribution: DistributionConfig) -> Self {
+        self.config.latitude_distribution = Some(distribution);
+        self
+    }
+
+    /// The distribution of longitudes of random spots, in degrees.
+    pub fn longitude_distribution(mut self, distribution: DistributionConfig) -> Self {
+        self.config.longitude_distribution = Some(distribution);
+        self
+    }
+
+    /// The distribution of lifetimes of random spots, in days.
+    pub fn lifetime_distribution(mut self, distribution: DistributionConfig) -> Self {
+        self.config.lifetime_distribution = Some(distribution);
+        self
+    }
+
+    /// How profiles are interpolated when they are Doppler shifted.
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.config.interpolation = Some(interpolation);
+        self
+    }
+
+    /// Whether to use the exact relativistic Doppler shift.
+    pub fn relativistic_doppler(mut self, relativistic: bool) -> Self {
+        self.config.relativistic_doppler = Some(relativistic);
+        self
+    }
+
+    /// A directory to keep integrated quiet stars in.
+    pub fn cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.config.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    pub fn build(&self) -> Result<Star, LatherError> {
+        Star::from_config(&self.config)
+    }
+}
+
+/// Builds a simulation of a star from parameters instead of a config file.
+pub struct SimulationBuilder {
+    star: Arc<Star>,
+    seed: u64,
+    spots: Vec<SpotConfig>,
+    exposure: Option<ExposureConfig>,
+    instruments: Vec<InstrumentConfig>,
+    phase_grid_samples: Option<usize>,
+    tolerance: Option<Tolerance>,
+}
+
+impl SimulationBuilder {
+    pub fn new(star: impl Into<Arc<Star>>) -> SimulationBuilder {
+        SimulationBuilder {
+            star: star.into(),
+            seed: DEFAULT_SEED,
+            spots: Vec::new(),
+            exposure: None,
+            instruments: Vec::new(),
+            phase_grid_samples: None,
+            tolerance: None,
+        }
+    }
+
+    /// Seeds the RNG random spots and noise are drawn from.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Adds a spot to the star.
+    pub fn spot(mut self, spot: SpotConfig) -> Self {
+        self.spots.push(spot);
+        self
+    }
+
+    /// Averages each observation over an exposure.
+    pub fn exposure(mut self, exposure: ExposureConfig) -> Self {
+        self.exposure = Some(exposure);
+        self
+    }
+
+    /// Adds an instrument whose noise can be added to observations.
+    pub fn instrument(mut self, instrument: InstrumentConfig) -> Self {
+        self.instruments.push(instrument);
+        self
+    }
+
+    /// Precomputes immortal spots at `samples` phases of a rotation.
+    pub fn phase_grid(mut self, samples: usize) -> Self {
+        self.phase_grid_samples = Some(samples);
+        self
+    }
+
+    /// Chooses the strip spacing of spots to meet the tolerance.
+    pub fn tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = Some(tolerance);
+        self
+    }
+
+    pub fn build(self) -> Result<Simulation, LatherError> {
+        if let Some(samples) = self.phase_grid_samples {
+            phase_grid::check_samples(samples)?;
+        }
+        let mut sim = Simulation::with_star(self.star, self.seed);
+        if let Some(exposure) = &self.exposure {
+            sim.exposure = Exposure::from_config(exposure)?;
+        }
+        sim.instruments = self
+            .instruments
+            .iter()
+            .map(Instrument::from_config)
+            .collect::<Result<Vec<_>, _>>()?;
+        sim.set_tolerance(self.tolerance);
+        for spot in &self.spots {
+            sim.add_spot(spot)?;
+        }
+        sim.set_phase_grid(self.phase_grid_samples);
+        Ok(sim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounds::Bounds;
+
+    #[test]
+    fn builder_matches_config() {
+        let config = "
+            seed = 7
+
+            [star]
+            grid_size = 500
+            radius = 1.0
+            period = 10.0
+            inclination = 60.0
+            temperature = 5000.0
+            spot_temp_diff = 500.0
+            limb_linear = 0.4
+            limb_quadratic = 0.2
+
+            [[spots]]
+            latitude = 20.0
+            longitude = 90.0
+            fill_factor = 0.01
+            lifetime = {start = 1.0, end = 8.0}
+
+            [[spots]]
+            latitude = -10.0
+            longitude = 200.0
+            fill_factor = 0.005
+            temperature = 4000.0
+        ";
+        let from_config = Simulation::from_toml_str(config).unwrap();
+
+        let star = StarBuilder::new()
+            .grid_size(500)
+            .period(10.0)
+            .inclination(60.0)
+            .temperature(5000.0)
+            .spot_temp_diff(500.0)
+            .limb_darkening(0.4, 0.2)
+            .build()
+            .unwrap();
+        let built = Simulation::builder(star)
+            .seed(7)
+            .spot(SpotConfig::new(20.0, 90.0, 0.01).with_lifetime(1.0, 8.0))
+            .spot(SpotConfig::new(-10.0, 200.0, 0.005).with_temperature(4000.0))
+            .build()
+            .unwrap();
+
+        let band = Bounds::new(4000e-10, 5000e-10);
+        let time = [0.0, 2.5, 5.0, 9.0];
+        assert_eq!(
+            built.observe_flux(&time, band).unwrap(),
+            from_config.observe_flux(&time, band).unwrap()
+        );
+        assert_eq!(
+            built.observe_rv(&time, band).unwrap(),
+            from_config.observe_rv(&time, band).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_small_phase_grid() {
+        let star = StarBuilder::new().grid_size(100).build().unwrap();
+        assert!(Simulation::builder(star).phase_grid(2).build().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_exposure() {
+        use crate::exposure::SupersampleConfig;
+
+        let star = StarBuilder::new().grid_size(100).build().unwrap();
+        let exposure = ExposureConfig {
+            duration: 1.0,
+            supersample: Some(SupersampleConfig::Adaptive {
+                max_phase_step: 0.0,
+            }),
+        };
+        assert!(Simulation::builder(star)
+            .exposure(exposure)
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,307 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::LatherError;
+use crate::spot::{Mortality, Spot};
+use crate::star::Star;
+
+/// How much hotter than the photosphere plage regions are (in K) if it isn't configured,
+/// as in SOAP 2.0.
+pub const DEFAULT_PLAGE_TEMP_DIFF: f64 = 250.0;
+
+/// Regions this large (in micro-hemispheres) or larger don't fit on one side of the star.
+const MAX_AREA: f64 = 5e5;
+
+fn default_plage_temp_diff() -> f64 {
+    DEFAULT_PLAGE_TEMP_DIFF
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct CatalogConfig {
+    pub path: String,
+    /// How much hotter than the photosphere plage regions are (in K)
+    pub plage_temp_diff: Option<f64>,
+}
+
+/// One observation of an active region in a catalog.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct Sample {
+    pub time: f64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub area: f64,
+}
+
+/// The history of a single active region, sorted by time.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Track {
+    pub id: String,
+    pub plage: bool,
+    pub samples: Vec<Sample>,
+}
+
+impl Track {
+    /// Linearly interpolates this region's position and area at `time`, or returns
+    /// `None` if the region was not recorded before and after `time`.
+    pub fn at(&self, time: f64) -> Option<Sample> {
+        let first = self.samples.first()?;
+        let last = self.samples.last()?;
+        if time < first.time || time > last.time {
+            return None;
+        }
+
+        let after = self
+            .samples
+            .iter()
+            .position(|s| s.time >= time)
+            .unwrap_or(self.samples.len() - 1);
+        let next = self.samples[after];
+        if after == 0 || next.time == time {
+            return Some(next);
+        }
+        let previous = self.samples[after - 1];
+
+        let fraction = (time - previous.time) / (next.time - previous.time);
+        let lerp = |a: f64, b: f64| a + (b - a) * fraction;
+
+        // Take the short way around if the region crosses longitude 0
+        let mut next_longitude = next.longitude;
+        if next_longitude - previous.longitude > 180.0 {
+            next_longitude -= 360.0;
+        } else if previous.longitude - next_longitude > 180.0 {
+            next_longitude += 360.0;
+        }
+
+        Some(Sample {
+            time,
+            latitude: lerp(previous.latitude, next.latitude),
+            longitude: lerp(previous.longitude, next_longitude).rem_euclid(360.0),
+            area: lerp(previous.area, next.area),
+        })
+    }
+}
+
+/// A time-dependent record of active regions, such as a historical sunspot group catalog.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpotCatalog {
+    pub tracks: Vec<Track>,
+    /// How much hotter than the photosphere plage regions are (in K). Spot regions are
+    /// as much cooler as the star's spots.
+    #[serde(default = "default_plage_temp_diff")]
+    pub plage_temp_diff: f64,
+}
+
+impl SpotCatalog {
+    /// Loads a catalog from a CSV file with the columns time (days), latitude (degrees),
+    /// longitude (degrees), area (micro-hemispheres), type (spot or plage), and id.
+    /// Rows with the same id are treated as observations of the same region.
+    /// A header row and lines starting with `#` are ignored.
+    pub fn from_file(path: &Path) -> Result<SpotCatalog, LatherError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| LatherError::io(path, e))?;
+
+        let mut tracks: Vec<Track> = Vec::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("time") {
+                continue;
+            }
+            let bad_line = || {
+                LatherError::parse(
+                    path,
+                    format!(
+                        "Line {} should be `time,latitude,longitude,area,type,id`, but is {:?}",
+                        line_number + 1,
+                        line
+                    ),
+                )
+            };
+            let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+            if fields.len() != 6 {
+                return Err(bad_line());
+            }
+            let finite = |field: &str| {
+                field
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(bad_line)
+            };
+            let sample = Sample {
+                time: finite(fields[0])?,
+                latitude: finite(fields[1])?,
+                longitude: finite(fields[2])?,
+                area: finite(fields[3])?,
+            };
+            let invalid = |message: String| {
+                LatherError::parse(path, format!("Line {}: {}", line_number + 1, message))
+            };
+            if !(-90.0..=90.0).contains(&sample.latitude) {
+                return Err(invalid(format!(
+                    "The latitude must be from -90 to 90 degrees, but is {}",
+                    sample.latitude
+                )));
+            }
+            // The same limit as a spot's fill factor of 0.5
+            if !(0.0..MAX_AREA).contains(&sample.area) {
+                return Err(invalid(format!(
+                    "The area must be at least 0 and less than {} micro-hemispheres, but is {}",
+                    MAX_AREA, sample.area
+                )));
+            }
+            let plage = match fields[4] {
+                "spot" => false,
+                "plage" => true,
+                _ => return Err(bad_line()),
+            };
+            let id = fields[5];
+
+            match tracks.iter_mut().find(|t| t.id == id) {
+                Some(track) => {
+                    if track.plage != plage {
+                        return Err(LatherError::Validation(format!(
+                            "Region {} in spot catalog {:?} is recorded as both a spot and a plage",
+                            id, path
+                        )));
+                    }
+                    track.samples.push(sample);
+                }
+                None => tracks.push(Track {
+                    id: id.to_string(),
+                    plage,
+                    samples: vec![sample],
+                }),
+            }
+        }
+
+        for track in &mut tracks {
+            track.samples.sort_by(|a, b| a.time.total_cmp(&b.time));
+        }
+
+        Ok(SpotCatalog {
+            tracks,
+            plage_temp_diff: DEFAULT_PLAGE_TEMP_DIFF,
+        })
+    }
+
+    /// Loads a catalog as configured.
+    pub fn from_config(
+        config: &CatalogConfig,
+        config_dir: &Path,
+    ) -> Result<SpotCatalog, LatherError> {
+        let mut catalog = SpotCatalog::from_file(&config_dir.join(&config.path))?;
+        if let Some(plage_temp_diff) = config.plage_temp_diff {
+            if !plage_temp_diff.is_finite() || plage_temp_diff < 0.0 {
+                return Err(LatherError::Validation(format!(
+                    "The plage temperature difference must be at least zero, but is {}",
+                    plage_temp_diff
+                )));
+            }
+            catalog.plage_temp_diff = plage_temp_diff;
+        }
+        Ok(catalog)
+    }
+
+    /// The temperature of a region in this catalog on `star`.
+    pub fn temperature(&self, star: &Star, plage: bool) -> f64 {
+        if plage {
+            star.temperature + self.plage_temp_diff
+        } else {
+            star.temperature - star.spot_temp_diff
+        }
+    }
+
+    /// Constructs the spots described by this catalog at `time`.
+    pub fn spots_at(&self, star: &Arc<Star>, time: f64) -> Vec<Spot> {
+        self.tracks
+            .iter()
+            .filter_map(|track| {
+                track.at(time).map(|sample| Spot {
+                    star: Arc::clone(star),
+                    latitude: sample.latitude.to_radians(),
+                    longitude: sample.longitude.to_radians(),
+                    // One micro-hemisphere is a fill factor of 1e-6
+                    radius: (2.0 * sample.area * 1e-6).sqrt(),
+                    temperature: self.temperature(star, track.plage),
+                    plage: track.plage,
+                    mortality: Mortality::Immortal,
+                    generated: false,
+                    phase_grid: None,
+                    strip_step: None,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(samples: &[(f64, f64, f64, f64)]) -> Track {
+        Track {
+            id: "1".to_string(),
+            plage: false,
+            samples: samples
+                .iter()
+                .map(|&(time, latitude, longitude, area)| Sample {
+                    time,
+                    latitude,
+                    longitude,
+                    area,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn interpolates_between_samples() {
+        let track = track(&[(0.0, 10.0, 100.0, 200.0), (2.0, 12.0, 110.0, 100.0)]);
+        let sample = track.at(1.0).unwrap();
+        assert_eq!(sample.latitude, 11.0);
+        assert_eq!(sample.longitude, 105.0);
+        assert_eq!(sample.area, 150.0);
+        assert_eq!(track.at(2.0).unwrap().area, 100.0);
+    }
+
+    #[test]
+    fn absent_outside_of_record() {
+        let track = track(&[(1.0, 10.0, 100.0, 200.0), (2.0, 12.0, 110.0, 100.0)]);
+        assert!(track.at(0.5).is_none());
+        assert!(track.at(2.5).is_none());
+    }
+
+    #[test]
+    fn wraps_longitude() {
+        let track = track(&[(0.0, 0.0, 350.0, 100.0), (1.0, 0.0, 10.0, 100.0)]);
+        assert!((track.at(0.25).unwrap().longitude - 355.0).abs() < 1e-12);
+        assert!((track.at(0.75).unwrap().longitude - 5.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn example_catalog_is_valid() {
+        let catalog = SpotCatalog::from_file(Path::new("examples/sunspot_catalog.csv")).unwrap();
+        assert_eq!(catalog.tracks.len(), 3);
+    }
+
+    #[test]
+    fn nan_times_are_rejected() {
+        let path = std::env::temp_dir().join(format!("lather-nan-{}.csv", std::process::id()));
+        std::fs::write(&path, "0.0,10,100,50,spot,1\nnan,10,100,50,spot,1\n").unwrap();
+        let catalog = SpotCatalog::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(catalog, Err(LatherError::Parse { .. })));
+    }
+
+    #[test]
+    fn impossible_regions_are_rejected() {
+        let path = std::env::temp_dir().join(format!("lather-area-{}.csv", std::process::id()));
+        for line in &["0.0,10,100,5e5,spot,1", "0.0,95,100,50,spot,1"] {
+            std::fs::write(&path, line).unwrap();
+            let catalog = SpotCatalog::from_file(&path);
+            assert!(matches!(catalog, Err(LatherError::Parse { .. })));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
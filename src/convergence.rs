@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::LatherError;
+use crate::spot::{Mortality, Spot};
+
+/// Errors are estimated assuming they shrink at least in proportion to the strip spacing.
+/// If the error at spacing h is C h, the difference from h/2 is C h / 2.
+const ERROR_PER_DIFFERENCE: f64 = 2.0;
+
+/// Adaptive strips start with this many across a spot's diameter.
+const INITIAL_STRIPS: f64 = 8.0;
+
+/// Strips are refined at most this many times, halving their spacing each time.
+const MAX_REFINEMENTS: usize = 12;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ResolutionConfig {
+    pub flux_tolerance: Option<f64>,
+    pub rv_tolerance: Option<f64>,
+}
+
+/// The largest discretization errors allowed for each spot, in relative flux and in RV (m/s).
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Tolerance {
+    pub flux: f64,
+    pub rv: f64,
+}
+
+impl Tolerance {
+    pub fn from_config(config: &ResolutionConfig) -> Result<Tolerance, LatherError> {
+        if config.flux_tolerance.is_none() && config.rv_tolerance.is_none() {
+            return Err(LatherError::Validation(
+                "The resolution config needs a flux_tolerance, an rv_tolerance, or both"
+                    .to_string(),
+            ));
+        }
+        let tolerance = Tolerance {
+            flux: config.flux_tolerance.unwrap_or(f64::INFINITY),
+            rv: config.rv_tolerance.unwrap_or(f64::INFINITY),
+        };
+        if tolerance.flux.is_nan()
+            || tolerance.flux <= 0.0
+            || tolerance.rv.is_nan()
+            || tolerance.rv <= 0.0
+        {
+            return Err(LatherError::Validation(format!(
+                "Resolution tolerances must be positive, but are {} (flux) and {} (rv)",
+                tolerance.flux, tolerance.rv
+            )));
+        }
+        Ok(tolerance)
+    }
+}
+
+/// Observations with estimates of their discretization error, from Richardson's method:
+/// everything is observed again with every spot's strips half as far apart, and the
+/// difference scaled to the error at the original spacing. The quiet star is integrated on
+/// the star's grid both times, so its error isn't included.
+#[derive(Debug)]
+pub struct DiscretizationError {
+    pub flux: Vec<f64>,
+    pub flux_error: Vec<f64>,
+    pub ccfs: Vec<Vec<f64>>,
+    /// The RV shift (m/s) that the difference between the CCFs would cause
+    pub rv_error: Vec<f64>,
+}
+
+impl DiscretizationError {
+    pub fn new(
+        flux: Vec<f64>,
+        fine_flux: &[f64],
+        ccfs: Vec<Vec<f64>>,
+        fine_ccfs: &[Vec<f64>],
+        rv: &[f64],
+    ) -> DiscretizationError {
+        let flux_error = flux
+            .iter()
+            .zip(fine_flux.iter())
+            .map(|(coarse, fine)| ERROR_PER_DIFFERENCE * (coarse - fine).abs())
+            .collect();
+        let rv_error = ccfs
+            .iter()
+            .zip(fine_ccfs.iter())
+            .map(|(coarse, fine)| {
+                let change: Vec<f64> = coarse.iter().zip(fine.iter()).map(|(c, f)| c - f).collect();
+                ERROR_PER_DIFFERENCE * rv_shift(coarse, rv, &change).abs()
+            })
+            .collect();
+        DiscretizationError {
+            flux,
+            flux_error,
+            ccfs,
+            rv_error,
+        }
+    }
+}
+
+/// The RV shift (m/s) of `ccf` that best matches adding `change` to it, to first order.
+/// A shift of δv changes the CCF by -δv dCCF/dv, so the least-squares δv is
+/// -Σ change dCCF/dv / Σ (dCCF/dv)² (Bouchy et al. 2001).
+pub fn rv_shift(ccf: &[f64], rv: &[f64], change: &[f64]) -> f64 {
+    let mut numerator = 0.0;
+    let mut denominator = 0.0;
+    for i in 1..ccf.len().saturating_sub(1) {
+        let slope = (ccf[i + 1] - ccf[i - 1]) / (rv[i + 1] - rv[i - 1]);
+        numerator += change[i] * slope;
+        denominator += slope * slope;
+    }
+    if denominator == 0.0 {
+        0.0
+    } else {
+        -numerator / denominator
+    }
+}
+
+/// Chooses the widest strip spacing for `spot` whose estimated errors are within
+/// `tolerance`, by halving the spacing until the change from halving it again is small
+/// enough. The errors are for a completely dark spot, the worst case, at a few phases
+/// around its crossing of the disk.
+pub fn choose_strip_step(spot: &Spot, tolerance: &Tolerance) -> f64 {
+    let star = &spot.star;
+    // Look at the spot at its full size and without any precomputation
+    let mut probe = Spot {
+        mortality: Mortality::Immortal,
+        phase_grid: None,
+        ..spot.clone()
+    };
+    // When the spot crosses the central meridian, and a sixth of a rotation either side
+    let crossing = (-spot.longitude / (2.0 * std::f64::consts::PI)).rem_euclid(1.0) * star.period;
+    let times = [
+        crossing - star.period / 6.0,
+        crossing,
+        crossing + star.period / 6.0,
+    ];
+    let check_rv = tolerance.rv.is_finite();
+    let len = star.profile_quiet.len();
+
+    let observe = |probe: &Spot| -> Vec<(f64, Vec<f64>)> {
+        times
+            .iter()
+            .map(|t| {
+                let flux = probe.limb_integral(*t) / star.flux_quiet;
+                let mut ccf = vec![0.0; len];
+                if check_rv {
+                    let mut active = vec![0.0; len];
+                    probe.add_profile_components(
+                        *t,
+                        &star.profile_quiet,
+                        &star.profile_spot,
+                        &mut ccf,
+                        &mut active,
+                    );
+                }
+                (flux, ccf)
+            })
+            .collect()
+    };
+
+    let mut step = 2.0 * spot.radius / INITIAL_STRIPS;
+    probe.strip_step = Some(step);
+    let mut coarse = observe(&probe);
+    for _ in 0..MAX_REFINEMENTS {
+        probe.strip_step = Some(step / 2.0);
+        let fine = observe(&probe);
+        let converged = coarse.iter().zip(fine.iter()).all(|((cf, cc), (ff, fc))| {
+            let flux_error = ERROR_PER_DIFFERENCE * (cf - ff).abs();
+            let rv_error = if check_rv {
+                let change: Vec<f64> = cc.iter().zip(fc.iter()).map(|(c, f)| c - f).collect();
+                ERROR_PER_DIFFERENCE
+                    * rv_shift(&star.integrated_ccf, &star.profile_quiet.rv, &change).abs()
+            } else {
+                0.0
+            };
+            flux_error <= tolerance.flux && rv_error <= tolerance.rv
+        });
+        if converged {
+            return step;
+        }
+        step /= 2.0;
+        coarse = fine;
+    }
+    step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Profile;
+    use crate::simulation::Simulation;
+    use crate::spot::SpotConfig;
+    use std::sync::Arc;
+
+    #[test]
+    fn rv_shift_of_shifted_gaussian() {
+        let rv: Vec<f64> = (-200..=200).map(|i| i as f64 * 50.0).collect();
+        let ccf: Vec<f64> = rv
+            .iter()
+            .map(|v| 1.0 - 0.5 * (-v * v / (2.0 * 3000.0f64.powi(2))).exp())
+            .collect();
+        let profile = Profile::new(rv.clone(), ccf.clone())
+            .with_interpolation(crate::profile::Interpolation::CubicSpline);
+        let mut shifted = vec![0.0; ccf.len()];
+        profile.shift_into(5.0, &mut shifted);
+        let change: Vec<f64> = shifted.iter().zip(ccf.iter()).map(|(s, c)| s - c).collect();
+        assert!((rv_shift(&ccf, &rv, &change) - 5.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn tighter_tolerances_use_finer_strips() {
+        let sim = Simulation::sun();
+        let spot = Spot::from_config(
+            Arc::clone(&sim.star),
+            &SpotConfig {
+                latitude: 15.0,
+                longitude: 30.0,
+                fill_factor: 0.005,
+                plage: false,
+                temperature: None,
+                lifetime: None,
+            },
+        )
+        .unwrap();
+        let loose = choose_strip_step(
+            &spot,
+            &Tolerance {
+                flux: 1e-4,
+                rv: f64::INFINITY,
+            },
+        );
+        let tight = choose_strip_step(
+            &spot,
+            &Tolerance {
+                flux: 1e-7,
+                rv: f64::INFINITY,
+            },
+        );
+        assert!(tight < loose);
+
+        // The chosen spacing delivers the requested accuracy, compared to a very fine one
+        let mut coarse = spot.clone();
+        coarse.strip_step = Some(tight);
+        let mut fine = spot.clone();
+        fine.strip_step = Some(tight / 16.0);
+        let crossing =
+            (-spot.longitude / (2.0 * std::f64::consts::PI)).rem_euclid(1.0) * sim.star.period;
+        let error = (coarse.limb_integral(crossing) - fine.limb_integral(crossing)).abs()
+            / sim.star.flux_quiet;
+        assert!(error < 1e-7);
+    }
+}
@@ -1,7 +1,13 @@
+use std::convert::TryFrom;
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+
 use rand::Rng;
-use rand_distr::{LogNormal, Normal, StandardNormal, Uniform};
+use rand_distr::{Exp, LogNormal, Normal, StandardNormal, Uniform};
 use serde::{Deserialize, Serialize};
 
+use crate::error::LatherError;
+
 #[derive(Deserialize, Serialize, Clone)]
 #[serde(tag = "name")]
 pub enum DistributionConfig {
@@ -13,6 +19,61 @@ pub enum DistributionConfig {
     Uniform { min: f64, max: f64 },
     #[serde(rename = "normal")]
     Normal { mean: f64, std_dev: f64 },
+    #[serde(rename = "truncated_normal")]
+    TruncatedNormal {
+        mean: f64,
+        std_dev: f64,
+        min: f64,
+        max: f64,
+    },
+    #[serde(rename = "exponential")]
+    Exponential { mean: f64 },
+    #[serde(rename = "power_law")]
+    PowerLaw { index: f64, min: f64, max: f64 },
+    #[serde(rename = "von_mises")]
+    VonMises { mean: f64, kappa: f64 },
+    #[serde(rename = "cos_latitude")]
+    CosLatitude { min: Option<f64>, max: Option<f64> },
+    #[serde(rename = "bimodal")]
+    Bimodal { mean: f64, std_dev: f64 },
+    #[serde(rename = "mixture")]
+    Mixture { components: Vec<MixtureComponent> },
+    #[serde(rename = "empirical")]
+    Empirical {
+        path: PathBuf,
+        format: EmpiricalFormat,
+    },
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct MixtureComponent {
+    pub weight: f64,
+    pub distribution: DistributionConfig,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub enum EmpiricalFormat {
+    /// One sample per line, which are drawn from with replacement
+    #[serde(rename = "samples")]
+    Samples,
+    /// Two columns of value and cumulative probability, linearly interpolated
+    #[serde(rename = "cdf")]
+    Cdf,
+}
+
+impl DistributionConfig {
+    /// Makes any relative paths in this config relative to `dir` instead.
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        match self {
+            DistributionConfig::Mixture { components } => {
+                for component in components {
+                    component.distribution.resolve_paths(dir);
+                }
+            }
+            DistributionConfig::Empirical { path, .. } => *path = dir.join(&*path),
+            _ => {}
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -21,32 +82,524 @@ pub enum Distribution {
     LogNormal(LogNormal<f64>),
     Uniform(Uniform<f64>),
     Normal(Normal<f64>),
+    TruncatedNormal(TruncatedNormal),
+    Exponential(Exp<f64>),
+    PowerLaw(PowerLaw),
+    VonMises(VonMises),
+    CosLatitude(CosLatitude),
+    Bimodal(Normal<f64>),
+    Mixture(Mixture),
+    Empirical(Empirical),
 }
 
 impl Distribution {
-    pub fn sample(&self, rng: &mut rand::rngs::StdRng) -> f64 {
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
         match self {
             Distribution::StandardNormal(ref inner) => rng.sample(inner),
             Distribution::LogNormal(ref inner) => rng.sample(inner),
             Distribution::Uniform(ref inner) => rng.sample(inner),
             Distribution::Normal(ref inner) => rng.sample(inner),
+            Distribution::TruncatedNormal(ref inner) => inner.sample(rng),
+            Distribution::Exponential(ref inner) => rng.sample(inner),
+            Distribution::PowerLaw(ref inner) => inner.sample(rng),
+            Distribution::VonMises(ref inner) => inner.sample(rng),
+            Distribution::CosLatitude(ref inner) => inner.sample(rng),
+            Distribution::Bimodal(ref inner) => {
+                let value = rng.sample(inner);
+                if rng.gen::<bool>() {
+                    value
+                } else {
+                    -value
+                }
+            }
+            Distribution::Mixture(ref inner) => inner.sample(rng),
+            Distribution::Empirical(ref inner) => inner.sample(rng),
         }
     }
 }
 
-impl From<DistributionConfig> for Distribution {
-    fn from(c: DistributionConfig) -> Distribution {
-        match c {
-            DistributionConfig::StandardNormal => Distribution::StandardNormal(StandardNormal),
-            DistributionConfig::LogNormal { mean, std_dev } => {
-                Distribution::LogNormal(LogNormal::new(mean, std_dev).unwrap())
+impl TryFrom<DistributionConfig> for Distribution {
+    type Error = LatherError;
+
+    fn try_from(c: DistributionConfig) -> Result<Distribution, LatherError> {
+        let positive = |name: &str, value: f64| {
+            if value.is_nan() || value <= 0.0 {
+                Err(LatherError::Validation(format!(
+                    "The {} of a distribution must be positive, but is {}",
+                    name, value
+                )))
+            } else {
+                Ok(value)
             }
+        };
+        let ordered = |min: f64, max: f64| {
+            if min.is_nan() || max.is_nan() || min >= max {
+                Err(LatherError::Validation(format!(
+                    "The min of a distribution must be less than its max, but they are {} and {}",
+                    min, max
+                )))
+            } else {
+                Ok(())
+            }
+        };
+
+        Ok(match c {
+            DistributionConfig::StandardNormal => Distribution::StandardNormal(StandardNormal),
+            DistributionConfig::LogNormal { mean, std_dev } => Distribution::LogNormal(
+                LogNormal::new(mean, positive("std_dev", std_dev)?)
+                    .map_err(|e| LatherError::Validation(format!("{:?}", e)))?,
+            ),
             DistributionConfig::Uniform { min, max } => {
+                ordered(min, max)?;
                 Distribution::Uniform(Uniform::new(min, max))
             }
-            DistributionConfig::Normal { mean, std_dev } => {
-                Distribution::Normal(Normal::new(mean, std_dev).unwrap())
+            DistributionConfig::Normal { mean, std_dev } => Distribution::Normal(
+                Normal::new(mean, positive("std_dev", std_dev)?)
+                    .map_err(|e| LatherError::Validation(format!("{:?}", e)))?,
+            ),
+            DistributionConfig::TruncatedNormal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } => {
+                ordered(min, max)?;
+                Distribution::TruncatedNormal(TruncatedNormal {
+                    mean,
+                    std_dev: positive("std_dev", std_dev)?,
+                    min,
+                    max,
+                })
+            }
+            DistributionConfig::Exponential { mean } => Distribution::Exponential(
+                Exp::new(1.0 / positive("mean", mean)?)
+                    .map_err(|e| LatherError::Validation(format!("{:?}", e)))?,
+            ),
+            DistributionConfig::PowerLaw { index, min, max } => {
+                ordered(min, max)?;
+                positive("min", min)?;
+                if index.is_nan() {
+                    return Err(LatherError::Validation(
+                        "The index of a power law distribution is NaN".to_string(),
+                    ));
+                }
+                Distribution::PowerLaw(PowerLaw { index, min, max })
+            }
+            DistributionConfig::VonMises { mean, kappa } => Distribution::VonMises(VonMises {
+                mean,
+                kappa: positive("kappa", kappa)?,
+            }),
+            DistributionConfig::CosLatitude { min, max } => {
+                let min = min.unwrap_or(-90.0);
+                let max = max.unwrap_or(90.0);
+                ordered(min, max)?;
+                if min < -90.0 || max > 90.0 {
+                    return Err(LatherError::Validation(format!(
+                        "Latitudes must be between -90 and 90 degrees, but the bounds are {} and {}",
+                        min, max
+                    )));
+                }
+                Distribution::CosLatitude(CosLatitude { min, max })
+            }
+            DistributionConfig::Bimodal { mean, std_dev } => Distribution::Bimodal(
+                Normal::new(mean, positive("std_dev", std_dev)?)
+                    .map_err(|e| LatherError::Validation(format!("{:?}", e)))?,
+            ),
+            DistributionConfig::Mixture { components } => {
+                let mut total = 0.0;
+                let mut cumulative_weights = Vec::new();
+                let mut distributions = Vec::new();
+                for component in components {
+                    if component.weight.is_nan() || component.weight < 0.0 {
+                        return Err(LatherError::Validation(format!(
+                            "The weights of a mixture distribution must not be negative, but one is {}",
+                            component.weight
+                        )));
+                    }
+                    total += component.weight;
+                    cumulative_weights.push(total);
+                    distributions.push(Distribution::try_from(component.distribution)?);
+                }
+                if total <= 0.0 {
+                    return Err(LatherError::Validation(
+                        "A mixture distribution needs at least one component with a positive weight"
+                            .to_string(),
+                    ));
+                }
+                for weight in &mut cumulative_weights {
+                    *weight /= total;
+                }
+                Distribution::Mixture(Mixture {
+                    cumulative_weights,
+                    distributions,
+                })
+            }
+            DistributionConfig::Empirical { path, format } => {
+                Distribution::Empirical(Empirical::from_file(&path, format)?)
+            }
+        })
+    }
+}
+
+/// A normal distribution restricted to `[min, max]`, sampled with the rejection
+/// algorithms of Robert (1995), which stay efficient far into the tails.
+#[derive(Debug)]
+pub struct TruncatedNormal {
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl TruncatedNormal {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        let a = (self.min - self.mean) / self.std_dev;
+        let b = (self.max - self.mean) / self.std_dev;
+        let z = if a >= 0.0 {
+            standard_tail(a, b, rng)
+        } else if b <= 0.0 {
+            -standard_tail(-b, -a, rng)
+        } else if b - a < (2.0 * PI).sqrt() {
+            // Uniform proposals beat normal ones on narrow intervals around the mean
+            loop {
+                let z = rng.gen_range(a, b);
+                if rng.gen::<f64>() <= (-0.5 * z * z).exp() {
+                    break z;
+                }
+            }
+        } else {
+            loop {
+                let z: f64 = rng.sample(StandardNormal);
+                if z >= a && z <= b {
+                    break z;
+                }
+            }
+        };
+        self.mean + self.std_dev * z
+    }
+}
+
+/// Samples a standard normal restricted to `[a, b]`, where `0 <= a`.
+fn standard_tail<R: Rng + ?Sized>(a: f64, b: f64, rng: &mut R) -> f64 {
+    if b - a < 1.0 {
+        loop {
+            let z = rng.gen_range(a, b);
+            if rng.gen::<f64>() <= (0.5 * (a * a - z * z)).exp() {
+                break z;
+            }
+        }
+    } else {
+        // Exponential proposals with the optimal rate
+        let rate = 0.5 * (a + (a * a + 4.0).sqrt());
+        loop {
+            let z = a - rng.gen::<f64>().ln() / rate;
+            if z <= b && rng.gen::<f64>() <= (-0.5 * (z - rate) * (z - rate)).exp() {
+                break z;
+            }
+        }
+    }
+}
+
+/// A power law with density proportional to `x^index` on `[min, max]`.
+#[derive(Debug)]
+pub struct PowerLaw {
+    pub index: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl PowerLaw {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        let u: f64 = rng.gen();
+        if (self.index + 1.0).abs() < 1e-12 {
+            self.min * (self.max / self.min).powf(u)
+        } else {
+            let k = self.index + 1.0;
+            let low = self.min.powf(k);
+            let high = self.max.powf(k);
+            (low + u * (high - low)).powf(1.0 / k)
+        }
+    }
+}
+
+/// The von Mises distribution, a normal distribution wrapped onto a circle. `mean` and
+/// samples are in degrees, and samples are between 0 and 360.
+#[derive(Debug)]
+pub struct VonMises {
+    pub mean: f64,
+    pub kappa: f64,
+}
+
+impl VonMises {
+    /// Best & Fisher (1979)
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        let tau = 1.0 + (1.0 + 4.0 * self.kappa * self.kappa).sqrt();
+        let rho = (tau - (2.0 * tau).sqrt()) / (2.0 * self.kappa);
+        let r = (1.0 + rho * rho) / (2.0 * rho);
+        let angle = loop {
+            let z = (PI * rng.gen::<f64>()).cos();
+            let f = (1.0 + r * z) / (r + z);
+            let c = self.kappa * (r - f);
+            let u: f64 = rng.gen();
+            if c * (2.0 - c) > u || (c / u).ln() + 1.0 >= c {
+                let angle = f.clamp(-1.0, 1.0).acos();
+                break if rng.gen::<bool>() { angle } else { -angle };
+            }
+        };
+        (self.mean + angle.to_degrees()).rem_euclid(360.0)
+    }
+}
+
+/// Latitudes in degrees distributed uniformly over the surface of a sphere, so the
+/// density is proportional to the cosine of latitude.
+#[derive(Debug)]
+pub struct CosLatitude {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl CosLatitude {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        let low = self.min.to_radians().sin();
+        let high = self.max.to_radians().sin();
+        (low + rng.gen::<f64>() * (high - low))
+            .clamp(-1.0, 1.0)
+            .asin()
+            .to_degrees()
+    }
+}
+
+/// A weighted combination of distributions.
+#[derive(Debug)]
+pub struct Mixture {
+    /// Normalized so the last is 1
+    pub cumulative_weights: Vec<f64>,
+    pub distributions: Vec<Distribution>,
+}
+
+impl Mixture {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        let u: f64 = rng.gen();
+        let index = self
+            .cumulative_weights
+            .partition_point(|w| *w <= u)
+            .min(self.distributions.len() - 1);
+        self.distributions[index].sample(rng)
+    }
+}
+
+/// A distribution defined by data instead of a formula.
+#[derive(Debug)]
+pub enum Empirical {
+    Samples(Vec<f64>),
+    /// Values and their cumulative probabilities, both nondecreasing
+    Cdf {
+        values: Vec<f64>,
+        cdf: Vec<f64>,
+    },
+}
+
+impl Empirical {
+    pub fn from_file(path: &Path, format: EmpiricalFormat) -> Result<Empirical, LatherError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| LatherError::io(path, e))?;
+
+        let columns = match format {
+            EmpiricalFormat::Samples => 1,
+            EmpiricalFormat::Cdf => 2,
+        };
+        let mut rows = Vec::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line
+                .split_whitespace()
+                .map(|f| f.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .ok()
+                .filter(|f| f.len() == columns && f.iter().all(|v| v.is_finite()))
+                .ok_or_else(|| {
+                    LatherError::parse(
+                        path,
+                        format!(
+                            "Line {} should have {} number(s), but is {:?}",
+                            line_number + 1,
+                            columns,
+                            line
+                        ),
+                    )
+                })?;
+            rows.push(fields);
+        }
+
+        match format {
+            EmpiricalFormat::Samples => {
+                if rows.is_empty() {
+                    return Err(LatherError::Validation(format!(
+                        "The empirical distribution {:?} has no samples",
+                        path
+                    )));
+                }
+                Ok(Empirical::Samples(rows.into_iter().map(|r| r[0]).collect()))
+            }
+            EmpiricalFormat::Cdf => {
+                let values: Vec<f64> = rows.iter().map(|r| r[0]).collect();
+                let cdf: Vec<f64> = rows.iter().map(|r| r[1]).collect();
+                Empirical::cdf(values, cdf).map_err(|e| LatherError::parse(path, e.to_string()))
+            }
+        }
+    }
+
+    pub fn cdf(values: Vec<f64>, cdf: Vec<f64>) -> Result<Empirical, LatherError> {
+        if values.len() < 2 || values.len() != cdf.len() {
+            return Err(LatherError::Validation(
+                "A tabulated CDF needs at least two values and probabilities".to_string(),
+            ));
+        }
+        if values.windows(2).any(|v| v[0] > v[1]) || cdf.windows(2).any(|c| c[0] > c[1]) {
+            return Err(LatherError::Validation(
+                "The values and probabilities of a CDF must not decrease".to_string(),
+            ));
+        }
+        if cdf[0] != 0.0 || cdf[cdf.len() - 1] != 1.0 {
+            return Err(LatherError::Validation(
+                "A CDF must go from 0 to 1".to_string(),
+            ));
+        }
+        Ok(Empirical::Cdf { values, cdf })
+    }
+
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match self {
+            Empirical::Samples(samples) => samples[rng.gen_range(0, samples.len())],
+            Empirical::Cdf { values, cdf } => {
+                let u: f64 = rng.gen();
+                // The first probability above u, which exists because u < 1
+                let i = cdf.partition_point(|c| *c <= u).clamp(1, cdf.len() - 1);
+                let fraction = (u - cdf[i - 1]) / (cdf[i] - cdf[i - 1]);
+                values[i - 1] + fraction * (values[i] - values[i - 1])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn samples(config: &str, n: usize) -> Vec<f64> {
+        let config: DistributionConfig = toml::from_str(config).unwrap();
+        let distribution = Distribution::try_from(config).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..n).map(|_| distribution.sample(&mut rng)).collect()
+    }
+
+    fn mean(values: &[f64]) -> f64 {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+
+    #[test]
+    fn truncated_normal_stays_in_bounds() {
+        for bounds in &[
+            "min = -1.0, max = 1.0",
+            "min = 3.0, max = 3.5",
+            "min = 4.0, max = 20.0",
+        ] {
+            let config = format!(
+                "name = \"truncated_normal\"\nmean = 0.0\nstd_dev = 1.0\n{}",
+                bounds.replace(", ", "\n")
+            );
+            let config: DistributionConfig = toml::from_str(&config).unwrap();
+            let (min, max) = match config {
+                DistributionConfig::TruncatedNormal { min, max, .. } => (min, max),
+                _ => unreachable!(),
+            };
+            let distribution = Distribution::try_from(config).unwrap();
+            let mut rng = StdRng::seed_from_u64(0);
+            for _ in 0..1000 {
+                let value = distribution.sample(&mut rng);
+                assert!(value >= min && value <= max);
             }
         }
+        // The tail sampler matches the known mean of a tail, phi(4) / (1 - Phi(4))
+        let values = samples(
+            "name = \"truncated_normal\"\nmean = 0.0\nstd_dev = 1.0\nmin = 4.0\nmax = 100.0",
+            20000,
+        );
+        assert!((mean(&values) - 4.2256).abs() < 0.01);
+    }
+
+    #[test]
+    fn power_law_mean() {
+        // x^-2 on [1, 10] has mean ln(10) / 0.9
+        let values = samples(
+            "name = \"power_law\"\nindex = -2.0\nmin = 1.0\nmax = 10.0",
+            20000,
+        );
+        assert!((mean(&values) - 10f64.ln() / 0.9).abs() < 0.05);
+    }
+
+    #[test]
+    fn von_mises_concentrates() {
+        let values = samples("name = \"von_mises\"\nmean = 350.0\nkappa = 100.0", 2000);
+        for value in &values {
+            assert!((0.0..360.0).contains(value));
+            let offset = (value - 350.0 + 180.0).rem_euclid(360.0) - 180.0;
+            assert!(offset.abs() < 45.0);
+        }
+    }
+
+    #[test]
+    fn cos_latitude_mean_sine() {
+        // sin(latitude) is uniform
+        let values = samples("name = \"cos_latitude\"", 20000);
+        let sines: Vec<f64> = values.iter().map(|v| v.to_radians().sin().abs()).collect();
+        assert!((mean(&sines) - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn bimodal_is_symmetric() {
+        let values = samples("name = \"bimodal\"\nmean = 15.0\nstd_dev = 2.0", 10000);
+        assert!(mean(&values).abs() < 0.5);
+        assert!((mean(&values.iter().map(|v| v.abs()).collect::<Vec<_>>()) - 15.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn mixture_weights() {
+        let values = samples(
+            "name = \"mixture\"\ncomponents = [\
+             {weight = 3.0, distribution = {name = \"uniform\", min = 0.0, max = 1.0}},\
+             {weight = 1.0, distribution = {name = \"uniform\", min = 10.0, max = 11.0}}]",
+            10000,
+        );
+        let high = values.iter().filter(|v| **v > 5.0).count() as f64 / values.len() as f64;
+        assert!((high - 0.25).abs() < 0.02);
+    }
+
+    #[test]
+    fn empirical_cdf_inverts() {
+        let distribution = Empirical::cdf(vec![0.0, 1.0, 3.0], vec![0.0, 0.5, 1.0]).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let values: Vec<f64> = (0..10000).map(|_| distribution.sample(&mut rng)).collect();
+        assert!((mean(&values) - 1.25).abs() < 0.05);
+        assert!(Empirical::cdf(vec![0.0, 1.0], vec![0.0, 0.5]).is_err());
+    }
+
+    #[test]
+    fn bad_parameters_are_errors() {
+        for config in &[
+            "name = \"normal\"\nmean = 0.0\nstd_dev = -1.0",
+            "name = \"uniform\"\nmin = 1.0\nmax = 0.0",
+            "name = \"exponential\"\nmean = 0.0",
+            "name = \"power_law\"\nindex = -2.0\nmin = 0.0\nmax = 1.0",
+            "name = \"mixture\"\ncomponents = []",
+            "name = \"empirical\"\npath = \"does_not_exist.txt\"\nformat = \"samples\"",
+        ] {
+            let config: DistributionConfig = toml::from_str(config).unwrap();
+            assert!(Distribution::try_from(config).is_err());
+        }
     }
 }
@@ -0,0 +1,134 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::generation::GenerationError;
+
+/// Everything that can go wrong building or observing a simulation.
+#[derive(Debug)]
+pub enum LatherError {
+    /// A file couldn't be read or written
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A config or data file isn't in the expected format. The path is `None` for
+    /// configs that didn't come from a file.
+    Parse {
+        path: Option<PathBuf>,
+        message: String,
+    },
+    /// A parameter is out of range, inconsistent with another, or not valid for the
+    /// simulation's current state
+    Validation(String),
+    /// A calculation produced something unusable, like a star with no flux
+    Numerical(String),
+    /// Random spots couldn't reach the star's target fill factor
+    Generation(GenerationError),
+}
+
+/// The kind of a `LatherError`, as reported through the C API.
+///
+/// cbindgen:prefix-with-name
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LatherErrorCode {
+    Ok = 0,
+    Io = 1,
+    Parse = 2,
+    Validation = 3,
+    Numerical = 4,
+    Generation = 5,
+}
+
+impl LatherError {
+    pub fn code(&self) -> LatherErrorCode {
+        match self {
+            LatherError::Io { .. } => LatherErrorCode::Io,
+            LatherError::Parse { .. } => LatherErrorCode::Parse,
+            LatherError::Validation(_) => LatherErrorCode::Validation,
+            LatherError::Numerical(_) => LatherErrorCode::Numerical,
+            LatherError::Generation(_) => LatherErrorCode::Generation,
+        }
+    }
+
+    pub(crate) fn io(path: &Path, source: std::io::Error) -> LatherError {
+        LatherError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub(crate) fn parse(path: &Path, message: impl Into<String>) -> LatherError {
+        LatherError::Parse {
+            path: Some(path.to_path_buf()),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for LatherError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LatherError::Io { path, source } => {
+                write!(f, "Tried to access {:?}, but couldn't: {}", path, source)
+            }
+            LatherError::Parse {
+                path: Some(path),
+                message,
+            } => write!(f, "{:?} is not valid. {}", path, message),
+            LatherError::Parse {
+                path: None,
+                message,
+            } => f.write_str(message),
+            LatherError::Validation(message) | LatherError::Numerical(message) => {
+                f.write_str(message)
+            }
+            LatherError::Generation(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for LatherError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LatherError::Io { source, .. } => Some(source),
+            LatherError::Generation(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<GenerationError> for LatherError {
+    fn from(error: GenerationError) -> LatherError {
+        LatherError::Generation(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Simulation, StarBuilder};
+
+    #[test]
+    fn errors_have_kinds() {
+        match Simulation::from_config(Path::new("examples/missing.toml")) {
+            Err(LatherError::Io { path, .. }) => assert!(path.ends_with("missing.toml")),
+            other => panic!("Expected an I/O error, got {:?}", other.err()),
+        }
+        assert!(matches!(
+            Simulation::from_toml_str("[star]\nradius = \"big\""),
+            Err(LatherError::Parse { path: None, .. })
+        ));
+        assert!(matches!(
+            StarBuilder::new().period(-1.0).build(),
+            Err(LatherError::Validation(_))
+        ));
+        assert!(matches!(
+            StarBuilder::new()
+                .grid_size(100)
+                .limb_darkening(4.0, 0.0)
+                .build(),
+            Err(LatherError::Numerical(_))
+        ));
+    }
+}
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::LatherError;
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "name")]
+pub enum SupersampleConfig {
+    #[serde(rename = "fixed")]
+    Fixed { count: usize },
+    #[serde(rename = "adaptive")]
+    Adaptive { max_phase_step: f64 },
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ExposureConfig {
+    pub duration: f64,
+    pub supersample: Option<SupersampleConfig>,
+}
+
+/// How an exposure is split into instantaneous sub-exposures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Supersample {
+    /// Always use this many sub-exposures
+    Fixed(usize),
+    /// Use enough sub-exposures that the star rotates by at most this fraction
+    /// of a rotation between them
+    Adaptive { max_phase_step: f64 },
+}
+
+/// The length of each observation, over which the star is averaged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exposure {
+    /// Exposure length in days
+    pub duration: f64,
+    pub supersample: Supersample,
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Exposure {
+            duration: 0.0,
+            supersample: Supersample::Fixed(1),
+        }
+    }
+}
+
+impl Exposure {
+    pub fn from_config(config: &ExposureConfig) -> Result<Exposure, LatherError> {
+        if !config.duration.is_finite() || config.duration < 0.0 {
+            return Err(LatherError::Validation(format!(
+                "The exposure duration must be at least 0 days, but is {}",
+                config.duration
+            )));
+        }
+        let supersample = match config.supersample {
+            Some(SupersampleConfig::Fixed { count }) => {
+                if count == 0 {
+                    return Err(LatherError::Validation(
+                        "An exposure needs at least one sub-exposure".to_string(),
+                    ));
+                }
+                Supersample::Fixed(count)
+            }
+            Some(SupersampleConfig::Adaptive { max_phase_step }) => {
+                if !max_phase_step.is_finite() || max_phase_step <= 0.0 {
+                    return Err(LatherError::Validation(format!(
+                        "The maximum phase step between sub-exposures must be positive, but is {}",
+                        max_phase_step
+                    )));
+                }
+                Supersample::Adaptive { max_phase_step }
+            }
+            None => Supersample::Adaptive {
+                max_phase_step: 1e-3,
+            },
+        };
+        Ok(Exposure {
+            duration: config.duration,
+            supersample,
+        })
+    }
+
+    /// The config `from_config` makes this exposure from.
+    pub fn to_config(&self) -> ExposureConfig {
+        let supersample = match self.supersample {
+            Supersample::Fixed(count) => SupersampleConfig::Fixed { count },
+            Supersample::Adaptive { max_phase_step } => {
+                SupersampleConfig::Adaptive { max_phase_step }
+            }
+        };
+        ExposureConfig {
+            duration: self.duration,
+            supersample: Some(supersample),
+        }
+    }
+
+    /// The number of sub-exposures each exposure is split into, on a star with
+    /// this rotation period (in days).
+    pub fn count(&self, period: f64) -> usize {
+        if self.duration == 0.0 {
+            return 1;
+        }
+        match self.supersample {
+            Supersample::Fixed(count) => count.max(1),
+            Supersample::Adaptive { max_phase_step } => {
+                ((self.duration / (period.abs() * max_phase_step)).ceil() as usize).max(1)
+            }
+        }
+    }
+
+    /// The midpoints of the sub-exposures of an exposure centered on `time`.
+    pub fn sub_times(&self, time: f64, period: f64) -> Vec<f64> {
+        let count = self.count(period);
+        let step = self.duration / count as f64;
+        (0..count)
+            .map(|i| time - 0.5 * self.duration + (i as f64 + 0.5) * step)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instantaneous_by_default() {
+        assert_eq!(Exposure::default().sub_times(3.0, 25.0), vec![3.0]);
+    }
+
+    #[test]
+    fn sub_exposures_are_centered() {
+        let exposure = Exposure {
+            duration: 1.0,
+            supersample: Supersample::Fixed(4),
+        };
+        assert_eq!(
+            exposure.sub_times(10.0, 25.0),
+            vec![9.625, 9.875, 10.125, 10.375]
+        );
+    }
+
+    #[test]
+    fn adaptive_limits_phase_step() {
+        let exposure = Exposure {
+            duration: 0.5,
+            supersample: Supersample::Adaptive {
+                max_phase_step: 0.01,
+            },
+        };
+        assert_eq!(exposure.count(10.0), 5);
+        assert_eq!(exposure.count(1.0), 50);
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        let config = |duration, max_phase_step| ExposureConfig {
+            duration,
+            supersample: Some(SupersampleConfig::Adaptive { max_phase_step }),
+        };
+        assert!(Exposure::from_config(&config(1.0, 1e-3)).is_ok());
+        assert!(Exposure::from_config(&config(1.0, 0.0)).is_err());
+        assert!(Exposure::from_config(&config(-1.0, 1e-3)).is_err());
+        assert!(Exposure::from_config(&config(f64::NAN, 1e-3)).is_err());
+        assert!(Exposure::from_config(&config(f64::INFINITY, 1e-3)).is_err());
+    }
+}
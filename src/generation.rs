@@ -0,0 +1,58 @@
+use std::fmt;
+
+/// Why random spot generation gave up.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GenerationFailure {
+    /// The spot size distribution kept producing sizes outside of its bounds
+    SizeDraws,
+    /// Every attempt to place a new spot collided with an existing one
+    Placement,
+}
+
+/// Diagnostics from an attempt to generate random spots that could not reach
+/// the star's target fill factor.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GenerationError {
+    pub failure: GenerationFailure,
+    /// The time in days at which generation gave up
+    pub time: f64,
+    pub target_fill_factor: f64,
+    /// The fill factor reached before giving up
+    pub fill_factor: f64,
+    /// The number of spots alive at `time`
+    pub spots: usize,
+    /// Spot sizes drawn for the last spot, and how many were outside of the size bounds
+    pub size_draws: usize,
+    pub rejected_sizes: usize,
+    /// Consecutive attempts to place a spot that collided with an existing spot
+    pub collisions: usize,
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.failure {
+            GenerationFailure::SizeDraws => write!(
+                f,
+                "Gave up generating spots at time {} after {} of {} sizes drawn from the spot \
+                 size distribution were outside of its bounds. The distribution rarely produces \
+                 usable spot sizes",
+                self.time, self.rejected_sizes, self.size_draws
+            )?,
+            GenerationFailure::Placement => write!(
+                f,
+                "Gave up generating spots at time {} after {} attempts in a row to place a spot \
+                 collided with existing spots. The star is too crowded to reach the target",
+                self.time, self.collisions
+            )?,
+        }
+        write!(
+            f,
+            ", reaching a fill factor of {} with {} spots instead of the target {}",
+            self.fill_factor, self.spots, self.target_fill_factor
+        )
+    }
+}
+
+impl std::error::Error for GenerationError {}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::error::LatherError;
+use crate::profile::{Doppler, Interpolation, Profile};
+
+/// Bump this when the integration or file layout changes, so stale files in disk caches
+/// are ignored.
+const FORMAT_VERSION: u64 = 3;
+
+/// How many integrals are kept in memory. Beyond this the least recently used is dropped,
+/// so a long-running process sweeping over many stars doesn't grow without bound.
+const MEMORY_CAPACITY: usize = 32;
+
+/// Everything the disk integral of a profile depends on. Floats are compared by their
+/// bits, so only exactly identical stars share a result. The whole profile is kept
+/// rather than a hash of it, so two stars can never be confused by a collision.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IntegrationKey {
+    rv: Vec<u64>,
+    ccf: Vec<u64>,
+    equatorial_velocity: u64,
+    grid_size: usize,
+    limb_linear: u64,
+    limb_quadratic: u64,
+    interpolation: Interpolation,
+    doppler: Doppler,
+}
+
+impl IntegrationKey {
+    pub fn new(
+        profile: &Profile,
+        equatorial_velocity: f64,
+        grid_size: usize,
+        limb_linear: f64,
+        limb_quadratic: f64,
+    ) -> IntegrationKey {
+        IntegrationKey {
+            rv: profile.rv.iter().map(|value| value.to_bits()).collect(),
+            ccf: profile.ccf.iter().map(|value| value.to_bits()).collect(),
+            equatorial_velocity: equatorial_velocity.to_bits(),
+            grid_size,
+            limb_linear: limb_linear.to_bits(),
+            limb_quadratic: limb_quadratic.to_bits(),
+            interpolation: profile.interpolation,
+            doppler: profile.doppler,
+        }
+    }
+
+    /// The key as a list of words, which starts each file in a disk cache so a file
+    /// whose name collides with another key's is recognised and ignored.
+    fn words(&self) -> Vec<u64> {
+        let interpolation = match self.interpolation {
+            Interpolation::Linear => 0,
+            Interpolation::CubicSpline => 1,
+            Interpolation::Lanczos { lobes } => 2 + lobes as u64,
+        };
+        let doppler = match self.doppler {
+            Doppler::Classical => 0,
+            Doppler::Relativistic => 1,
+            Doppler::Logarithmic => 2,
+        };
+        let mut words = vec![
+            FORMAT_VERSION,
+            self.equatorial_velocity,
+            self.grid_size as u64,
+            self.limb_linear,
+            self.limb_quadratic,
+            interpolation,
+            doppler,
+            self.rv.len() as u64,
+        ];
+        words.extend_from_slice(&self.rv);
+        words.push(self.ccf.len() as u64);
+        words.extend_from_slice(&self.ccf);
+        words
+    }
+
+    /// A name for this key's file in a disk cache. Unlike `std`'s hashers this is stable
+    /// across builds, so a cache directory stays valid when the crate is recompiled.
+    fn file_name(&self) -> String {
+        let mut hash = Fnv::new();
+        for word in self.words() {
+            hash.write(word);
+        }
+        format!("quiet_{:016x}.bin", hash.finish())
+    }
+}
+
+/// The FNV-1a hash, fed 64 bits at a time.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, value: u64) {
+        for byte in &value.to_le_bytes() {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+type Integral = Arc<(Vec<f64>, f64)>;
+
+/// The integrals in memory, each with the tick it was last used at.
+#[derive(Default)]
+struct Memory {
+    entries: HashMap<IntegrationKey, (Integral, u64)>,
+    tick: u64,
+}
+
+impl Memory {
+    fn get(&mut self, key: &IntegrationKey) -> Option<Integral> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.entries.get_mut(key).map(|(integral, used)| {
+            *used = tick;
+            integral.clone()
+        })
+    }
+
+    fn insert(&mut self, key: IntegrationKey, integral: Integral) {
+        if self.entries.len() >= MEMORY_CAPACITY && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(key, (integral, self.tick));
+    }
+}
+
+fn memory() -> std::sync::MutexGuard<'static, Memory> {
+    static MEMORY: OnceLock<Mutex<Memory>> = OnceLock::new();
+    MEMORY
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Looks up the integrated profile and flux for `key`, first in memory then in
+/// `disk_dir` if provided, and only calls `integrate` if neither has it. New results are
+/// stored in both, and failing to write one to `disk_dir` is an error.
+pub fn integrate_cached(
+    key: IntegrationKey,
+    disk_dir: Option<&Path>,
+    integrate: impl FnOnce() -> (Vec<f64>, f64),
+) -> Result<(Vec<f64>, f64), LatherError> {
+    if let Some(hit) = memory().get(&key) {
+        return Ok((hit.0.clone(), hit.1));
+    }
+
+    let integral = match disk_dir.and_then(|dir| read(&dir.join(key.file_name()), &key)) {
+        Some(integral) => integral,
+        None => {
+            let integral = integrate();
+            if let Some(dir) = disk_dir {
+                write(dir, &key, &integral)?;
+            }
+            integral
+        }
+    };
+
+    memory().insert(key, Arc::new(integral.clone()));
+    Ok(integral)
+}
+
+/// Forgets every integral cached in memory. Disk caches are left alone.
+pub fn clear_integration_cache() {
+    memory().entries.clear();
+}
+
+/// Files are the key's words, then the flux followed by the integrated profile as
+/// little-endian f64s. A file written for any other key is treated as missing.
+fn read(path: &Path, key: &IntegrationKey) -> Option<(Vec<f64>, f64)> {
+    let bytes = std::fs::read(path).ok()?;
+    let words = key.words();
+    if bytes.len() != (words.len() + key.ccf.len() + 1) * 8 {
+        return None;
+    }
+    let mut chunks = bytes.chunks_exact(8).map(|chunk| {
+        let mut value = [0; 8];
+        value.copy_from_slice(chunk);
+        u64::from_le_bytes(value)
+    });
+    if !words.iter().all(|word| chunks.next() == Some(*word)) {
+        return None;
+    }
+    let mut values = chunks.map(f64::from_bits);
+    let flux = values.next()?;
+    Some((values.collect(), flux))
+}
+
+fn write(dir: &Path, key: &IntegrationKey, integral: &(Vec<f64>, f64)) -> Result<(), LatherError> {
+    let file_name = key.file_name();
+    let path = dir.join(&file_name);
+    let words = key.words();
+    let mut bytes = Vec::with_capacity((words.len() + integral.0.len() + 1) * 8);
+    for word in words
+        .into_iter()
+        .chain(std::iter::once(integral.1.to_bits()))
+        .chain(integral.0.iter().map(|value| value.to_bits()))
+    {
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    // Write then rename, so simulations sharing the directory never read a partial file
+    let temporary = dir.join(format!("{}.{}.tmp", file_name, std::process::id()));
+    std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&temporary, bytes))
+        .and_then(|_| std::fs::rename(&temporary, &path))
+        .map_err(|e| LatherError::io(&path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(equatorial_velocity: f64) -> IntegrationKey {
+        let profile = Profile::new(vec![0.0, 1.0, 2.0], vec![1.0, 0.5, 1.0]);
+        IntegrationKey::new(&profile, equatorial_velocity, 10, 0.29, 0.34)
+    }
+
+    #[test]
+    fn memory_cache_skips_integration() {
+        let key = key(1234.5);
+        let first = integrate_cached(key.clone(), None, || (vec![1.0, 2.0, 3.0], 4.0)).unwrap();
+        let second = integrate_cached(key, None, || panic!("should have been cached")).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn memory_cache_evicts_least_recently_used() {
+        let mut memory = Memory::default();
+        let integral = Arc::new((vec![1.0], 1.0));
+        for i in 0..MEMORY_CAPACITY {
+            memory.insert(key(i as f64), integral.clone());
+        }
+        assert!(memory.get(&key(0.0)).is_some());
+        memory.insert(key(-1.0), integral);
+        assert_eq!(memory.entries.len(), MEMORY_CAPACITY);
+        assert!(memory.get(&key(0.0)).is_some());
+        assert!(memory.get(&key(1.0)).is_none());
+    }
+
+    #[test]
+    fn disk_cache_round_trips() {
+        let dir = std::env::temp_dir().join(format!("lather_cache_test_{}", std::process::id()));
+        let integral = (vec![0.1, 0.2, 0.3], 0.7);
+        let written = key(5432.1);
+        integrate_cached(written.clone(), Some(&dir), || integral.clone()).unwrap();
+        let path = dir.join(written.file_name());
+        assert_eq!(read(&path, &written), Some(integral.clone()));
+        // A file for another key, even under the same name, isn't used
+        assert!(read(&path, &key(5432.2)).is_none());
+
+        // A key that isn't in memory yet is found on disk
+        let unseen = key(9876.5);
+        write(&dir, &unseen, &integral).unwrap();
+        let cached =
+            integrate_cached(unseen, Some(&dir), || panic!("should have been on disk")).unwrap();
+        assert_eq!(cached, integral);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unwritable_disk_cache_is_an_error() {
+        let file = std::env::temp_dir().join(format!("lather_cache_file_{}", std::process::id()));
+        std::fs::write(&file, b"not a directory").unwrap();
+        let result = integrate_cached(key(2468.0), Some(&file), || (vec![1.0; 3], 1.0));
+        assert!(matches!(result, Err(LatherError::Io { .. })));
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -3,60 +3,292 @@
 //!
 //! This project was inspired by a desire to improve upon the
 //! starspot modeling library named SOAP.
+//!
+//! From Rust, a `Simulation` is built from a config file, a TOML string, or a
+//! `SimulationConfig`, or from parameters with `StarBuilder` and `SimulationBuilder`:
+//!
+//! ```no_run
+//! use lather::{Bounds, Simulation, SpotConfig, StarBuilder};
+//!
+//! let star = StarBuilder::new().period(10.0).build()?;
+//! let sim = Simulation::builder(star)
+//!     .spot(SpotConfig::new(30.0, 180.0, 0.01))
+//!     .build()?;
+//! let light_curve = sim.light_curve(&[0.0, 2.5, 5.0], Bounds::new(4000e-10, 7000e-10))?;
+//! # Ok::<(), lather::LatherError>(())
+//! ```
+//!
+//! The `extern "C"` functions are the C API declared in `lather.h`. A simulation or star
+//! passed to one is live if it came from this library and hasn't been freed, and no other
+//! call may change a simulation while one is using it. An `error` out-parameter that isn't
+//! null receives a message the caller must free with `lather_string_free`. Each function's
+//! `# Safety` section says what its pointers must be.
 
 mod boundingshape;
 mod bounds;
+mod builder;
+mod catalog;
+mod convergence;
 mod distributions;
+mod error;
+mod exposure;
+mod generation;
+mod integration_cache;
+mod line_group;
 mod linspace;
+mod noise;
+mod observation;
+mod phase_grid;
 mod planck;
 mod point;
 mod profile;
+#[cfg(feature = "python")]
+mod python;
+mod rng;
+mod schedule;
 mod simulation;
 mod solar_ccfs;
+mod spectral_model;
 mod spot;
+mod spot_size;
 mod star;
+mod state;
+mod surface;
+mod synthesis;
 
 pub use bounds::Bounds;
+pub use builder::{SimulationBuilder, StarBuilder};
+pub use convergence::{DiscretizationError, Tolerance};
+pub use distributions::{Distribution, DistributionConfig};
+pub use error::{LatherError, LatherErrorCode};
+pub use exposure::{Exposure, Supersample};
+pub use generation::{GenerationError, GenerationFailure};
+pub use integration_cache::clear_integration_cache;
 pub use linspace::{floatrange, linspace};
-pub use simulation::Simulation;
+pub use observation::{CcfSeries, LightCurve};
+pub use profile::{Doppler, Interpolation, Profile};
+pub use simulation::{Simulation, SimulationConfig};
 pub use solar_ccfs::*;
-pub use spot::SpotConfig;
+pub use spot::{Mortality, Spot, SpotConfig};
+pub use star::{Star, StarConfig, IMAGE_SIZE};
+pub use state::{GeneratorPositions, SimulationState, SpotState, SurfaceState, STATE_FORMAT};
 
+/// The sections of simulation config files, for building a `SimulationConfig` in code
+pub mod config {
+    pub use crate::catalog::CatalogConfig;
+    pub use crate::convergence::ResolutionConfig;
+    pub use crate::distributions::{EmpiricalFormat, MixtureComponent};
+    pub use crate::exposure::{ExposureConfig, SupersampleConfig};
+    pub use crate::line_group::LineGroupConfig;
+    pub use crate::noise::{InstrumentConfig, PhotonNoiseConfig, RedNoiseConfig};
+    pub use crate::phase_grid::PhaseGridConfig;
+    pub use crate::schedule::ScheduleConfig;
+    pub use crate::simulation::SeedConfig;
+    pub use crate::spectral_model::{ModelSpectrumConfig, SpectralModelConfig};
+    pub use crate::spot::Lifetime;
+    pub use crate::spot_size::{SizePreset, SizeUnit, SpotSizeConfig};
+    pub use crate::surface::SurfaceConfig;
+    pub use crate::synthesis::SynthesisConfig;
+}
+
+use std::cell::RefCell;
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
 use std::path::Path;
+use std::sync::Arc;
+
+use exposure::{ExposureConfig, SupersampleConfig};
+use spot::Lifetime;
 
 /// Build a simulation from a path to a config file
+///
+/// # Safety
+///
+/// `filename` must be null or a nul-terminated string, and `error` null or writable.
 #[no_mangle]
 pub unsafe extern "C" fn simulation_new(
     filename: *const c_char,
-    error: *mut *const c_char,
+    error: *mut *mut c_char,
 ) -> *mut Simulation {
-    let filename = match CStr::from_ptr(filename).to_str() {
-        Ok(v) => v,
-        Err(_) => {
-            let error_message = CString::new("Filename ust be valid UTF-8").unwrap();
-            *error = error_message.as_ptr() as *mut c_char;
-            std::mem::forget(error_message);
-            return std::ptr::null_mut();
-        }
-    };
-
-    match Simulation::from_config(&Path::new(filename)) {
+    match utf8(filename, "The filename").and_then(|f| Simulation::from_config(Path::new(f))) {
         Ok(v) => {
             let obj = Box::new(v);
             Box::into_raw(obj)
         }
         Err(e) => {
-            let error_message = CString::new(e).unwrap();
-            *error = error_message.as_ptr() as *mut c_char;
-            std::mem::forget(error_message);
+            set_error(error, e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Build a simulation from the contents of a config file. Relative paths in the config are
+/// relative to the working directory.
+///
+/// # Safety
+///
+/// `contents` must be null or a nul-terminated string, and `error` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_from_toml(
+    contents: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut Simulation {
+    match utf8(contents, "The config").and_then(Simulation::from_toml_str) {
+        Ok(sim) => Box::into_raw(Box::new(sim)),
+        Err(e) => {
+            set_error(error, e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Resume a simulation from a state file written by `simulation_save_state`
+///
+/// # Safety
+///
+/// `filename` must be null or a nul-terminated string, and `error` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_load_state(
+    filename: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut Simulation {
+    match utf8(filename, "The filename").and_then(|f| Simulation::load_state(Path::new(f))) {
+        Ok(sim) => Box::into_raw(Box::new(sim)),
+        Err(e) => {
+            set_error(error, e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Resume a simulation from a state written by `simulation_state_tostring`.
+///
+/// # Safety
+///
+/// `contents` must be null or a nul-terminated string, and `error` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_from_state(
+    contents: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut Simulation {
+    match utf8(contents, "The state")
+        .and_then(SimulationState::from_toml_str)
+        .and_then(Simulation::from_state)
+    {
+        Ok(sim) => Box::into_raw(Box::new(sim)),
+        Err(e) => {
+            set_error(error, e);
             std::ptr::null_mut()
         }
     }
 }
 
+/// Build a simulation of `star` with no spots, drawing random spots and noise from `seed`.
+/// The simulation shares the star, which the caller still has to free with `star_free`.
+///
+/// # Safety
+///
+/// `star` must be null or a live star, and `error` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_from_star(
+    star: *const Star,
+    seed: u64,
+    error: *mut *mut c_char,
+) -> *mut Simulation {
+    if star.is_null() {
+        set_error(
+            error,
+            LatherError::Validation("The star pointer is null".to_string()),
+        );
+        return std::ptr::null_mut();
+    }
+    Arc::increment_strong_count(star);
+    Box::into_raw(Box::new(Simulation::with_star(Arc::from_raw(star), seed)))
+}
+
+/// Build a star from parameters, as in the star section of a config file. A
+/// `target_fill_factor` of 0 or less means the star has no random spots. The star must be
+/// freed with `star_free`.
+///
+/// # Safety
+///
+/// `error` must be null or writable.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn star_new(
+    grid_size: usize,
+    radius: f64,
+    period: f64,
+    inclination: f64,
+    temperature: f64,
+    spot_temp_diff: f64,
+    limb_linear: f64,
+    limb_quadratic: f64,
+    target_fill_factor: f64,
+    error: *mut *mut c_char,
+) -> *const Star {
+    let mut builder = StarBuilder::new()
+        .grid_size(grid_size)
+        .radius(radius)
+        .period(period)
+        .inclination(inclination)
+        .temperature(temperature)
+        .spot_temp_diff(spot_temp_diff)
+        .limb_darkening(limb_linear, limb_quadratic);
+    if target_fill_factor > 0.0 {
+        builder = builder.target_fill_factor(target_fill_factor);
+    }
+    into_star(builder.build(), error)
+}
+
+/// Build a star from the star section of a config file. Relative paths in it are relative
+/// to the working directory. The star must be freed with `star_free`.
+///
+/// # Safety
+///
+/// `contents` must be null or a nul-terminated string, and `error` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn star_from_toml(
+    contents: *const c_char,
+    error: *mut *mut c_char,
+) -> *const Star {
+    let star = utf8(contents, "The star config").and_then(|contents| {
+        let config = ::toml::from_str(contents).map_err(|e| LatherError::Parse {
+            path: None,
+            message: e.to_string(),
+        })?;
+        Star::from_config(&config)
+    });
+    into_star(star, error)
+}
+
+unsafe fn into_star(star: Result<Star, LatherError>, error: *mut *mut c_char) -> *const Star {
+    match star {
+        Ok(star) => Arc::into_raw(Arc::new(star)),
+        Err(e) => {
+            set_error(error, e);
+            std::ptr::null()
+        }
+    }
+}
+
+/// Release a star. Simulations built from it keep their own reference to it.
+///
+/// # Safety
+///
+/// `star` must be null or a live star, which can't be used again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn star_free(star: *const Star) {
+    if !star.is_null() {
+        drop(Arc::from_raw(star));
+    }
+}
+
 /// Close down a simulation
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, which can't be used again afterwards.
 #[no_mangle]
 pub unsafe extern "C" fn simulation_free(sim: *mut Simulation) {
     if sim.is_null() {
@@ -67,33 +299,215 @@ pub unsafe extern "C" fn simulation_free(sim: *mut Simulation) {
     let _: Box<Simulation> = ::std::mem::transmute(sim);
 }
 
-/// Print a simulation
+/// Print a simulation. The string must be freed with `lather_string_free`.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
 #[no_mangle]
-pub unsafe extern "C" fn simulation_tostring(sim: *mut Simulation) -> *const c_char {
+pub unsafe extern "C" fn simulation_tostring(sim: *mut Simulation) -> *mut c_char {
     use std::fmt::Write;
     if sim.is_null() {
-        return std::ptr::null();
+        return std::ptr::null_mut();
     }
 
     let mut output = String::new();
     write!(&mut output, "{:#?}", *sim).unwrap();
-    let output = CString::new(output).unwrap();
-    let ptr = output.as_ptr();
-    std::mem::forget(output);
-    ptr
+    owned_c_string(output)
+}
+
+/// Save everything about a simulation, including its random spots and how far its random
+/// number generators have got, to a TOML file. The simulation resumed from it with
+/// `simulation_load_state` continues exactly as this one would.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, `filename` null or a nul-terminated string, and `error` null
+/// or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_save_state(
+    sim: *mut Simulation,
+    filename: *const c_char,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+    match utf8(filename, "The filename").and_then(|f| (*sim).save_state(Path::new(f))) {
+        Ok(()) => true,
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// The state `simulation_save_state` would save, as a TOML string. The string must be freed
+/// with `lather_string_free`.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_state_tostring(sim: *mut Simulation) -> *mut c_char {
+    if sim.is_null() {
+        return std::ptr::null_mut();
+    }
+    owned_c_string((*sim).state().to_toml())
+}
+
+/// The version of this library, like `LATHER_VERSION` in the header it was built with.
+/// The string belongs to the library.
+#[no_mangle]
+pub extern "C" fn lather_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ccf_len() -> usize {
+pub extern "C" fn ccf_len() -> usize {
     solar_ccfs::CCF_LEN
 }
 
+/// The number of RVs the simulation's CCFs are sampled at
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
 #[no_mangle]
-pub unsafe extern "C" fn rv_for_ccfs() -> *const f64 {
+pub unsafe extern "C" fn simulation_ccf_len(sim: *mut Simulation) -> usize {
+    if sim.is_null() {
+        return 0;
+    }
+    let sim = &*sim;
+    sim.star.profile_quiet.len()
+}
+
+/// The width and height in pixels of the images `simulation_draw_bgr` draws, which have 3
+/// bytes per pixel.
+#[no_mangle]
+pub extern "C" fn lather_image_size() -> usize {
+    IMAGE_SIZE
+}
+
+#[no_mangle]
+pub extern "C" fn rv_for_ccfs() -> *const f64 {
     solar_ccfs::RV.as_ptr()
 }
 
-/// Observe the flux of a simulation at given time values in days
+/// Set the length of each exposure in days. Each exposure is averaged over `sub_exposures`
+/// instantaneous observations, or if `sub_exposures` is 0, over enough that the star rotates
+/// by at most `max_phase_step` of a rotation between them. Returns false and leaves the
+/// exposure unchanged if it isn't valid.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, and `error` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_set_exposure(
+    sim: *mut Simulation,
+    duration: f64,
+    sub_exposures: usize,
+    max_phase_step: f64,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+    let supersample = if sub_exposures == 0 {
+        SupersampleConfig::Adaptive { max_phase_step }
+    } else {
+        SupersampleConfig::Fixed {
+            count: sub_exposures,
+        }
+    };
+    let config = ExposureConfig {
+        duration,
+        supersample: Some(supersample),
+    };
+    match Exposure::from_config(&config) {
+        Ok(exposure) => {
+            (*sim).exposure = exposure;
+            true
+        }
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// Precompute every immortal spot at `samples` phases of a rotation, so observations
+/// interpolate between them instead of integrating each spot at every time. If `samples`
+/// is 0, spots are integrated at every time again. Fewer than 4 samples can't be
+/// interpolated, so returns false and leaves the simulation unchanged.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, and `error` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_set_phase_grid(
+    sim: *mut Simulation,
+    samples: usize,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+    if samples == 0 {
+        (*sim).set_phase_grid(None);
+        return true;
+    }
+    match phase_grid::check_samples(samples) {
+        Ok(()) => {
+            (*sim).set_phase_grid(Some(samples));
+            true
+        }
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// Get the number of observation times generated by the simulation's observing schedule,
+/// or 0 if it has none.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_schedule_len(sim: *mut Simulation) -> usize {
+    if sim.is_null() {
+        return 0;
+    }
+    (*sim).scheduled_times().map_or(0, |t| t.len())
+}
+
+/// Copy the observation times generated by the simulation's observing schedule into `times`,
+/// which must have room for `simulation_schedule_len` values.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, and `times` must have room for
+/// `simulation_schedule_len` values.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_schedule_times(sim: *mut Simulation, times: *mut f64) {
+    if sim.is_null() {
+        return;
+    }
+    if let Some(scheduled) = (*sim).scheduled_times() {
+        std::ptr::copy_nonoverlapping(scheduled.as_ptr(), times, scheduled.len());
+    }
+}
+
+/// Observe the flux of a simulation at given time values in days. Like every observation,
+/// this fails if random spots haven't been generated at the times with
+/// `simulation_ensure_spots` or `simulation_generate_spots`.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation. `times` must hold `n_times` values, `flux` must have
+/// room for as many, and `error` must be null or writable.
 #[no_mangle]
 pub unsafe extern "C" fn simulation_observe_flux(
     sim: *mut Simulation,
@@ -102,16 +516,141 @@ pub unsafe extern "C" fn simulation_observe_flux(
     wave_start: f64,
     wave_end: f64,
     flux: *mut f64,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+    let time_slice = std::slice::from_raw_parts(times, n_times);
+    match (*sim).observe_flux(time_slice, Bounds::new(wave_start, wave_end)) {
+        Ok(output) => {
+            std::ptr::copy_nonoverlapping(output.as_ptr(), flux, n_times);
+            true
+        }
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// Choose the strip spacing of every spot so its estimated discretization error in
+/// relative flux and in RV (m/s) is at most `flux_tolerance` and `rv_tolerance`. A
+/// tolerance of 0 or less is not checked, and if neither is, spots use the star's grid.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_set_tolerance(
+    sim: *mut Simulation,
+    flux_tolerance: f64,
+    rv_tolerance: f64,
 ) {
     if sim.is_null() {
         return;
     }
+    let limit = |tolerance: f64| {
+        if tolerance > 0.0 {
+            tolerance
+        } else {
+            f64::INFINITY
+        }
+    };
+    let tolerance = if flux_tolerance > 0.0 || rv_tolerance > 0.0 {
+        Some(Tolerance {
+            flux: limit(flux_tolerance),
+            rv: limit(rv_tolerance),
+        })
+    } else {
+        None
+    };
+    (*sim).set_tolerance(tolerance);
+}
+
+/// Observe the flux of a simulation at given time values in days, along with the estimated
+/// discretization errors of the flux and of the RV of the CCFs at the same times.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation. `times` must hold `n_times` values, `flux`,
+/// `flux_error`, and `rv_error` must each have room for as many, and `error` must be null or
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_estimate_errors(
+    sim: *mut Simulation,
+    times: *mut f64,
+    n_times: usize,
+    wave_start: f64,
+    wave_end: f64,
+    flux: *mut f64,
+    flux_error: *mut f64,
+    rv_error: *mut f64,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
     let time_slice = std::slice::from_raw_parts(times, n_times);
-    let output = (*sim).observe_flux(time_slice, Bounds::new(wave_start, wave_end));
-    std::ptr::copy_nonoverlapping(output.as_ptr(), flux, n_times)
+    match (*sim).estimate_errors(time_slice, Bounds::new(wave_start, wave_end)) {
+        Ok(estimate) => {
+            std::ptr::copy_nonoverlapping(estimate.flux.as_ptr(), flux, n_times);
+            std::ptr::copy_nonoverlapping(estimate.flux_error.as_ptr(), flux_error, n_times);
+            std::ptr::copy_nonoverlapping(estimate.rv_error.as_ptr(), rv_error, n_times);
+            true
+        }
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// Observe the flux of a simulation at given time values in days, in each of `n_bands`
+/// wavelength bands. `flux` is filled with `n_times * n_bands` values, indexed by time then band.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation. `times` must hold `n_times` values,
+/// `wave_starts` and `wave_ends` `n_bands` values each, `flux` must have room for
+/// `n_times * n_bands` values, and `error` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_observe_flux_bands(
+    sim: *mut Simulation,
+    times: *mut f64,
+    n_times: usize,
+    wave_starts: *mut f64,
+    wave_ends: *mut f64,
+    n_bands: usize,
+    flux: *mut f64,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+    let time_slice = std::slice::from_raw_parts(times, n_times);
+    let bands = bands_from_raw(wave_starts, wave_ends, n_bands);
+    match (*sim).observe_flux_bands(time_slice, &bands) {
+        Ok(observations) => {
+            let output = std::slice::from_raw_parts_mut(flux, n_times * n_bands);
+            for (i, value) in observations.iter().flat_map(|ob| ob.iter()).enumerate() {
+                output[i] = *value;
+            }
+            true
+        }
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
 }
 
 /// Observe the rv and bisectors of a simulation at given time values in days
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation. `times` must hold `n_times` values, `ccfs` must have
+/// room for `n_times * simulation_ccf_len(sim)` values, and `error` must be null or writable.
 #[no_mangle]
 pub unsafe extern "C" fn simulation_observe_rv(
     sim: *mut Simulation,
@@ -120,25 +659,367 @@ pub unsafe extern "C" fn simulation_observe_rv(
     wave_start: f64,
     wave_end: f64,
     ccfs: *mut f64,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+
+    let time_slice = std::slice::from_raw_parts(times, n_times);
+    match (*sim).observe_rv(time_slice, Bounds::new(wave_start, wave_end)) {
+        Ok(observations) => {
+            let ccf_len = (&*sim).star.profile_quiet.len();
+            let ccf_output = std::slice::from_raw_parts_mut(ccfs, n_times * ccf_len);
+            for (i, ccf_value) in observations.iter().flat_map(|ob| ob.iter()).enumerate() {
+                ccf_output[i] = *ccf_value;
+            }
+            true
+        }
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// Observe the CCFs of a simulation at given time values in days, in each of `n_bands`
+/// wavelength bands. `ccfs` is filled with `n_times * n_bands * ccf_len()` values,
+/// indexed by time then band.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation. `times` must hold `n_times` values,
+/// `wave_starts` and `wave_ends` `n_bands` values each, `ccfs` must have room for
+/// `n_times * n_bands * simulation_ccf_len(sim)` values, and `error` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_observe_rv_bands(
+    sim: *mut Simulation,
+    times: *mut f64,
+    n_times: usize,
+    wave_starts: *mut f64,
+    wave_ends: *mut f64,
+    n_bands: usize,
+    ccfs: *mut f64,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+    let time_slice = std::slice::from_raw_parts(times, n_times);
+    let bands = bands_from_raw(wave_starts, wave_ends, n_bands);
+    match (*sim).observe_rv_bands(time_slice, &bands) {
+        Ok(observations) => {
+            let ccf_len = (&*sim).star.profile_quiet.len();
+            let ccf_output = std::slice::from_raw_parts_mut(ccfs, n_times * n_bands * ccf_len);
+            for (i, ccf_value) in observations
+                .iter()
+                .flat_map(|ob| ob.iter())
+                .flat_map(|ccf| ccf.iter())
+                .enumerate()
+            {
+                ccf_output[i] = *ccf_value;
+            }
+            true
+        }
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+unsafe fn bands_from_raw(
+    wave_starts: *mut f64,
+    wave_ends: *mut f64,
+    n_bands: usize,
+) -> Vec<Bounds> {
+    let starts = std::slice::from_raw_parts(wave_starts, n_bands);
+    let ends = std::slice::from_raw_parts(wave_ends, n_bands);
+    starts
+        .iter()
+        .zip(ends.iter())
+        .map(|(start, end)| Bounds::new(*start, *end))
+        .collect()
+}
+
+/// The number of line groups the star has CCFs for
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_line_group_count(sim: *mut Simulation) -> usize {
+    if sim.is_null() {
+        return 0;
+    }
+    let sim = &*sim;
+    sim.star.line_groups.len()
+}
+
+/// The name of a line group, which must be freed with `lather_string_free`
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_line_group_name(
+    sim: *mut Simulation,
+    group: usize,
+) -> *mut c_char {
+    if sim.is_null() {
+        return std::ptr::null_mut();
+    }
+    let sim = &*sim;
+    if group >= sim.star.line_groups.len() {
+        return std::ptr::null_mut();
+    }
+    owned_c_string(sim.star.line_groups[group].name.clone())
+}
+
+/// The number of radial velocities in the CCF of a line group
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_line_group_len(sim: *mut Simulation, group: usize) -> usize {
+    if sim.is_null() {
+        return 0;
+    }
+    let sim = &*sim;
+    if group >= sim.star.line_groups.len() {
+        return 0;
+    }
+    sim.star.line_groups[group].profile_quiet.len()
+}
+
+/// Copy the radial velocities in m/s of the CCF of a line group
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, and `rv` must have room for
+/// `simulation_line_group_len(sim, group)` values.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_line_group_rv(
+    sim: *mut Simulation,
+    group: usize,
+    rv: *mut f64,
 ) {
     if sim.is_null() {
         return;
     }
+    let sim = &*sim;
+    if group >= sim.star.line_groups.len() {
+        return;
+    }
+    let profile = &sim.star.line_groups[group].profile_quiet;
+    std::ptr::copy_nonoverlapping(profile.rv.as_ptr(), rv, profile.len());
+}
+
+/// Observe the CCF of every line group at given time values in days. The CCFs are
+/// written for each time in turn, and within each time for each line group in turn.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation. `times` must hold `n_times` values, `ccfs` must
+/// have room for every line group's CCF at every time, and `error` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_observe_line_groups(
+    sim: *mut Simulation,
+    times: *mut f64,
+    n_times: usize,
+    wave_start: f64,
+    wave_end: f64,
+    ccfs: *mut f64,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
 
     let time_slice = std::slice::from_raw_parts(times, n_times);
-    let observations = (*sim).observe_rv(time_slice, Bounds::new(wave_start, wave_end));
-    let ccf_output = std::slice::from_raw_parts_mut(ccfs, n_times * 401);
-    for (i, ccf_value) in observations.iter().flat_map(|ob| ob.iter()).enumerate() {
-        ccf_output[i] = *ccf_value;
+    match (*sim).observe_line_groups(time_slice, Bounds::new(wave_start, wave_end)) {
+        Ok(observations) => {
+            for (i, ccf_value) in observations
+                .iter()
+                .flat_map(|ob| ob.iter())
+                .flat_map(|group| group.iter())
+                .enumerate()
+            {
+                *ccfs.add(i) = *ccf_value;
+            }
+            true
+        }
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
     }
 }
 
+/// The number of wavelengths in spectra produced by `simulation_observe_spectrum`,
+/// which is 0 if the star has no template spectra
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_spectrum_len(sim: *mut Simulation) -> usize {
+    if sim.is_null() {
+        return 0;
+    }
+    (*sim).spectrum_wavelength().map(|w| w.len()).unwrap_or(0)
+}
+
+/// Copy the wavelengths in meters of spectra produced by `simulation_observe_spectrum`
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, and `wavelength` must have room for
+/// `simulation_spectrum_len` values.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_spectrum_wavelength(
+    sim: *mut Simulation,
+    wavelength: *mut f64,
+) {
+    if sim.is_null() {
+        return;
+    }
+    if let Some(w) = (*sim).spectrum_wavelength() {
+        std::ptr::copy_nonoverlapping(w.as_ptr(), wavelength, w.len());
+    }
+}
+
+/// Observe the disk-integrated spectrum of a simulation at given time values in days
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation. `times` must hold `n_times` values, `spectra` must
+/// have room for `n_times * simulation_spectrum_len(sim)` values, and `error` must be null or
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_observe_spectrum(
+    sim: *mut Simulation,
+    times: *mut f64,
+    n_times: usize,
+    spectra: *mut f64,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+
+    let time_slice = std::slice::from_raw_parts(times, n_times);
+    match (*sim).observe_spectrum(time_slice) {
+        Ok(observations) => {
+            for (i, value) in observations.iter().flat_map(|ob| ob.iter()).enumerate() {
+                *spectra.add(i) = *value;
+            }
+            true
+        }
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// Add the noise of the instrument named `instrument` to `n_times` fluxes observed at `times`.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, `instrument` null or a nul-terminated string, and
+/// `error` null or writable. `times` and `flux` must each hold `n_times` values.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_apply_flux_noise(
+    sim: *mut Simulation,
+    instrument: *const c_char,
+    times: *mut f64,
+    n_times: usize,
+    flux: *mut f64,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+
+    let time_slice = std::slice::from_raw_parts(times, n_times);
+    let flux_slice = std::slice::from_raw_parts_mut(flux, n_times);
+    let result = utf8(instrument, "The instrument name")
+        .and_then(|name| (*sim).apply_flux_noise(name, time_slice, flux_slice));
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// Add the noise of the instrument named `instrument` to `n_times` CCFs observed at `times`.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, `instrument` null or a nul-terminated string, and
+/// `error` null or writable. `times` must hold `n_times` values, and `ccfs`
+/// `n_times * simulation_ccf_len(sim)` values.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_apply_ccf_noise(
+    sim: *mut Simulation,
+    instrument: *const c_char,
+    times: *mut f64,
+    n_times: usize,
+    ccfs: *mut f64,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+
+    let time_slice = std::slice::from_raw_parts(times, n_times);
+    let ccf_len = (&*sim).star.profile_quiet.len();
+    let ccf_slice = std::slice::from_raw_parts_mut(ccfs, n_times * ccf_len);
+    let mut ccf_vecs: Vec<Vec<f64>> = ccf_slice.chunks(ccf_len).map(|c| c.to_vec()).collect();
+    let result = utf8(instrument, "The instrument name")
+        .and_then(|name| (*sim).apply_ccf_noise(name, time_slice, &mut ccf_vecs));
+    match result {
+        Ok(()) => {
+            for (i, value) in ccf_vecs.iter().flat_map(|ccf| ccf.iter()).enumerate() {
+                ccf_slice[i] = *value;
+            }
+            true
+        }
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// Copy the CCF of the unspotted star into `ccf`
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, and `ccf` must have room for
+/// `simulation_ccf_len(sim)` values.
 #[no_mangle]
 pub unsafe extern "C" fn simulation_get_quiet_ccf(sim: *mut Simulation, ccf: *mut f64) {
-    std::ptr::copy_nonoverlapping((*sim).star.integrated_ccf.as_ptr(), ccf, 401)
+    if sim.is_null() {
+        return;
+    }
+    let sim = &*sim;
+    std::ptr::copy_nonoverlapping(
+        sim.star.integrated_ccf.as_ptr(),
+        ccf,
+        sim.star.integrated_ccf.len(),
+    )
 }
 
 /// Remove all spots on this simulation
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
 #[no_mangle]
 pub unsafe extern "C" fn simulation_clear_spots(sim: *mut Simulation) {
     if sim.is_null() {
@@ -148,7 +1029,77 @@ pub unsafe extern "C" fn simulation_clear_spots(sim: *mut Simulation) {
     (*sim).clear_spots();
 }
 
-/// Add a spot to the simulation
+/// Generate random spots over the window of time from `start` to `end` in days, replacing
+/// any previously generated spots. If the star's target fill factor can't be reached,
+/// returns false and fills `diagnostics` if it is not null.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, and `diagnostics` and `error` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_generate_spots(
+    sim: *mut Simulation,
+    start: f64,
+    end: f64,
+    diagnostics: *mut GenerationError,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+    match (*sim).generate_spots(Bounds::new(start, end)) {
+        Ok(()) => true,
+        Err(e) => {
+            if !diagnostics.is_null() {
+                *diagnostics = e;
+            }
+            set_error(error, LatherError::Generation(e));
+            false
+        }
+    }
+}
+
+/// Make sure random spots have been generated over the exposure at every one of `n_times`
+/// times in days, which observations need. Spots are only generated the first time they are
+/// needed, so after this has covered all the times, the simulation can be observed from
+/// several threads at once. A time before the spots generated so far generates every random
+/// spot again from that time. If the star's target fill factor can't be reached, returns
+/// false and fills `diagnostics` if it is not null.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation. `times` must hold `n_times` values, and
+/// `diagnostics` and `error` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_ensure_spots(
+    sim: *mut Simulation,
+    times: *mut f64,
+    n_times: usize,
+    diagnostics: *mut GenerationError,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+    let time_slice = std::slice::from_raw_parts(times, n_times);
+    match (*sim).ensure_spots(time_slice) {
+        Ok(()) => true,
+        Err(e) => {
+            if !diagnostics.is_null() {
+                *diagnostics = e;
+            }
+            set_error(error, LatherError::Generation(e));
+            false
+        }
+    }
+}
+
+/// Add an immortal spot at the star's spot temperature. `simulation_add_spot_info` adds
+/// spots with every option. Returns false and adds nothing if the spot isn't valid.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, and `error` null or writable.
 #[no_mangle]
 pub unsafe extern "C" fn simulation_add_spot(
     sim: *mut Simulation,
@@ -156,23 +1107,328 @@ pub unsafe extern "C" fn simulation_add_spot(
     longitude: f64,
     fill_factor: f64,
     plage: bool,
-) {
+    error: *mut *mut c_char,
+) -> bool {
     if sim.is_null() {
-        return;
+        return null_simulation(error);
     }
 
-    (*sim).add_spot(&SpotConfig {
+    let config = SpotConfig {
         latitude,
         longitude,
         fill_factor,
         plage,
         temperature: None,
         lifetime: None,
-    });
+    };
+    match (*sim).add_spot(&config) {
+        Ok(()) => true,
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// A spot as seen through the C API. Immortal spots have a `start` of negative infinity and
+/// an `end` of infinity.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpotInfo {
+    /// Degrees from the equator
+    pub latitude: f64,
+    /// Degrees
+    pub longitude: f64,
+    /// The fraction of the star the spot covers
+    pub fill_factor: f64,
+    /// Kelvin. When adding a spot, NaN uses the star's spot temperature.
+    pub temperature: f64,
+    pub plage: bool,
+    /// Whether the spot was randomly generated. This is ignored when adding a spot.
+    pub generated: bool,
+    /// The time in days the spot appears
+    pub start: f64,
+    /// The time in days the spot disappears
+    pub end: f64,
+}
+
+impl SpotInfo {
+    fn to_config(self) -> SpotConfig {
+        let immortal = self.start == f64::NEG_INFINITY && self.end == f64::INFINITY;
+        SpotConfig {
+            latitude: self.latitude,
+            longitude: self.longitude,
+            fill_factor: self.fill_factor,
+            plage: self.plage,
+            temperature: Some(self.temperature).filter(|t| !t.is_nan()),
+            lifetime: Some(Lifetime {
+                start: self.start,
+                end: self.end,
+            })
+            .filter(|_| !immortal),
+        }
+    }
+}
+
+impl From<&Spot> for SpotInfo {
+    fn from(spot: &Spot) -> SpotInfo {
+        let lifetime = match spot.mortality {
+            Mortality::Immortal => Bounds::new(f64::NEG_INFINITY, f64::INFINITY),
+            Mortality::Mortal(lifetime) => lifetime,
+        };
+        SpotInfo {
+            latitude: spot.latitude.to_degrees(),
+            longitude: spot.longitude.to_degrees(),
+            fill_factor: spot.radius * spot.radius / 2.0,
+            temperature: spot.temperature,
+            plage: spot.plage,
+            generated: spot.generated,
+            start: lifetime.lower,
+            end: lifetime.upper,
+        }
+    }
+}
+
+/// Add a spot with any of the options of a spot in a config file. Randomly generated spots
+/// are discarded, and regenerated around the new spot when they are next needed. Returns
+/// false and adds nothing if the spot isn't valid.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, `spot` null or a valid `SpotInfo`, and `error` null
+/// or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_add_spot_info(
+    sim: *mut Simulation,
+    spot: *const SpotInfo,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+    if spot.is_null() {
+        set_error(
+            error,
+            LatherError::Validation("The spot pointer is null".to_string()),
+        );
+        return false;
+    }
+    match (*sim).add_spot(&(*spot).to_config()) {
+        Ok(()) => true,
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// The number of spots on the star, including randomly generated spots
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_spot_count(sim: *mut Simulation) -> usize {
+    if sim.is_null() {
+        return 0;
+    }
+    let sim = &*sim;
+    sim.spots.len()
+}
+
+/// Copy the spot at `index`, which is less than `simulation_spot_count`, into `spot`.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, and `spot` and `error` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_get_spot(
+    sim: *mut Simulation,
+    index: usize,
+    spot: *mut SpotInfo,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+    if spot.is_null() {
+        set_error(
+            error,
+            LatherError::Validation("The spot pointer is null".to_string()),
+        );
+        return false;
+    }
+    let sim = &*sim;
+    match sim.spots.get(index) {
+        Some(s) => {
+            *spot = SpotInfo::from(s);
+            true
+        }
+        None => {
+            set_error(
+                error,
+                LatherError::Validation(format!(
+                    "There is no spot {}, because the simulation has {} spots",
+                    index,
+                    sim.spots.len()
+                )),
+            );
+            false
+        }
+    }
 }
 
+/// Remove the spot at `index`. Randomly generated spots can't be removed, and are
+/// discarded and regenerated around the remaining spots when they are next needed, so the
+/// indices of every spot may change.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, and `error` null or writable.
 #[no_mangle]
-pub unsafe extern "C" fn simulation_draw_bgr(sim: *mut Simulation, time: f64, image_ptr: *mut u8) {
-    let image = std::slice::from_raw_parts_mut(image_ptr, 1000 * 1000 * 3);
-    (*sim).draw_bgr(time, image);
+pub unsafe extern "C" fn simulation_remove_spot(
+    sim: *mut Simulation,
+    index: usize,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+    match (*sim).remove_spot(index) {
+        Ok(_) => true,
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// The seed random spots and noise are drawn from
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_seed(sim: *mut Simulation) -> u64 {
+    if sim.is_null() {
+        return 0;
+    }
+    (*sim).seed()
+}
+
+/// Reseed the random spots and noise. Randomly generated spots are discarded, and
+/// regenerated from the new seed when they are next needed.
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_set_seed(sim: *mut Simulation, seed: u64) {
+    if sim.is_null() {
+        return;
+    }
+    (*sim).set_seed(seed);
+}
+
+/// Draw the simulation at `time` in days as a square BGR image, `lather_image_size()` pixels
+/// on a side
+///
+/// # Safety
+///
+/// `sim` must be null or a live simulation, `image_ptr` must have room for
+/// `lather_image_size() * lather_image_size() * 3` bytes, and `error` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn simulation_draw_bgr(
+    sim: *mut Simulation,
+    time: f64,
+    image_ptr: *mut u8,
+    error: *mut *mut c_char,
+) -> bool {
+    if sim.is_null() {
+        return null_simulation(error);
+    }
+    let image = std::slice::from_raw_parts_mut(image_ptr, IMAGE_SIZE * IMAGE_SIZE * 3);
+    match (*sim).draw_bgr(time, image) {
+        Ok(()) => true,
+        Err(e) => {
+            set_error(error, e);
+            false
+        }
+    }
+}
+
+/// The kind of error reported by the most recent failed call on this thread. Returns
+/// `LatherErrorCode_Ok` if no call on this thread has failed.
+#[no_mangle]
+pub extern "C" fn lather_last_error_code() -> LatherErrorCode {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(LatherErrorCode::Ok, |(code, _)| *code)
+    })
+}
+
+/// The message of the most recent failed call on this thread, or null if none has failed.
+/// The message belongs to the library and is valid until the next call on this thread fails.
+#[no_mangle]
+pub extern "C" fn lather_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |(_, message)| message.as_ptr())
+    })
+}
+
+/// Free a string returned by the library through an `error` out-parameter, by
+/// `simulation_tostring`, `simulation_state_tostring`, or `simulation_line_group_name`.
+/// Null is ignored.
+///
+/// # Safety
+///
+/// `string` must be null or a string from this library that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn lather_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<(LatherErrorCode, CString)>> = const { RefCell::new(None) };
+}
+
+/// Converts `string` to a C string the caller owns, replacing any nul bytes.
+fn owned_c_string(string: String) -> *mut c_char {
+    CString::new(string.replace('\0', " "))
+        .expect("Nul bytes were replaced")
+        .into_raw()
+}
+
+/// Records `error` as this thread's last error, and hands its message to the caller through
+/// the `error` out-parameter if that isn't null.
+unsafe fn set_error(error: *mut *mut c_char, e: LatherError) {
+    let message = e.to_string();
+    if !error.is_null() {
+        *error = owned_c_string(message.clone());
+    }
+    let message = CString::new(message.replace('\0', " ")).expect("Nul bytes were replaced");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some((e.code(), message)));
+}
+
+/// Borrows a C string the caller passed in, where `what` describes it in errors.
+unsafe fn utf8<'a>(string: *const c_char, what: &str) -> Result<&'a str, LatherError> {
+    if string.is_null() {
+        return Err(LatherError::Validation(format!("{} is null", what)));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| LatherError::Validation(format!("{} must be valid UTF-8", what)))
+}
+
+unsafe fn null_simulation(error: *mut *mut c_char) -> bool {
+    set_error(
+        error,
+        LatherError::Validation("The simulation pointer is null".to_string()),
+    );
+    false
 }
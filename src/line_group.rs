@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::LatherError;
+use crate::profile::Profile;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct LineGroupConfig {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl LineGroupConfig {
+    /// Loads the quiet and spot profiles from a file in the same format as the built-in
+    /// solar CCFs: two header lines, then whitespace-separated columns of radial velocity
+    /// (km/s), the quiet CCF, and the spot CCF.
+    pub fn load_profiles(&self) -> Result<(Profile, Profile), LatherError> {
+        let contents =
+            std::fs::read_to_string(&self.path).map_err(|e| LatherError::io(&self.path, e))?;
+
+        let mut rv = Vec::new();
+        let mut ccf_quiet = Vec::new();
+        let mut ccf_spot = Vec::new();
+        for (line_number, line) in contents.lines().enumerate().skip(2) {
+            let mut fields = line.split_whitespace().map(|s| s.parse::<f64>());
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(Ok(v)), Some(Ok(quiet)), Some(Ok(spot))) => {
+                    rv.push(v * 1e3);
+                    ccf_quiet.push(quiet);
+                    ccf_spot.push(spot);
+                }
+                _ => {
+                    return Err(LatherError::parse(
+                        &self.path,
+                        format!(
+                            "Line {} of the CCFs for line group {:?} should be `rv ccf ccf_spot`, but is {:?}",
+                            line_number + 1,
+                            self.name,
+                            line
+                        ),
+                    ))
+                }
+            }
+        }
+
+        if rv.len() < 2 || rv.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(LatherError::Validation(format!(
+                "The CCFs for line group {:?} must have at least two strictly increasing velocities",
+                self.name
+            )));
+        }
+
+        Ok((
+            Profile::new(rv.clone(), ccf_quiet),
+            Profile::new(rv, ccf_spot),
+        ))
+    }
+
+    /// Makes any relative paths in this config relative to `dir` instead.
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        self.path = dir.join(&self.path);
+    }
+}
+
+/// The CCF of a subset of spectral lines, such as those binned by depth or
+/// formation height, with separate shapes for the quiet photosphere and spots.
+#[derive(Debug)]
+pub struct LineGroup {
+    pub name: String,
+    pub profile_quiet: Profile,
+    pub profile_spot: Profile,
+    pub integrated_ccf: Vec<f64>,
+}
@@ -0,0 +1,253 @@
+use rand::Rng;
+use rand_distr::StandardNormal;
+use serde::{Deserialize, Serialize};
+
+use crate::error::LatherError;
+use crate::profile::Profile;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct PhotonNoiseConfig {
+    pub magnitude: f64,
+    pub zero_point: f64,
+    pub throughput: f64,
+    pub exposure: f64,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct RedNoiseConfig {
+    pub amplitude: f64,
+    pub timescale: f64,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct InstrumentConfig {
+    pub name: String,
+    pub photon: Option<PhotonNoiseConfig>,
+    pub ccf_snr: Option<f64>,
+    pub white_noise: Option<f64>,
+    pub red_noise: Option<RedNoiseConfig>,
+    pub offset: Option<f64>,
+}
+
+/// A stationary Gaussian process with an exponential covariance (an Ornstein-Uhlenbeck
+/// process), which models slowly-varying instrumental systematics.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct RedNoise {
+    pub amplitude: f64,
+    /// Correlation timescale in days
+    pub timescale: f64,
+}
+
+/// The noise properties of an instrument. For flux observations `white_noise`,
+/// `red_noise`, and `offset` are in units of relative flux; for CCFs they are
+/// radial velocities in m/s.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Instrument {
+    pub name: String,
+    /// Photons collected in one exposure of the unspotted star
+    pub photons: Option<f64>,
+    /// Signal to noise ratio of the CCF continuum
+    pub ccf_snr: Option<f64>,
+    pub white_noise: f64,
+    pub red_noise: Option<RedNoise>,
+    pub offset: f64,
+}
+
+impl Instrument {
+    pub fn from_config(config: &InstrumentConfig) -> Result<Instrument, LatherError> {
+        let photons = match &config.photon {
+            Some(photon) => {
+                // zero_point is the photon rate in photons/s of a magnitude 0 star
+                let photons = photon.zero_point
+                    * 10f64.powf(-0.4 * photon.magnitude)
+                    * photon.throughput
+                    * photon.exposure;
+                if photons.is_nan() || photons <= 0.0 {
+                    return Err(LatherError::Validation(format!(
+                        "Instrument {:?} would collect {} photons per exposure, but it must collect some",
+                        config.name, photons
+                    )));
+                }
+                Some(photons)
+            }
+            None => None,
+        };
+        if let Some(snr) = config.ccf_snr {
+            if snr.is_nan() || snr <= 0.0 {
+                return Err(LatherError::Validation(format!(
+                    "The CCF signal to noise ratio of instrument {:?} must be positive, but is {}",
+                    config.name, snr
+                )));
+            }
+        }
+        let red_noise = match config.red_noise {
+            Some(red) => {
+                if red.timescale.is_nan() || red.timescale <= 0.0 {
+                    return Err(LatherError::Validation(format!(
+                        "The red noise timescale of instrument {:?} must be positive, but is {}",
+                        config.name, red.timescale
+                    )));
+                }
+                Some(RedNoise {
+                    amplitude: red.amplitude,
+                    timescale: red.timescale,
+                })
+            }
+            None => None,
+        };
+        Ok(Instrument {
+            name: config.name.clone(),
+            photons,
+            ccf_snr: config.ccf_snr,
+            white_noise: config.white_noise.unwrap_or(0.0),
+            red_noise,
+            offset: config.offset.unwrap_or(0.0),
+        })
+    }
+
+    /// Draws the instrumental offset, white noise, and red noise at each time.
+    /// Times do not need to be sorted.
+    fn systematics(&self, time: &[f64], rng: &mut impl Rng) -> Vec<f64> {
+        let mut noise: Vec<f64> = time
+            .iter()
+            .map(|_| self.offset + self.white_noise * rng.sample::<f64, _>(StandardNormal))
+            .collect();
+
+        if let Some(red) = self.red_noise {
+            let mut order: Vec<usize> = (0..time.len()).collect();
+            order.sort_by(|a, b| time[*a].total_cmp(&time[*b]));
+            let mut previous: Option<(f64, f64)> = None;
+            for i in order {
+                let value = match previous {
+                    None => red.amplitude * rng.sample::<f64, _>(StandardNormal),
+                    Some((previous_time, previous_value)) => {
+                        let decay = (-(time[i] - previous_time) / red.timescale).exp();
+                        previous_value * decay
+                            + red.amplitude
+                                * (1.0 - decay * decay).sqrt()
+                                * rng.sample::<f64, _>(StandardNormal)
+                    }
+                };
+                noise[i] += value;
+                previous = Some((time[i], value));
+            }
+        }
+
+        noise
+    }
+
+    /// Adds photon noise and instrumental noise to relative fluxes observed at `time`.
+    pub fn apply_flux_noise(&self, time: &[f64], flux: &mut [f64], rng: &mut impl Rng) {
+        let systematics = self.systematics(time, rng);
+        for (flux, systematic) in flux.iter_mut().zip(systematics) {
+            if let Some(photons) = self.photons {
+                let sigma = (flux.max(0.0) / photons).sqrt();
+                *flux += sigma * rng.sample::<f64, _>(StandardNormal);
+            }
+            *flux += systematic;
+        }
+    }
+
+    /// Adds instrumental radial velocity noise and per-pixel photon noise to CCFs observed
+    /// at `time`, which are sampled at the velocities `rv`.
+    pub fn apply_ccf_noise(
+        &self,
+        time: &[f64],
+        rv: &[f64],
+        ccfs: &mut [Vec<f64>],
+        rng: &mut impl Rng,
+    ) {
+        let systematics = self.systematics(time, rng);
+        for (ccf, velocity) in ccfs.iter_mut().zip(systematics) {
+            if velocity != 0.0 {
+                let profile = Profile::new(rv.to_vec(), ccf.clone());
+                profile.shift_into(velocity, ccf);
+            }
+            if let Some(snr) = self.ccf_snr {
+                let continuum = ccf.iter().cloned().fold(0.0, f64::max);
+                for value in ccf.iter_mut() {
+                    let sigma = (value.max(0.0) * continuum).sqrt() / snr;
+                    *value += sigma * rng.sample::<f64, _>(StandardNormal);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn instrument() -> Instrument {
+        Instrument {
+            name: "test".to_string(),
+            photons: None,
+            ccf_snr: None,
+            white_noise: 0.0,
+            red_noise: None,
+            offset: 0.0,
+        }
+    }
+
+    fn std_dev(values: &[f64]) -> f64 {
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn photon_noise_scales_with_photons() {
+        let instrument = Instrument {
+            photons: Some(1e6),
+            ..instrument()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let time = vec![0.0; 10000];
+        let mut flux = vec![1.0; 10000];
+        instrument.apply_flux_noise(&time, &mut flux, &mut rng);
+        assert!((std_dev(&flux) / 1e-3 - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn red_noise_is_correlated() {
+        let instrument = Instrument {
+            red_noise: Some(RedNoise {
+                amplitude: 1.0,
+                timescale: 10.0,
+            }),
+            offset: 5.0,
+            ..instrument()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        // Unsorted times are handled in time order
+        let time: Vec<f64> = (0..1000).rev().map(|i| i as f64 * 0.01).collect();
+        let noise = instrument.systematics(&time, &mut rng);
+        for pair in noise.windows(2) {
+            assert!((pair[0] - pair[1]).abs() < 0.2);
+        }
+        assert!(noise.iter().all(|n| (n - 5.0).abs() < 5.0));
+    }
+
+    #[test]
+    fn offset_shifts_ccf() {
+        let rv: Vec<f64> = (0..101).map(|i| (i as f64 - 50.0) * 100.0).collect();
+        let ccf: Vec<f64> = rv
+            .iter()
+            .map(|v| 1.0 - 0.5 * (-(v / 1000.0f64).powi(2)).exp())
+            .collect();
+        let instrument = Instrument {
+            offset: 200.0,
+            ..instrument()
+        };
+        let mut ccfs = vec![ccf.clone()];
+        let mut rng = StdRng::seed_from_u64(0);
+        instrument.apply_ccf_noise(&[0.0], &rv, &mut ccfs, &mut rng);
+        let minimum = |ccf: &[f64]| {
+            (0..ccf.len())
+                .min_by(|a, b| ccf[*a].partial_cmp(&ccf[*b]).unwrap())
+                .unwrap()
+        };
+        assert_eq!(rv[minimum(&ccfs[0])] - rv[minimum(&ccf)], 200.0);
+    }
+}
@@ -0,0 +1,46 @@
+use crate::bounds::Bounds;
+use crate::convergence::rv_shift;
+
+/// The relative flux of a simulation observed at a series of times in one band.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightCurve {
+    /// The time of each observation in days
+    pub time: Vec<f64>,
+    /// The wavelength band in meters
+    pub band: Bounds,
+    /// The flux at each time, relative to the unspotted star
+    pub flux: Vec<f64>,
+}
+
+/// The CCFs of a simulation observed at a series of times in one band.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CcfSeries {
+    /// The time of each observation in days
+    pub time: Vec<f64>,
+    /// The wavelength band in meters
+    pub band: Bounds,
+    /// The radial velocities (m/s) the CCFs are sampled at
+    pub rv: Vec<f64>,
+    /// The CCF at each time
+    pub ccfs: Vec<Vec<f64>>,
+    /// The CCF of the unspotted star
+    pub quiet_ccf: Vec<f64>,
+}
+
+impl CcfSeries {
+    /// The RV shift (m/s) of each CCF from the unspotted star's, to first order in the
+    /// change spots make to the CCF.
+    pub fn rv_shifts(&self) -> Vec<f64> {
+        self.ccfs
+            .iter()
+            .map(|ccf| {
+                let change: Vec<f64> = ccf
+                    .iter()
+                    .zip(self.quiet_ccf.iter())
+                    .map(|(observed, quiet)| observed - quiet)
+                    .collect();
+                rv_shift(&self.quiet_ccf, &self.rv, &change)
+            })
+            .collect()
+    }
+}
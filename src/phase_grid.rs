@@ -0,0 +1,182 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error::LatherError;
+use crate::spot::Spot;
+
+/// The cubic interpolation needs four phases around any time.
+const MIN_SAMPLES: usize = 4;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct PhaseGridConfig {
+    pub samples: usize,
+}
+
+/// Checks that a phase grid of `samples` phases can be interpolated.
+pub(crate) fn check_samples(samples: usize) -> Result<(), LatherError> {
+    if samples < MIN_SAMPLES {
+        return Err(LatherError::Validation(format!(
+            "The phase grid needs at least {} samples, but has {}",
+            MIN_SAMPLES, samples
+        )));
+    }
+    Ok(())
+}
+
+/// An immortal spot's covered limb-darkened area and CCF components, tabulated at
+/// `samples` evenly spaced phases of one rotation. An immortal spot looks the same every
+/// rotation, so these replace the strip integration at any time with a periodic cubic
+/// interpolation between the four nearest phases.
+#[derive(Debug)]
+pub struct PhaseGrid {
+    period: f64,
+    profile_len: usize,
+    limb_integral: Vec<f64>,
+    /// `quiet_covered` and `active_covered` of `Spot::add_profile_components` for the
+    /// star's quiet and spot profiles, with `profile_len` values per phase
+    quiet_covered: Vec<f64>,
+    active_covered: Vec<f64>,
+}
+
+impl PhaseGrid {
+    /// `samples` must have passed `check_samples`.
+    pub fn new(spot: &Spot, samples: usize) -> PhaseGrid {
+        debug_assert!(samples >= MIN_SAMPLES);
+        // Make sure the grid is computed from the exact integrals
+        let spot = Spot {
+            phase_grid: None,
+            ..spot.clone()
+        };
+        let star = &spot.star;
+        let profile_len = star.profile_quiet.len();
+        let times: Vec<f64> = (0..samples)
+            .map(|i| i as f64 * star.period / samples as f64)
+            .collect();
+
+        let limb_integral = times.par_iter().map(|t| spot.limb_integral(*t)).collect();
+        let components: Vec<(Vec<f64>, Vec<f64>)> = times
+            .par_iter()
+            .map(|t| {
+                let mut quiet_covered = vec![0.0; profile_len];
+                let mut active_covered = vec![0.0; profile_len];
+                spot.add_profile_components(
+                    *t,
+                    &star.profile_quiet,
+                    &star.profile_spot,
+                    &mut quiet_covered,
+                    &mut active_covered,
+                );
+                (quiet_covered, active_covered)
+            })
+            .collect();
+        let (quiet_covered, active_covered): (Vec<_>, Vec<_>) = components.into_iter().unzip();
+
+        PhaseGrid {
+            period: star.period,
+            profile_len,
+            limb_integral,
+            quiet_covered: quiet_covered.concat(),
+            active_covered: active_covered.concat(),
+        }
+    }
+
+    /// The indices and weights of the four phases used to interpolate at `time`, from
+    /// Lagrange interpolation through them.
+    fn weights(&self, time: f64) -> [(usize, f64); 4] {
+        let samples = self.limb_integral.len();
+        let x = (time / self.period).rem_euclid(1.0) * samples as f64;
+        let i = x.floor() as usize;
+        let f = x - i as f64;
+        let index = |offset: usize| (i + offset + samples - 1) % samples;
+        [
+            (index(0), -f * (f - 1.0) * (f - 2.0) / 6.0),
+            (index(1), (f + 1.0) * (f - 1.0) * (f - 2.0) / 2.0),
+            (index(2), -(f + 1.0) * f * (f - 2.0) / 2.0),
+            (index(3), (f + 1.0) * f * (f - 1.0) / 6.0),
+        ]
+    }
+
+    /// Interpolates `Spot::limb_integral`.
+    pub fn limb_integral(&self, time: f64) -> f64 {
+        let value: f64 = self
+            .weights(time)
+            .iter()
+            .map(|(i, w)| w * self.limb_integral[*i])
+            .sum();
+        // The spot's area goes to zero with a kink at the limb, where the cubic overshoots
+        value.max(0.0)
+    }
+
+    /// Interpolates `Spot::add_profile_components` for the star's quiet and spot profiles.
+    pub fn add_profile_components(
+        &self,
+        time: f64,
+        quiet_covered: &mut [f64],
+        active_covered: &mut [f64],
+    ) {
+        for (i, w) in self.weights(time).iter() {
+            let range = i * self.profile_len..(i + 1) * self.profile_len;
+            for (tot, q) in quiet_covered
+                .iter_mut()
+                .zip(self.quiet_covered[range.clone()].iter())
+            {
+                *tot += w * q;
+            }
+            for (tot, a) in active_covered
+                .iter_mut()
+                .zip(self.active_covered[range].iter())
+            {
+                *tot += w * a;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::spot::SpotConfig;
+    use std::sync::Arc;
+
+    #[test]
+    fn interpolation_matches_integration() {
+        let sim = Simulation::sun();
+        let spot = Spot::from_config(
+            Arc::clone(&sim.star),
+            &SpotConfig {
+                latitude: 20.0,
+                longitude: 90.0,
+                fill_factor: 0.01,
+                plage: false,
+                temperature: None,
+                lifetime: None,
+            },
+        )
+        .unwrap();
+        let grid = PhaseGrid::new(&spot, 300);
+        let len = sim.star.profile_quiet.len();
+
+        for &time in &[0.37, 3.3, 6.1, 12.9, 40.2, -7.7] {
+            let exact = spot.limb_integral(time);
+            assert!((grid.limb_integral(time) - exact).abs() < 1e-5 * sim.star.flux_quiet);
+
+            let mut quiet = vec![0.0; len];
+            let mut active = vec![0.0; len];
+            spot.add_profile_components(
+                time,
+                &sim.star.profile_quiet,
+                &sim.star.profile_spot,
+                &mut quiet,
+                &mut active,
+            );
+            let mut quiet_grid = vec![0.0; len];
+            let mut active_grid = vec![0.0; len];
+            grid.add_profile_components(time, &mut quiet_grid, &mut active_grid);
+            let scale = quiet.iter().cloned().fold(0.0, f64::max);
+            for (exact, interpolated) in quiet.iter().zip(quiet_grid.iter()) {
+                assert!((exact - interpolated).abs() < 1e-2 * scale.max(1e-12));
+            }
+        }
+    }
+}
@@ -27,7 +27,7 @@ mod tests {
         let pow = 10.0f64.powi(precision + 1);
         let delta = (expected - actual).abs();
         let max_delta = 10.0f64.powi(-precision) / 2.0;
-        return (delta * pow).round() / pow <= max_delta;
+        (delta * pow).round() / pow <= max_delta
     }
 
     #[test]
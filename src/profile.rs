@@ -1,78 +1,279 @@
-/// A cross-correlation profile, which can be shifted by fast linear interpolation.
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+
+/// Velocity steps that differ by less than this fraction count as an even grid.
+const UNIFORM_TOLERANCE: f64 = 1e-9;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+#[serde(tag = "name")]
+pub enum Interpolation {
+    /// Straight lines between neighboring samples
+    #[default]
+    #[serde(rename = "linear")]
+    Linear,
+    /// A natural cubic spline through every sample, which is smooth and follows the
+    /// curvature of line cores much better than straight lines
+    #[serde(rename = "cubic_spline")]
+    CubicSpline,
+    /// A sinc kernel windowed to `lobes` samples on each side, which is nearly exact for
+    /// well-sampled profiles
+    #[serde(rename = "lanczos")]
+    Lanczos { lobes: usize },
+}
+
+/// How a line-of-sight velocity moves a profile along its velocity axis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Doppler {
+    /// Profiles are translated by the velocity, which is accurate to first order in v/c
+    #[default]
+    Classical,
+    /// The exact relativistic shift, for profiles whose velocity axis is `c (λ/λ0 - 1)`
+    Relativistic,
+    /// The exact relativistic shift, for profiles whose velocity axis is `c ln(λ/λ0)`.
+    /// Shifts on these axes are still translations, so they are as fast as classical ones.
+    Logarithmic,
+}
+
+/// A cross-correlation profile, which can be shifted by fast interpolation.
 #[derive(Debug)]
 pub struct Profile {
     pub rv: Vec<f64>,
     pub ccf: Vec<f64>,
-    derivative: Vec<f64>,
-    stepsize: f64,
+    /// Second derivatives of the natural cubic spline through the profile
+    second_derivative: Vec<f64>,
+    /// The velocity step, if the velocities are evenly spaced
+    stepsize: Option<f64>,
+    pub interpolation: Interpolation,
+    pub doppler: Doppler,
 }
 
 impl Profile {
-    /// Creates a profile with the provided radial velocity and
-    /// cross-correlation function, and computes the derivative to enable fast
-    /// linear interpolation to simulate viewing the profile at different
-    /// relative velocities.
+    /// Creates a profile with the provided radial velocity and cross-correlation function,
+    /// which is shifted with linear interpolation and the classical Doppler formula. The
+    /// velocities must be strictly increasing, but need not be evenly spaced.
     pub fn new(rv: Vec<f64>, ccf: Vec<f64>) -> Self {
-        use std::iter;
-
-        let derivative = {
-            let ccf_diff = ccf.windows(2).map(|s| s[0] - s[1]);
-            let rv_diff = rv.windows(2).map(|s| s[0] - s[1]);
-
-            ccf_diff
-                .zip(rv_diff)
-                .map(|(c, r)| c / r)
-                .chain(iter::once(0.0))
-                .collect()
+        let step = rv[1] - rv[0];
+        let stepsize = if rv
+            .windows(2)
+            .all(|w| ((w[1] - w[0]) - step).abs() <= UNIFORM_TOLERANCE * step.abs())
+        {
+            Some(step)
+        } else {
+            None
         };
 
-        let stepsize = (rv[0] - rv[1]).abs();
-
         Profile {
+            second_derivative: spline_second_derivative(&rv, &ccf),
             rv,
             ccf,
-            derivative,
             stepsize,
+            interpolation: Interpolation::default(),
+            doppler: Doppler::default(),
         }
     }
 
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn with_doppler(mut self, doppler: Doppler) -> Self {
+        self.doppler = doppler;
+        self
+    }
+
     /// Returns the number of elements in the profile.
     pub fn len(&self) -> usize {
         self.rv.len()
     }
 
-    /// Uses the pre-computed derivative to compute a shifted version of
-    /// this profile's cross-correlation function by linear interpolation.
-    /// The units of velocity must match those of the radial velocity used
-    /// to construct this profile.
+    /// Returns `true` if the profile has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.rv.is_empty()
+    }
+
+    /// Computes this profile as seen from an observer moving away from its source at
+    /// `velocity`, at the same radial velocities. The units of velocity must be m/s when
+    /// the shift is relativistic, and otherwise must match the profile's. Beyond the ends
+    /// of the profile its edge values are used, so any shift is safe.
     pub fn shift_into(&self, velocity: f64, output: &mut [f64]) {
-        use std::iter;
-        let quotient = (velocity / self.stepsize).round() as isize;
-        let remainder = velocity - (quotient as f64) * self.stepsize;
-
-        if velocity >= 0.0 {
-            iter::repeat(self.ccf[0])
-                .take(quotient as usize)
-                .chain(
-                    self.ccf
-                        .iter()
-                        .zip(self.derivative.iter())
-                        .take(self.ccf.len() - quotient as usize)
-                        .map(|(ccf, der)| ccf - remainder * der),
-                )
-                .zip(output.iter_mut())
-                .for_each(|(shifted, output)| *output = shifted)
-        } else {
-            self.ccf
-                .iter()
-                .zip(self.derivative.iter())
-                .skip((-quotient) as usize)
-                .map(|(ccf, der)| ccf - remainder * der)
-                .chain(iter::repeat(self.ccf[self.ccf.len() - 1]).take((-quotient) as usize))
-                .zip(output.iter_mut())
-                .for_each(|(shifted, output)| *output = shifted);
+        let translation = match self.doppler {
+            Doppler::Classical => Some(velocity),
+            Doppler::Logarithmic => Some(SPEED_OF_LIGHT * (velocity / SPEED_OF_LIGHT).atanh()),
+            Doppler::Relativistic => None,
+        };
+        match (translation, self.stepsize) {
+            (Some(translation), Some(stepsize)) => {
+                self.translate_uniform(translation / stepsize, output)
+            }
+            (Some(translation), None) => {
+                self.resample(|rv| rv - translation, output);
+            }
+            (None, _) => {
+                let beta = velocity / SPEED_OF_LIGHT;
+                let factor = ((1.0 + beta) / (1.0 - beta)).sqrt();
+                self.resample(
+                    |rv| SPEED_OF_LIGHT * ((1.0 + rv / SPEED_OF_LIGHT) / factor - 1.0),
+                    output,
+                );
+            }
+        }
+    }
+
+    /// Translates an evenly spaced profile by `steps` samples. Every output uses the same
+    /// weights on the same relative samples, so this is a few multiply-adds of whole slices.
+    fn translate_uniform(&self, steps: f64, output: &mut [f64]) {
+        let position = -steps;
+        let base = position.floor();
+        let fraction = position - base;
+        let base = base as isize;
+
+        output.iter_mut().for_each(|o| *o = 0.0);
+        match self.interpolation {
+            Interpolation::Linear => {
+                add_offset(output, &self.ccf, base, 1.0 - fraction);
+                add_offset(output, &self.ccf, base + 1, fraction);
+            }
+            Interpolation::CubicSpline => {
+                let stepsize = self.stepsize.unwrap_or(1.0);
+                let [a, b, c, d] = spline_weights(fraction, stepsize);
+                add_offset(output, &self.ccf, base, a);
+                add_offset(output, &self.ccf, base + 1, b);
+                add_offset(output, &self.second_derivative, base, c);
+                add_offset(output, &self.second_derivative, base + 1, d);
+            }
+            Interpolation::Lanczos { lobes } => {
+                for (offset, weight) in lanczos_weights(fraction, lobes) {
+                    add_offset(output, &self.ccf, base + offset, weight);
+                }
+            }
+        }
+    }
+
+    /// Fills `output` with the profile interpolated at `source(rv)` for each of its
+    /// velocities. `source` must be increasing.
+    fn resample(&self, source: impl Fn(f64) -> f64, output: &mut [f64]) {
+        let last = self.len() - 1;
+        let mut segment = 0;
+        for (rv, output) in self.rv.iter().zip(output.iter_mut()) {
+            let x = source(*rv);
+            if x <= self.rv[0] {
+                *output = self.ccf[0];
+                continue;
+            }
+            if x >= self.rv[last] {
+                *output = self.ccf[last];
+                continue;
+            }
+            while self.rv[segment + 1] <= x {
+                segment += 1;
+            }
+            let width = self.rv[segment + 1] - self.rv[segment];
+            let fraction = (x - self.rv[segment]) / width;
+
+            *output = match self.interpolation {
+                Interpolation::Linear => {
+                    self.ccf[segment] + fraction * (self.ccf[segment + 1] - self.ccf[segment])
+                }
+                Interpolation::CubicSpline => {
+                    let [a, b, c, d] = spline_weights(fraction, width);
+                    a * self.ccf[segment]
+                        + b * self.ccf[segment + 1]
+                        + c * self.second_derivative[segment]
+                        + d * self.second_derivative[segment + 1]
+                }
+                // On uneven grids this treats the local spacing as if it were even
+                Interpolation::Lanczos { lobes } => lanczos_weights(fraction, lobes)
+                    .map(|(offset, weight)| {
+                        let i = (segment as isize + offset).clamp(0, last as isize) as usize;
+                        weight * self.ccf[i]
+                    })
+                    .sum(),
+            };
+        }
+    }
+}
+
+/// Adds `weight * data[i + offset]` to each `output[i]`, using the edge values of `data`
+/// where `i + offset` is out of bounds.
+fn add_offset(output: &mut [f64], data: &[f64], offset: isize, weight: f64) {
+    if weight == 0.0 {
+        return;
+    }
+    let len = output.len().min(data.len()) as isize;
+    // The outputs whose source is inside `data`
+    let start = (-offset).clamp(0, len) as usize;
+    let end = (len - offset).clamp(0, len) as usize;
+
+    for o in &mut output[..start] {
+        *o += weight * data[0];
+    }
+    if start < end {
+        let source = &data[(start as isize + offset) as usize..(end as isize + offset) as usize];
+        for (o, d) in output[start..end].iter_mut().zip(source.iter()) {
+            *o += weight * d;
         }
     }
+    let edge = data[data.len() - 1];
+    for o in &mut output[end.max(start)..len as usize] {
+        *o += weight * edge;
+    }
+}
+
+/// Solves for the second derivatives of the natural cubic spline through the points.
+fn spline_second_derivative(x: &[f64], y: &[f64]) -> Vec<f64> {
+    let n = x.len();
+    let mut second = vec![0.0; n];
+    if n < 3 {
+        return second;
+    }
+    // Forward elimination of the tridiagonal system, as in Numerical Recipes
+    let mut u = vec![0.0; n];
+    for i in 1..n - 1 {
+        let sig = (x[i] - x[i - 1]) / (x[i + 1] - x[i - 1]);
+        let p = sig * second[i - 1] + 2.0;
+        second[i] = (sig - 1.0) / p;
+        let slope_change =
+            (y[i + 1] - y[i]) / (x[i + 1] - x[i]) - (y[i] - y[i - 1]) / (x[i] - x[i - 1]);
+        u[i] = (6.0 * slope_change / (x[i + 1] - x[i - 1]) - sig * u[i - 1]) / p;
+    }
+    second[n - 1] = 0.0;
+    for i in (0..n - 1).rev() {
+        second[i] = second[i] * second[i + 1] + u[i];
+    }
+    second
+}
+
+/// The weights of `y[j]`, `y[j + 1]`, `y''[j]`, and `y''[j + 1]` in the cubic spline at
+/// `fraction` of the way across a segment of `width`.
+fn spline_weights(fraction: f64, width: f64) -> [f64; 4] {
+    let b = fraction;
+    let a = 1.0 - b;
+    let h2 = width * width / 6.0;
+    [a, b, (a * a * a - a) * h2, (b * b * b - b) * h2]
+}
+
+/// The offsets from the sample below the interpolation point and their Lanczos weights,
+/// normalized so a constant profile stays constant.
+fn lanczos_weights(fraction: f64, lobes: usize) -> impl Iterator<Item = (isize, f64)> {
+    let lobes = lobes.max(1) as isize;
+    let kernel = move |x: f64| {
+        if x == 0.0 {
+            1.0
+        } else if x.abs() >= lobes as f64 {
+            0.0
+        } else {
+            let px = PI * x;
+            lobes as f64 * px.sin() * (px / lobes as f64).sin() / (px * px)
+        }
+    };
+    let total: f64 = (1 - lobes..=lobes)
+        .map(|offset| kernel(fraction - offset as f64))
+        .sum();
+    (1 - lobes..=lobes).map(move |offset| (offset, kernel(fraction - offset as f64) / total))
 }
 
 #[cfg(test)]
@@ -80,38 +281,36 @@ mod tests {
     use super::*;
     use crate::solar_ccfs::*;
 
-    #[test]
-    fn derivative_len() {
-        let test_profile = Profile::new(RV.to_vec(), CCF_QUIET.to_vec());
-        assert_eq!(
-            test_profile.derivative.len(),
-            CCF_QUIET.len(),
-            "derivative length is {} but should be {}",
-            test_profile.derivative.len(),
-            CCF_QUIET.len()
-        );
+    fn shifted(profile: &Profile, velocity: f64) -> Vec<f64> {
+        let mut shifted = vec![0.0; profile.len()];
+        profile.shift_into(velocity, &mut shifted);
+        shifted
     }
 
     #[test]
     fn pos_zero_shift() {
-        let test_profile = Profile::new(RV.to_vec(), CCF_QUIET.to_vec());
-        let mut shifted = vec![0.0; test_profile.len()];
-        test_profile.shift_into(0.0, &mut shifted);
-        for (original, shifted) in CCF_QUIET.iter().zip(shifted.iter()) {
-            assert_eq!(
-                original, shifted,
-                "zero-shifted profile value is {} but should be {}",
-                original, shifted
-            );
+        for interpolation in &[
+            Interpolation::Linear,
+            Interpolation::CubicSpline,
+            Interpolation::Lanczos { lobes: 3 },
+        ] {
+            let test_profile =
+                Profile::new(RV.to_vec(), CCF_QUIET.to_vec()).with_interpolation(*interpolation);
+            for (original, shifted) in CCF_QUIET.iter().zip(shifted(&test_profile, 0.0).iter()) {
+                assert!(
+                    (original - shifted).abs() < 1e-12 * original.abs(),
+                    "zero-shifted profile value is {} but should be {}",
+                    shifted,
+                    original
+                );
+            }
         }
     }
 
     #[test]
     fn neg_zero_shift() {
         let test_profile = Profile::new(RV.to_vec(), CCF_QUIET.to_vec());
-        let mut shifted = vec![0.0; test_profile.len()];
-        test_profile.shift_into(-0.0, &mut shifted);
-        for (original, shifted) in CCF_QUIET.iter().zip(shifted.iter()) {
+        for (original, shifted) in CCF_QUIET.iter().zip(shifted(&test_profile, -0.0).iter()) {
             assert_eq!(
                 original, shifted,
                 "negative zero-shifted profile value is {} but should be {}",
@@ -119,4 +318,77 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn kernels_reproduce_smooth_profiles() {
+        // A Gaussian sampled finely enough that every kernel should be accurate
+        let gaussian = |v: f64| 1.0 - 0.5 * (-v * v / (2.0 * 3000.0f64.powi(2))).exp();
+        let rv: Vec<f64> = (-100..=100).map(|i| i as f64 * 250.0).collect();
+        let ccf: Vec<f64> = rv.iter().map(|v| gaussian(*v)).collect();
+        for (interpolation, tolerance) in &[
+            (Interpolation::Linear, 2e-3),
+            (Interpolation::CubicSpline, 1e-5),
+            (Interpolation::Lanczos { lobes: 4 }, 2e-4),
+        ] {
+            let profile = Profile::new(rv.clone(), ccf.clone()).with_interpolation(*interpolation);
+            for &velocity in &[123.4, -987.6, 2000.0] {
+                for (v, s) in rv.iter().zip(shifted(&profile, velocity).iter()) {
+                    assert!(
+                        (s - gaussian(v - velocity)).abs() < *tolerance,
+                        "{:?} is off by {} at {}",
+                        interpolation,
+                        s - gaussian(v - velocity),
+                        v
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn uneven_grids_match_even_grids() {
+        let rv: Vec<f64> = (0..50).map(|i| i as f64 * 100.0).collect();
+        let ccf: Vec<f64> = rv.iter().map(|v| (v / 700.0).sin()).collect();
+        let even = Profile::new(rv.clone(), ccf.clone());
+        // Nudge one velocity so the grid is no longer even, without changing the data
+        let mut uneven_rv = rv.clone();
+        uneven_rv[25] += 1e-3;
+        let uneven = Profile::new(uneven_rv, ccf);
+        assert!(uneven.stepsize.is_none());
+        for (a, b) in shifted(&even, 321.0)
+            .iter()
+            .zip(shifted(&uneven, 321.0).iter())
+        {
+            assert!((a - b).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn large_shifts_give_edge_values() {
+        let profile = Profile::new(RV.to_vec(), CCF_QUIET.to_vec());
+        for &velocity in &[1e9, -1e9] {
+            let edge = if velocity > 0.0 {
+                CCF_QUIET[0]
+            } else {
+                CCF_QUIET[CCF_QUIET.len() - 1]
+            };
+            assert!(shifted(&profile, velocity).iter().all(|v| *v == edge));
+        }
+    }
+
+    #[test]
+    fn relativistic_shifts_approach_classical() {
+        let classical = Profile::new(RV.to_vec(), CCF_QUIET.to_vec());
+        let relativistic =
+            Profile::new(RV.to_vec(), CCF_QUIET.to_vec()).with_doppler(Doppler::Relativistic);
+        let logarithmic =
+            Profile::new(RV.to_vec(), CCF_QUIET.to_vec()).with_doppler(Doppler::Logarithmic);
+        let velocity = 2000.0;
+        let expected = shifted(&classical, velocity);
+        for profile in &[relativistic, logarithmic] {
+            for (a, b) in expected.iter().zip(shifted(profile, velocity).iter()) {
+                assert!((a - b).abs() < 1e-4 * a.abs());
+            }
+        }
+    }
 }
@@ -0,0 +1,846 @@
+//! The `lather.native` Python extension module. Its classes wrap the Rust types directly,
+//! borrow NumPy arrays instead of copying them, and release the GIL while observing.
+
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use numpy::{
+    IntoPyArray, PyArray1, PyArray2, PyArray3, PyArrayMethods, PyReadonlyArray1,
+    PyUntypedArrayMethods,
+};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::bounds::Bounds;
+use crate::builder::StarBuilder;
+use crate::convergence::{rv_shift, ResolutionConfig, Tolerance};
+use crate::error::LatherError;
+use crate::exposure::{Exposure, ExposureConfig, SupersampleConfig};
+use crate::generation::{GenerationError, GenerationFailure};
+use crate::phase_grid;
+use crate::simulation::{Simulation, DEFAULT_SEED};
+use crate::solar_ccfs::RV;
+use crate::spot::{Lifetime, Mortality, Spot, SpotConfig};
+use crate::star::{Star, StarConfig, IMAGE_SIZE};
+use crate::state::SimulationState;
+
+/// Raises the matching exception from `lather.errors`.
+impl From<LatherError> for PyErr {
+    fn from(error: LatherError) -> PyErr {
+        Python::attach(|py| python_error(py, &error))
+            .unwrap_or_else(|_| PyRuntimeError::new_err(error.to_string()))
+    }
+}
+
+fn python_error(py: Python, error: &LatherError) -> PyResult<PyErr> {
+    let errors = py.import("lather.errors")?;
+    let message = error.to_string();
+    let class = match error {
+        LatherError::Io { .. } => "LatherIOError",
+        LatherError::Parse { .. } => "ConfigParseError",
+        LatherError::Validation(_) => "ValidationError",
+        LatherError::Numerical(_) => "NumericalError",
+        LatherError::Generation(diagnostics) => {
+            let kwargs = generation_diagnostics(py, diagnostics)?;
+            let exception = errors
+                .getattr("SpotGenerationError")?
+                .call((message,), Some(&kwargs))?;
+            return Ok(PyErr::from_value(exception));
+        }
+    };
+    Ok(PyErr::from_value(errors.getattr(class)?.call1((message,))?))
+}
+
+fn generation_diagnostics<'py>(
+    py: Python<'py>,
+    error: &GenerationError,
+) -> PyResult<Bound<'py, PyDict>> {
+    let kwargs = PyDict::new(py);
+    let failure = match error.failure {
+        GenerationFailure::SizeDraws => "size_draws",
+        GenerationFailure::Placement => "placement",
+    };
+    kwargs.set_item("failure", failure)?;
+    kwargs.set_item("time", error.time)?;
+    kwargs.set_item("target_fill_factor", error.target_fill_factor)?;
+    kwargs.set_item("fill_factor", error.fill_factor)?;
+    kwargs.set_item("spots", error.spots)?;
+    kwargs.set_item("size_draws", error.size_draws)?;
+    kwargs.set_item("rejected_sizes", error.rejected_sizes)?;
+    kwargs.set_item("collisions", error.collisions)?;
+    Ok(kwargs)
+}
+
+fn bands(wave_starts: &[f64], wave_ends: &[f64]) -> Result<Vec<Bounds>, LatherError> {
+    if wave_starts.len() != wave_ends.len() {
+        return Err(LatherError::Validation(format!(
+            "There are {} band starts but {} band ends",
+            wave_starts.len(),
+            wave_ends.len()
+        )));
+    }
+    Ok(wave_starts
+        .iter()
+        .zip(wave_ends)
+        .map(|(&start, &end)| Bounds::new(start, end))
+        .collect())
+}
+
+/// Moves rows of `width` values into a 2D array, copying them once to make them contiguous.
+fn rows_to_pyarray(
+    py: Python,
+    rows: Vec<Vec<f64>>,
+    width: usize,
+) -> PyResult<Bound<PyArray2<f64>>> {
+    let height = rows.len();
+    let flat: Vec<f64> = rows.into_iter().flatten().collect();
+    flat.into_pyarray(py).reshape([height, width])
+}
+
+/// A star, built from the same parameters as `StarBuilder`.
+#[pyclass(name = "Star", module = "lather.native", frozen)]
+pub struct PyStar {
+    star: Arc<Star>,
+}
+
+#[pymethods]
+impl PyStar {
+    #[new]
+    #[pyo3(signature = (
+        grid_size=1000,
+        radius=1.0,
+        period=25.05,
+        inclination=90.0,
+        temperature=5778.0,
+        spot_temp_diff=663.0,
+        limb_linear=0.29,
+        limb_quadratic=0.34,
+        target_fill_factor=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python,
+        grid_size: usize,
+        radius: f64,
+        period: f64,
+        inclination: f64,
+        temperature: f64,
+        spot_temp_diff: f64,
+        limb_linear: f64,
+        limb_quadratic: f64,
+        target_fill_factor: Option<f64>,
+    ) -> PyResult<PyStar> {
+        let mut builder = StarBuilder::new()
+            .grid_size(grid_size)
+            .radius(radius)
+            .period(period)
+            .inclination(inclination)
+            .temperature(temperature)
+            .spot_temp_diff(spot_temp_diff)
+            .limb_darkening(limb_linear, limb_quadratic);
+        if let Some(target_fill_factor) = target_fill_factor {
+            builder = builder.target_fill_factor(target_fill_factor);
+        }
+        Ok(py.detach(|| PyStar::from_config(builder.config().clone()))?)
+    }
+
+    /// Builds a star from the `[star]` section of a config, as a TOML string. Relative
+    /// paths in it are relative to the working directory.
+    #[staticmethod]
+    fn from_toml(py: Python, contents: &str) -> PyResult<PyStar> {
+        let config: StarConfig = ::toml::from_str(contents).map_err(|e| LatherError::Parse {
+            path: None,
+            message: e.to_string(),
+        })?;
+        Ok(py.detach(|| PyStar::from_config(config))?)
+    }
+
+    #[getter]
+    fn grid_size(&self) -> usize {
+        self.star.config().grid_size
+    }
+
+    /// The radius in solar radii
+    #[getter]
+    fn radius(&self) -> f64 {
+        self.star.config().radius
+    }
+
+    /// The rotation period in days
+    #[getter]
+    fn period(&self) -> f64 {
+        self.star.config().period
+    }
+
+    /// The inclination of the rotation axis in degrees
+    #[getter]
+    fn inclination(&self) -> f64 {
+        self.star.config().inclination
+    }
+
+    #[getter]
+    fn temperature(&self) -> f64 {
+        self.star.config().temperature
+    }
+
+    #[getter]
+    fn spot_temp_diff(&self) -> f64 {
+        self.star.config().spot_temp_diff
+    }
+
+    #[getter]
+    fn limb_darkening(&self) -> (f64, f64) {
+        (
+            self.star.config().limb_linear,
+            self.star.config().limb_quadratic,
+        )
+    }
+
+    #[getter]
+    fn target_fill_factor(&self) -> f64 {
+        self.star.target_fill_factor
+    }
+
+    /// The projected equatorial velocity in m/s
+    #[getter]
+    fn equatorial_velocity(&self) -> f64 {
+        self.star.equatorial_velocity
+    }
+
+    /// The radial velocities (m/s) CCFs are sampled at
+    #[getter]
+    fn rv<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<f64>> {
+        PyArray1::from_slice(py, &self.star.profile_quiet.rv)
+    }
+
+    /// The CCF of the unspotted star
+    #[getter]
+    fn quiet_ccf<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<f64>> {
+        PyArray1::from_slice(py, &self.star.integrated_ccf)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.star)
+    }
+
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(Bound<'py, PyAny>, (String,))> {
+        let config = ::toml::Value::try_from(self.star.config())
+            .and_then(|value| ::toml::to_string(&value))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok((py.get_type::<PyStar>().getattr("from_toml")?, (config,)))
+    }
+}
+
+impl PyStar {
+    fn from_config(config: StarConfig) -> Result<PyStar, LatherError> {
+        Ok(PyStar {
+            star: Arc::new(Star::from_config(&config)?),
+        })
+    }
+}
+
+/// A spot on a simulated star. Spots are copies, so changing the simulation doesn't
+/// change them.
+#[pyclass(name = "Spot", module = "lather.native", frozen, get_all)]
+pub struct PySpot {
+    /// Degrees from the equator
+    latitude: f64,
+    /// Degrees
+    longitude: f64,
+    /// The fraction of the star the spot covers
+    fill_factor: f64,
+    /// Kelvin
+    temperature: f64,
+    plage: bool,
+    /// Whether the spot was generated to maintain the star's fill factor
+    generated: bool,
+    /// The time in days the spot appears, or None if it always exists
+    start: Option<f64>,
+    /// The time in days the spot disappears, or None if it always exists
+    end: Option<f64>,
+}
+
+#[pymethods]
+impl PySpot {
+    fn __repr__(&self) -> String {
+        format!(
+            "Spot(latitude={}, longitude={}, fill_factor={}, temperature={}, plage={}, \
+             generated={}, start={:?}, end={:?})",
+            self.latitude,
+            self.longitude,
+            self.fill_factor,
+            self.temperature,
+            self.plage,
+            self.generated,
+            self.start,
+            self.end
+        )
+    }
+}
+
+impl From<&Spot> for PySpot {
+    fn from(spot: &Spot) -> PySpot {
+        let lifetime = match &spot.mortality {
+            Mortality::Immortal => None,
+            Mortality::Mortal(lifetime) => Some(*lifetime),
+        };
+        PySpot {
+            latitude: spot.latitude.to_degrees(),
+            longitude: spot.longitude.to_degrees(),
+            fill_factor: spot.radius * spot.radius / 2.0,
+            temperature: spot.temperature,
+            plage: spot.plage,
+            generated: spot.generated,
+            start: lifetime.map(|l| l.lower),
+            end: lifetime.map(|l| l.upper),
+        }
+    }
+}
+
+/// The relative flux of a simulation observed at a series of times in one band.
+#[pyclass(name = "LightCurve", module = "lather.native", frozen, get_all)]
+pub struct PyLightCurve {
+    /// The time of each observation in days
+    time: Py<PyArray1<f64>>,
+    /// The wavelength band in meters
+    band: (f64, f64),
+    /// The flux at each time, relative to the unspotted star
+    flux: Py<PyArray1<f64>>,
+}
+
+/// The CCFs of a simulation observed at a series of times in one band.
+#[pyclass(name = "CcfSeries", module = "lather.native", frozen, get_all)]
+pub struct PyCcfSeries {
+    /// The time of each observation in days
+    time: Py<PyArray1<f64>>,
+    /// The wavelength band in meters
+    band: (f64, f64),
+    /// The radial velocities (m/s) the CCFs are sampled at
+    rv: Py<PyArray1<f64>>,
+    /// The CCF at each time, one row per time
+    ccfs: Py<PyArray2<f64>>,
+    /// The CCF of the unspotted star
+    quiet_ccf: Py<PyArray1<f64>>,
+}
+
+#[pymethods]
+impl PyCcfSeries {
+    /// The RV shift (m/s) of each CCF from the unspotted star's, to first order in the
+    /// change spots make to the CCF.
+    fn rv_shifts<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray1<f64>>> {
+        let rv = self.rv.bind(py).readonly();
+        let quiet_ccf = self.quiet_ccf.bind(py).readonly();
+        let ccfs = self.ccfs.bind(py).readonly();
+        let (rv, quiet_ccf) = (rv.as_slice()?, quiet_ccf.as_slice()?);
+        let shifts: Vec<f64> = ccfs
+            .as_slice()?
+            .chunks(rv.len())
+            .map(|ccf| {
+                let change: Vec<f64> = ccf
+                    .iter()
+                    .zip(quiet_ccf)
+                    .map(|(observed, quiet)| observed - quiet)
+                    .collect();
+                rv_shift(quiet_ccf, rv, &change)
+            })
+            .collect();
+        Ok(shifts.into_pyarray(py))
+    }
+}
+
+/// A star with spots that can be observed.
+///
+/// Observations release the GIL and only read the simulation, so one simulation can be
+/// observed from several threads at once. Random spots are generated before observing
+/// the first time they are needed, and methods that change the simulation wait for
+/// observations in progress to finish. Observing before any time observed so far
+/// generates every random spot again from there, so call `generate_spots` first to keep
+/// the same spots throughout.
+///
+/// Pickling saves the simulation's whole state, so an unpickled simulation draws the same
+/// random spots and noise as the original would have.
+#[pyclass(name = "Simulation", module = "lather.native", frozen)]
+pub struct PySimulation {
+    sim: RwLock<Simulation>,
+}
+
+impl From<Simulation> for PySimulation {
+    fn from(sim: Simulation) -> PySimulation {
+        PySimulation {
+            sim: RwLock::new(sim),
+        }
+    }
+}
+
+impl PySimulation {
+    /// Reads the simulation. Locks are only taken with the GIL released, so a thread
+    /// waiting for one never blocks the thread holding it.
+    fn read<T: Send>(&self, py: Python, f: impl FnOnce(&Simulation) -> T + Send) -> T {
+        py.detach(|| f(&self.sim.read().unwrap_or_else(|e| e.into_inner())))
+    }
+
+    /// Changes the simulation, once observations in progress have finished.
+    fn write<T: Send>(&self, py: Python, f: impl FnOnce(&mut Simulation) -> T + Send) -> T {
+        py.detach(|| f(&mut self.sim.write().unwrap_or_else(|e| e.into_inner())))
+    }
+
+    /// Generates any random spots needed at `time`, then observes. The spots can't change
+    /// in between, since the same lock is held for both. Instrument noise is added inside
+    /// `f` too, so the seed can't be changed between observing and drawing the noise.
+    fn observe<T: Send>(
+        &self,
+        py: Python,
+        time: &[f64],
+        f: impl FnOnce(&Simulation) -> Result<T, LatherError> + Send,
+    ) -> Result<T, LatherError> {
+        py.detach(|| {
+            let sim = self.sim.read().unwrap_or_else(|e| e.into_inner());
+            if sim.spots_generated(time) {
+                return f(&sim);
+            }
+            drop(sim);
+            let mut sim = self.sim.write().unwrap_or_else(|e| e.into_inner());
+            sim.ensure_spots(time)?;
+            f(&sim)
+        })
+    }
+}
+
+#[pymethods]
+impl PySimulation {
+    /// Builds a simulation from a config file.
+    #[new]
+    fn new(py: Python, path: PathBuf) -> PyResult<PySimulation> {
+        Ok(py.detach(|| Simulation::from_config(&path).map(PySimulation::from))?)
+    }
+
+    /// Builds a simulation from the contents of a config file. Relative paths in it are
+    /// relative to the working directory.
+    #[staticmethod]
+    fn from_toml(py: Python, contents: &str) -> PyResult<PySimulation> {
+        Ok(py.detach(|| Simulation::from_toml_str(contents).map(PySimulation::from))?)
+    }
+
+    /// Builds a simulation of a star with no spots.
+    #[staticmethod]
+    #[pyo3(signature = (star, seed=None))]
+    fn from_star(star: &PyStar, seed: Option<u64>) -> PySimulation {
+        // The star is shared rather than integrated again
+        Simulation::with_star(Arc::clone(&star.star), seed.unwrap_or(DEFAULT_SEED)).into()
+    }
+
+    /// Resumes a simulation from a file written by `save_state`.
+    #[staticmethod]
+    fn load_state(py: Python, path: PathBuf) -> PyResult<PySimulation> {
+        Ok(py.detach(|| Simulation::load_state(&path).map(PySimulation::from))?)
+    }
+
+    /// Saves everything about the simulation to a TOML file, including its random spots
+    /// and how far its random number generators have got.
+    ///
+    /// `load_state` resumes from it exactly where this simulation is.
+    fn save_state(&self, py: Python, path: PathBuf) -> PyResult<()> {
+        Ok(self.read(py, |sim| sim.save_state(&path))?)
+    }
+
+    #[getter]
+    fn star(&self, py: Python) -> PyStar {
+        self.read(py, |sim| PyStar {
+            star: Arc::clone(&sim.star),
+        })
+    }
+
+    /// Every spot on the star, including generated spots
+    #[getter]
+    fn spots(&self, py: Python) -> Vec<PySpot> {
+        self.read(py, |sim| sim.spots.iter().map(PySpot::from).collect())
+    }
+
+    /// The seed random spots and noise are drawn from
+    #[getter]
+    fn seed(&self, py: Python) -> u64 {
+        self.read(py, |sim| sim.seed())
+    }
+
+    fn __repr__(&self, py: Python) -> String {
+        self.read(py, |sim| format!("{:?}", sim))
+    }
+
+    /// Generates random spots between the times `start` and `end` in days.
+    ///
+    /// Any previously generated spots are replaced. Observing any time in this window
+    /// sees the same star, regardless of what else has been observed.
+    fn generate_spots(&self, py: Python, start: f64, end: f64) -> PyResult<()> {
+        self.write(py, |sim| sim.generate_spots(Bounds::new(start, end)))
+            .map_err(|e| LatherError::Generation(e).into())
+    }
+
+    /// Adds a spot at the latitude and longitude in degrees. `lifetime` is a
+    /// `(start, end)` pair of times in days, and the spot always exists without one.
+    #[pyo3(signature = (latitude, longitude, fill_factor, temperature=None, lifetime=None, plage=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_spot(
+        &self,
+        py: Python,
+        latitude: f64,
+        longitude: f64,
+        fill_factor: f64,
+        temperature: Option<f64>,
+        lifetime: Option<(f64, f64)>,
+        plage: bool,
+    ) -> PyResult<()> {
+        let spot = SpotConfig {
+            latitude,
+            longitude,
+            fill_factor,
+            plage,
+            temperature,
+            lifetime: lifetime.map(|(start, end)| Lifetime { start, end }),
+        };
+        self.write(py, |sim| sim.add_spot(&spot))?;
+        Ok(())
+    }
+
+    /// Removes every spot, including generated spots.
+    fn clear_spots(&self, py: Python) {
+        self.write(py, |sim| sim.clear_spots())
+    }
+
+    /// Sets the length of each exposure in days.
+    ///
+    /// Each observation is averaged over `sub_exposures` instantaneous observations, or
+    /// if that is None, over enough that the star rotates by at most `max_phase_step` of
+    /// a rotation between them.
+    #[pyo3(signature = (duration, sub_exposures=None, max_phase_step=1e-3))]
+    fn set_exposure(
+        &self,
+        py: Python,
+        duration: f64,
+        sub_exposures: Option<usize>,
+        max_phase_step: f64,
+    ) -> PyResult<()> {
+        let config = ExposureConfig {
+            duration,
+            supersample: Some(match sub_exposures {
+                Some(count) => SupersampleConfig::Fixed { count },
+                None => SupersampleConfig::Adaptive { max_phase_step },
+            }),
+        };
+        let exposure = Exposure::from_config(&config)?;
+        self.write(py, |sim| sim.exposure = exposure);
+        Ok(())
+    }
+
+    /// Precomputes immortal spots at `samples` phases of a rotation.
+    ///
+    /// Observations then interpolate between phases instead of integrating each spot at
+    /// every time, which makes long time series much cheaper. Pass None to integrate at
+    /// every time again.
+    #[pyo3(signature = (samples=Some(1000)))]
+    fn set_phase_grid(&self, py: Python, samples: Option<usize>) -> PyResult<()> {
+        if let Some(samples) = samples {
+            phase_grid::check_samples(samples)?;
+        }
+        self.write(py, |sim| sim.set_phase_grid(samples));
+        Ok(())
+    }
+
+    /// Chooses each spot's strip spacing to meet discretization error tolerances.
+    ///
+    /// `flux` is in relative flux and `rv` in m/s. With neither, spots are integrated on
+    /// the star's grid again.
+    #[pyo3(signature = (flux=None, rv=None))]
+    fn set_tolerance(&self, py: Python, flux: Option<f64>, rv: Option<f64>) -> PyResult<()> {
+        let config = ResolutionConfig {
+            flux_tolerance: flux,
+            rv_tolerance: rv,
+        };
+        let tolerance = match (flux, rv) {
+            (None, None) => None,
+            _ => Some(Tolerance::from_config(&config)?),
+        };
+        self.write(py, |sim| sim.set_tolerance(tolerance));
+        Ok(())
+    }
+
+    /// Generates observation times in days from the observing schedule in the config.
+    ///
+    /// Returns None if the config has no schedule.
+    fn schedule<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray1<f64>>> {
+        self.read(py, |sim| sim.scheduled_times())
+            .map(|times| times.into_pyarray(py))
+    }
+
+    /// Observes the relative flux at each time in the wavelength band, in meters.
+    ///
+    /// If `instrument` names an instrument in the config, its noise is added.
+    #[pyo3(signature = (time, wave_start, wave_end, instrument=None))]
+    fn observe_flux<'py>(
+        &self,
+        py: Python<'py>,
+        time: PyReadonlyArray1<f64>,
+        wave_start: f64,
+        wave_end: f64,
+        instrument: Option<&str>,
+    ) -> PyResult<Bound<'py, PyArray1<f64>>> {
+        let time = time.as_slice()?;
+        let flux = self.observe(py, time, |sim| {
+            let mut flux = sim.observe_flux(time, Bounds::new(wave_start, wave_end))?;
+            if let Some(instrument) = instrument {
+                sim.apply_flux_noise(instrument, time, &mut flux)?;
+            }
+            Ok(flux)
+        })?;
+        Ok(flux.into_pyarray(py))
+    }
+
+    /// Observes the CCF at each time in the wavelength band, in meters, one row per time.
+    ///
+    /// If `instrument` names an instrument in the config, its noise is added.
+    #[pyo3(signature = (time, wave_start, wave_end, instrument=None))]
+    fn observe_rv<'py>(
+        &self,
+        py: Python<'py>,
+        time: PyReadonlyArray1<f64>,
+        wave_start: f64,
+        wave_end: f64,
+        instrument: Option<&str>,
+    ) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        let time = time.as_slice()?;
+        let (ccfs, width) = self.observe(py, time, |sim| {
+            let mut ccfs = sim.observe_rv(time, Bounds::new(wave_start, wave_end))?;
+            if let Some(instrument) = instrument {
+                sim.apply_ccf_noise(instrument, time, &mut ccfs)?;
+            }
+            Ok((ccfs, sim.star.profile_quiet.len()))
+        })?;
+        rows_to_pyarray(py, ccfs, width)
+    }
+
+    /// Observes the relative flux at each time in several wavelength bands at once.
+    ///
+    /// Returns an array with one row per time and one column per band.
+    fn observe_flux_bands<'py>(
+        &self,
+        py: Python<'py>,
+        time: PyReadonlyArray1<f64>,
+        wave_starts: Vec<f64>,
+        wave_ends: Vec<f64>,
+    ) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        let time = time.as_slice()?;
+        let bands = bands(&wave_starts, &wave_ends)?;
+        let flux = self.observe(py, time, |sim| sim.observe_flux_bands(time, &bands))?;
+        rows_to_pyarray(py, flux, bands.len())
+    }
+
+    /// Observes the CCF at each time in several wavelength bands at once.
+    ///
+    /// Returns an array indexed by time, then band, then RV.
+    fn observe_rv_bands<'py>(
+        &self,
+        py: Python<'py>,
+        time: PyReadonlyArray1<f64>,
+        wave_starts: Vec<f64>,
+        wave_ends: Vec<f64>,
+    ) -> PyResult<Bound<'py, PyArray3<f64>>> {
+        let time = time.as_slice()?;
+        let bands = bands(&wave_starts, &wave_ends)?;
+        let (ccfs, width) = self.observe(py, time, |sim| {
+            Ok((
+                sim.observe_rv_bands(time, &bands)?,
+                sim.star.profile_quiet.len(),
+            ))
+        })?;
+        let flat: Vec<f64> = ccfs.into_iter().flatten().flatten().collect();
+        flat.into_pyarray(py)
+            .reshape([time.len(), bands.len(), width])
+    }
+
+    /// Observes the CCF of each line group at each time in the wavelength band.
+    ///
+    /// Returns a dict mapping each group's name to its radial velocities and an array of
+    /// CCFs, one row per time.
+    fn observe_line_groups<'py>(
+        &self,
+        py: Python<'py>,
+        time: PyReadonlyArray1<f64>,
+        wave_start: f64,
+        wave_end: f64,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let time = time.as_slice()?;
+        let (groups, ccfs) = self.observe(py, time, |sim| {
+            let groups: Vec<(String, Vec<f64>)> = sim
+                .star
+                .line_groups
+                .iter()
+                .map(|group| (group.name.clone(), group.profile_quiet.rv.clone()))
+                .collect();
+            Ok((
+                groups,
+                sim.observe_line_groups(time, Bounds::new(wave_start, wave_end))?,
+            ))
+        })?;
+
+        let observed = PyDict::new(py);
+        for (group, (name, rv)) in groups.into_iter().enumerate() {
+            let width = rv.len();
+            let group_ccfs = ccfs.iter().map(|groups| groups[group].clone()).collect();
+            observed.set_item(
+                name,
+                (rv.into_pyarray(py), rows_to_pyarray(py, group_ccfs, width)?),
+            )?;
+        }
+        Ok(observed)
+    }
+
+    /// The wavelengths (m) spectra are observed at, or None without template spectra
+    #[getter]
+    fn spectrum_wavelength<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray1<f64>>> {
+        self.read(py, |sim| sim.spectrum_wavelength().map(<[f64]>::to_vec))
+            .map(|wavelength| wavelength.into_pyarray(py))
+    }
+
+    /// Observes the disk-integrated spectrum at each time, one row per time.
+    fn observe_spectrum<'py>(
+        &self,
+        py: Python<'py>,
+        time: PyReadonlyArray1<f64>,
+    ) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        let time = time.as_slice()?;
+        let (spectra, width) = self.observe(py, time, |sim| {
+            let spectra = sim.observe_spectrum(time)?;
+            let width = sim.spectrum_wavelength().map_or(0, <[f64]>::len);
+            Ok((spectra, width))
+        })?;
+        rows_to_pyarray(py, spectra, width)
+    }
+
+    /// Observes the flux, with estimates of the discretization errors.
+    ///
+    /// Returns the flux at each time, its estimated error, and the estimated error in RV
+    /// (m/s) of the CCF at each time.
+    #[allow(clippy::type_complexity)]
+    fn estimate_errors<'py>(
+        &self,
+        py: Python<'py>,
+        time: PyReadonlyArray1<f64>,
+        wave_start: f64,
+        wave_end: f64,
+    ) -> PyResult<(
+        Bound<'py, PyArray1<f64>>,
+        Bound<'py, PyArray1<f64>>,
+        Bound<'py, PyArray1<f64>>,
+    )> {
+        let time = time.as_slice()?;
+        let errors = self.observe(py, time, |sim| {
+            sim.estimate_errors(time, Bounds::new(wave_start, wave_end))
+        })?;
+        Ok((
+            errors.flux.into_pyarray(py),
+            errors.flux_error.into_pyarray(py),
+            errors.rv_error.into_pyarray(py),
+        ))
+    }
+
+    /// Observes the relative flux at each time in the band, keeping the times and band.
+    fn light_curve(
+        &self,
+        py: Python,
+        time: PyReadonlyArray1<f64>,
+        wave_start: f64,
+        wave_end: f64,
+    ) -> PyResult<PyLightCurve> {
+        let band = Bounds::new(wave_start, wave_end);
+        let time = time.as_slice()?;
+        let curve = self.observe(py, time, |sim| sim.light_curve(time, band))?;
+        Ok(PyLightCurve {
+            time: curve.time.into_pyarray(py).unbind(),
+            band: (band.lower, band.upper),
+            flux: curve.flux.into_pyarray(py).unbind(),
+        })
+    }
+
+    /// Observes the CCF at each time in the band, keeping the times, band, and the
+    /// unspotted star's CCF.
+    fn ccf_series(
+        &self,
+        py: Python,
+        time: PyReadonlyArray1<f64>,
+        wave_start: f64,
+        wave_end: f64,
+    ) -> PyResult<PyCcfSeries> {
+        let band = Bounds::new(wave_start, wave_end);
+        let time = time.as_slice()?;
+        let series = self.observe(py, time, |sim| sim.ccf_series(time, band))?;
+        let width = series.rv.len();
+        Ok(PyCcfSeries {
+            time: series.time.into_pyarray(py).unbind(),
+            band: (band.lower, band.upper),
+            rv: series.rv.into_pyarray(py).unbind(),
+            ccfs: rows_to_pyarray(py, series.ccfs, width)?.unbind(),
+            quiet_ccf: series.quiet_ccf.into_pyarray(py).unbind(),
+        })
+    }
+
+    /// Draws the star at `time` as a BGR image of `IMAGE_SIZE` by `IMAGE_SIZE` pixels,
+    /// into `out` if it is given.
+    #[pyo3(signature = (time, out=None))]
+    fn draw_bgr<'py>(
+        &self,
+        py: Python<'py>,
+        time: f64,
+        out: Option<Bound<'py, PyArray3<u8>>>,
+    ) -> PyResult<Bound<'py, PyArray3<u8>>> {
+        let shape = [IMAGE_SIZE, IMAGE_SIZE, 3];
+        let image = match out {
+            Some(out) if out.shape() != shape => {
+                return Err(LatherError::Validation(format!(
+                    "The image must have shape {:?}, but has {:?}",
+                    shape,
+                    out.shape()
+                ))
+                .into())
+            }
+            Some(out) => out,
+            None => PyArray3::zeros(py, shape, false),
+        };
+        {
+            let mut pixels = image.readwrite();
+            let pixels = pixels.as_slice_mut()?;
+            self.observe(py, &[time], |sim| sim.draw_bgr(time, pixels))?;
+        }
+        Ok(image)
+    }
+
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(Bound<'py, PyAny>, (String,))> {
+        let state = self.read(py, |sim| sim.state().to_toml());
+        Ok((
+            py.get_type::<PySimulation>().getattr("_from_state")?,
+            (state,),
+        ))
+    }
+
+    /// Rebuilds a pickled simulation.
+    #[staticmethod]
+    fn _from_state(py: Python, state: &str) -> PyResult<PySimulation> {
+        Ok(py.detach(|| {
+            SimulationState::from_toml_str(state)
+                .and_then(Simulation::from_state)
+                .map(PySimulation::from)
+        })?)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "native")]
+fn native_module(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_class::<PyStar>()?;
+    m.add_class::<PySpot>()?;
+    m.add_class::<PySimulation>()?;
+    m.add_class::<PyLightCurve>()?;
+    m.add_class::<PyCcfSeries>()?;
+    m.add("IMAGE_SIZE", IMAGE_SIZE)?;
+    m.add("RV_FOR_CCFS", PyArray1::from_slice(m.py(), &RV))?;
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+/// The random number generator used for everything seeded from a config. ChaCha20 is a
+/// fixed algorithm, and `from_seed` is a fixed mapping from seed to state, so a seed
+/// produces the same numbers on every platform and with every version of this crate.
+/// `StdRng` makes no such promise across versions of `rand`.
+pub type Generator = ChaCha20Rng;
+
+/// Each source of randomness draws from its own ChaCha stream, so adding draws to one
+/// (e.g. a new spot property, or an extra size redraw) doesn't change the others.
+/// The discriminants select the stream and must never be changed or reused.
+#[derive(Clone, Copy, Debug)]
+pub enum Stream {
+    SpotSizes = 1,
+    SpotPositions = 2,
+    SpotLifetimes = 3,
+    Noise = 4,
+    Schedule = 5,
+}
+
+/// Creates the generator for one purpose from a seed.
+pub fn stream(seed: u64, stream: Stream) -> Generator {
+    let mut key = [0; 32];
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    let mut generator = Generator::from_seed(key);
+    generator.set_stream(stream as u64);
+    // Seeking fills the buffer, without which `get_word_pos` overflows in rand_chacha 0.2
+    generator.set_word_pos(0);
+    generator
+}
+
+/// Creates the generator for one purpose from a seed, `position` words into its stream,
+/// where `Generator::get_word_pos` said a generator had got to.
+pub fn stream_at(seed: u64, stream: Stream, position: u128) -> Generator {
+    let mut generator = self::stream(seed, stream);
+    generator.set_word_pos(position);
+    generator
+}
+
+/// The generators used to create random spots.
+#[derive(Clone, Debug)]
+pub struct SpotGenerators {
+    pub sizes: Generator,
+    pub positions: Generator,
+    pub lifetimes: Generator,
+}
+
+impl SpotGenerators {
+    pub fn new(seed: u64) -> SpotGenerators {
+        SpotGenerators {
+            sizes: stream(seed, Stream::SpotSizes),
+            positions: stream(seed, Stream::SpotPositions),
+            lifetimes: stream(seed, Stream::SpotLifetimes),
+        }
+    }
+
+    /// Where each generator has got to in its stream, as sizes, positions, and lifetimes.
+    pub fn positions(&self) -> [u128; 3] {
+        [
+            self.sizes.get_word_pos(),
+            self.positions.get_word_pos(),
+            self.lifetimes.get_word_pos(),
+        ]
+    }
+
+    /// Recreates the generators from a seed at `positions` in their streams.
+    pub fn at(seed: u64, positions: [u128; 3]) -> SpotGenerators {
+        SpotGenerators {
+            sizes: stream_at(seed, Stream::SpotSizes, positions[0]),
+            positions: stream_at(seed, Stream::SpotPositions, positions[1]),
+            lifetimes: stream_at(seed, Stream::SpotLifetimes, positions[2]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn streams_are_stable() {
+        // If this changes, every seeded config in existence produces a different star
+        let mut generator = stream(0x0123456789ABCDEF, Stream::SpotPositions);
+        assert_eq!(generator.gen::<u64>(), 421217942479609032);
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        let mut sizes = stream(1, Stream::SpotSizes);
+        let mut positions = stream(1, Stream::SpotPositions);
+        assert_ne!(sizes.gen::<u64>(), positions.gen::<u64>());
+        assert_ne!(
+            stream(1, Stream::Noise).gen::<u64>(),
+            stream(2, Stream::Noise).gen::<u64>()
+        );
+    }
+
+    #[test]
+    fn streams_resume_where_they_left_off() {
+        let mut generator = stream(7, Stream::Noise);
+        // Stop partway through a block, and partway through a u64
+        for _ in 0..21 {
+            generator.gen::<u32>();
+        }
+        let mut resumed = stream_at(7, Stream::Noise, generator.get_word_pos());
+        let expected: Vec<u64> = (0..100).map(|_| generator.gen()).collect();
+        let actual: Vec<u64> = (0..100).map(|_| resumed.gen()).collect();
+        assert_eq!(actual, expected);
+    }
+}
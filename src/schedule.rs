@@ -0,0 +1,241 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::error::LatherError;
+use crate::rng::{self, Stream};
+
+/// The Julian date of the J2000.0 epoch
+const J2000: f64 = 2_451_545.0;
+/// Spacing of the grid used to find when the target is observable, in days (5 minutes)
+const GRID_STEP: f64 = 5.0 / (24.0 * 60.0);
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ScheduleConfig {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub ra: f64,
+    pub dec: f64,
+    pub start: f64,
+    pub nights: usize,
+    pub max_airmass: Option<f64>,
+    pub max_sun_altitude: Option<f64>,
+    pub visits_per_night: Option<usize>,
+    pub weather_loss: Option<f64>,
+    pub seed: Option<u64>,
+}
+
+/// Generates realistic observation times of a target from a ground-based site.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Schedule {
+    /// Geodetic latitude of the site in radians
+    pub latitude: f64,
+    /// Longitude of the site in radians, positive east
+    pub longitude: f64,
+    /// Right ascension of the target in radians
+    pub ra: f64,
+    /// Declination of the target in radians
+    pub dec: f64,
+    /// Julian date the campaign starts at
+    pub start: f64,
+    pub nights: usize,
+    pub max_airmass: f64,
+    /// The sun must be below this altitude in radians, -12 degrees is nautical twilight
+    pub max_sun_altitude: f64,
+    pub visits_per_night: usize,
+    /// Probability that any one night is lost to weather
+    pub weather_loss: f64,
+    pub seed: u64,
+}
+
+impl Schedule {
+    pub fn from_config(config: &ScheduleConfig) -> Result<Schedule, LatherError> {
+        if config.latitude.abs() > 90.0 || config.dec.abs() > 90.0 {
+            return Err(LatherError::Validation(
+                "The site latitude and target declination must be between -90 and 90 degrees"
+                    .to_string(),
+            ));
+        }
+        let max_airmass = config.max_airmass.unwrap_or(2.0);
+        if max_airmass.is_nan() || max_airmass < 1.0 {
+            return Err(LatherError::Validation(format!(
+                "The airmass limit must be at least 1, but is {}",
+                max_airmass
+            )));
+        }
+        let weather_loss = config.weather_loss.unwrap_or(0.0);
+        if !(0.0..=1.0).contains(&weather_loss) {
+            return Err(LatherError::Validation(format!(
+                "The weather loss is a probability between 0 and 1, but is {}",
+                weather_loss
+            )));
+        }
+        Ok(Schedule {
+            latitude: config.latitude.to_radians(),
+            longitude: config.longitude.to_radians(),
+            ra: config.ra.to_radians(),
+            dec: config.dec.to_radians(),
+            start: config.start,
+            nights: config.nights,
+            max_airmass,
+            max_sun_altitude: config.max_sun_altitude.unwrap_or(-12.0).to_radians(),
+            visits_per_night: config.visits_per_night.unwrap_or(1),
+            weather_loss,
+            seed: config.seed.unwrap_or(0x0123456789ABCDEFu64),
+        })
+    }
+
+    /// Computes the observation times in days since `start`, in increasing order.
+    /// Each clear night with an observable window gets `visits_per_night` visits, one at a
+    /// random time in each equal part of the window. Windows too short for that are skipped.
+    pub fn times(&self) -> Vec<f64> {
+        let mut rng = rng::stream(self.seed, Stream::Schedule);
+        let mut times = Vec::new();
+
+        for (first, last) in self.windows() {
+            if rng.gen::<f64>() < self.weather_loss {
+                continue;
+            }
+            let visit_length = (last - first) / self.visits_per_night as f64;
+            if visit_length < GRID_STEP {
+                // Too short to fit the visits in
+                continue;
+            }
+            for visit in 0..self.visits_per_night {
+                let time = first + (visit as f64 + rng.gen::<f64>()) * visit_length;
+                times.push(time - self.start);
+            }
+        }
+
+        times
+    }
+
+    /// Whether the target can be observed at the Julian date `jd`.
+    pub fn observable(&self, jd: f64) -> bool {
+        let (sun_ra, sun_dec) = sun_position(jd);
+        if self.altitude(jd, sun_ra, sun_dec) > self.max_sun_altitude {
+            return false;
+        }
+        let altitude = self.altitude(jd, self.ra, self.dec);
+        altitude > 0.0 && 1.0 / altitude.sin() <= self.max_airmass
+    }
+
+    /// Finds the first and last observable Julian date of each night of the campaign.
+    fn windows(&self) -> Vec<(f64, f64)> {
+        // Nights are divided at local noon, which is when the Julian date (local solar
+        // time) rolls over
+        let night_of = |jd: f64| (jd + self.longitude / std::f64::consts::TAU).floor() as i64;
+
+        let steps = (self.nights as f64 / GRID_STEP).ceil() as usize;
+        let mut windows: Vec<(i64, f64, f64)> = Vec::new();
+        for jd in (0..=steps).map(|i| self.start + i as f64 * GRID_STEP) {
+            if !self.observable(jd) {
+                continue;
+            }
+            let night = night_of(jd);
+            match windows.last_mut() {
+                Some((last_night, _, last)) if *last_night == night => *last = jd,
+                _ => windows.push((night, jd, jd)),
+            }
+        }
+
+        windows
+            .into_iter()
+            .map(|(_, first, last)| (first, last))
+            .collect()
+    }
+
+    /// The altitude in radians of an object at the provided equatorial coordinates.
+    fn altitude(&self, jd: f64, ra: f64, dec: f64) -> f64 {
+        let hour_angle = sidereal_time(jd) + self.longitude - ra;
+        (self.latitude.sin() * dec.sin() + self.latitude.cos() * dec.cos() * hour_angle.cos())
+            .asin()
+    }
+}
+
+/// Greenwich mean sidereal time in radians at the Julian date `jd`.
+fn sidereal_time(jd: f64) -> f64 {
+    let hours = 18.697_374_558 + 24.065_709_824_419_08 * (jd - J2000);
+    (hours.rem_euclid(24.0) * 15.0).to_radians()
+}
+
+/// The right ascension and declination of the sun in radians at the Julian date `jd`,
+/// from the low-precision formulae of the Astronomical Almanac (accurate to 0.01 degrees).
+fn sun_position(jd: f64) -> (f64, f64) {
+    let n = jd - J2000;
+    let mean_longitude = 280.460 + 0.985_647_4 * n;
+    let mean_anomaly = (357.528 + 0.985_600_3 * n).to_radians();
+    let ecliptic_longitude =
+        (mean_longitude + 1.915 * mean_anomaly.sin() + 0.020 * (2.0 * mean_anomaly).sin())
+            .to_radians();
+    let obliquity = (23.439 - 0.000_000_4 * n).to_radians();
+
+    let ra = (obliquity.cos() * ecliptic_longitude.sin()).atan2(ecliptic_longitude.cos());
+    let dec = (obliquity.sin() * ecliptic_longitude.sin()).asin();
+    (ra, dec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn la_silla() -> ScheduleConfig {
+        ScheduleConfig {
+            latitude: -29.26,
+            longitude: -70.73,
+            ra: 101.29, // Sirius
+            dec: -16.72,
+            start: 2_459_580.5, // 2022 January 1
+            nights: 365,
+            max_airmass: Some(1.5),
+            max_sun_altitude: None,
+            visits_per_night: Some(2),
+            weather_loss: Some(0.2),
+            seed: Some(42),
+        }
+    }
+
+    #[test]
+    fn sun_at_june_solstice() {
+        // 2000 June 21 01:48 UT
+        let (_, dec) = sun_position(2_451_716.575);
+        assert!((dec.to_degrees() - 23.44).abs() < 0.01);
+    }
+
+    #[test]
+    fn times_are_observable() {
+        let schedule = Schedule::from_config(&la_silla()).unwrap();
+        let times = schedule.times();
+        assert!(!times.is_empty());
+        assert!(times.windows(2).all(|t| t[0] < t[1]));
+        for t in &times {
+            assert!(schedule.observable(schedule.start + t));
+        }
+        assert_eq!(times, schedule.times());
+    }
+
+    #[test]
+    fn seasonal_gap() {
+        let schedule = Schedule::from_config(&la_silla()).unwrap();
+        let times = schedule.times();
+        // Sirius is behind the sun in late June
+        assert!(!times.iter().any(|t| (170.0..190.0).contains(t)));
+        // and up all night in early January
+        assert!(times.iter().any(|t| *t < 10.0));
+    }
+
+    #[test]
+    fn weather_loses_nights() {
+        let clear = Schedule::from_config(&ScheduleConfig {
+            weather_loss: None,
+            ..la_silla()
+        })
+        .unwrap();
+        let cloudy = Schedule::from_config(&ScheduleConfig {
+            weather_loss: Some(1.0),
+            ..la_silla()
+        })
+        .unwrap();
+        assert!(cloudy.times().is_empty());
+        assert!(clear.times().len() > Schedule::from_config(&la_silla()).unwrap().times().len());
+    }
+}
@@ -1,24 +1,74 @@
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
-use rand::prelude::*;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::boundingshape::BoundingShape;
 use crate::bounds::Bounds;
+use crate::builder::{SimulationBuilder, StarBuilder};
+use crate::catalog::{CatalogConfig, SpotCatalog};
+use crate::convergence::{choose_strip_step, DiscretizationError, ResolutionConfig, Tolerance};
+use crate::error::LatherError;
+use crate::exposure::{Exposure, ExposureConfig};
+use crate::generation::{GenerationError, GenerationFailure};
 use crate::linspace::floatrange;
-use crate::planck::planck_integral;
-use crate::solar_ccfs::CCF_LEN;
+use crate::noise::{Instrument, InstrumentConfig};
+use crate::observation::{CcfSeries, LightCurve};
+use crate::phase_grid::{self, PhaseGrid, PhaseGridConfig};
+use crate::profile::Profile;
+use crate::rng::{self, Generator, SpotGenerators, Stream};
+use crate::schedule::{Schedule, ScheduleConfig};
+use crate::spot::Lifetime;
 use crate::spot::Mortality::Mortal;
-use crate::spot::{Spot, SpotConfig};
+use crate::spot::{Mortality, Spot, SpotConfig};
 use crate::star::{Star, StarConfig};
+use crate::state::{GeneratorPositions, SimulationState, SpotState, SurfaceState, STATE_FORMAT};
+use crate::surface::{SurfaceConfig, SurfaceMap};
+
+/// Random spots are born at times which are multiples of this many days after the start
+/// of the generated window.
+const GENERATION_STEP: f64 = 0.1;
+
+pub(crate) const DEFAULT_SEED: u64 = 0x0123456789ABCDEFu64;
+
+/// The brightness of surface features relative to the quiet photosphere in one band.
+struct BandIntensities {
+    spots: Vec<f64>,
+    catalog: f64,
+    catalog_plage: f64,
+    surface: Vec<Vec<f64>>,
+}
+
+impl BandIntensities {
+    /// The intensity of a catalog region, which is a spot or a plage.
+    fn catalog(&self, plage: bool) -> f64 {
+        if plage {
+            self.catalog_plage
+        } else {
+            self.catalog
+        }
+    }
+}
 
 /// A model of a star with spots that can be observed.
 pub struct Simulation {
     pub star: Arc<Star>,
     pub spots: Vec<Spot>,
-    generator: Arc<Mutex<StdRng>>,
+    pub surface: Option<SurfaceMap>,
+    pub catalog: Option<SpotCatalog>,
+    pub exposure: Exposure,
+    pub instruments: Vec<Instrument>,
+    pub schedule: Option<Schedule>,
+    /// The number of phases immortal spots are precomputed at, if they are
+    phase_grid_samples: Option<usize>,
+    /// The discretization errors spot strips are chosen for, if they are
+    tolerance: Option<Tolerance>,
+    seed: u64,
+    /// The window over which random spots have been generated so far
+    generated: Option<Bounds>,
+    spot_generators: SpotGenerators,
+    noise_generator: Arc<Mutex<Generator>>,
 }
 
 impl std::fmt::Debug for Simulation {
@@ -26,28 +76,74 @@ impl std::fmt::Debug for Simulation {
         f.debug_struct("Simulation")
             .field("star", &self.star)
             .field("spots", &self.spots)
+            .field("surface", &self.surface)
+            .field("catalog", &self.catalog)
+            .field("exposure", &self.exposure)
+            .field("instruments", &self.instruments)
+            .field("schedule", &self.schedule)
+            .field("phase_grid_samples", &self.phase_grid_samples)
+            .field("tolerance", &self.tolerance)
+            .field("seed", &self.seed)
+            .field("generated", &self.generated)
             .finish()
     }
 }
 
-#[derive(Deserialize, Serialize)]
+/// How the simulation's RNG is seeded, either with a number or with `"entropy"` to
+/// draw a seed from the system.
+#[derive(Deserialize, Serialize, Clone)]
 #[serde(untagged)]
-enum SeedConfig {
+pub enum SeedConfig {
     Number(u64),
     Text(String),
 }
 
-#[derive(Deserialize, Serialize)]
-struct Config {
-    seed: Option<SeedConfig>,
-    star: StarConfig,
-    spots: Option<Vec<SpotConfig>>,
+/// The contents of a simulation config file.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SimulationConfig {
+    pub seed: Option<SeedConfig>,
+    pub star: StarConfig,
+    pub spots: Option<Vec<SpotConfig>>,
+    pub surface: Option<SurfaceConfig>,
+    pub catalog: Option<CatalogConfig>,
+    pub exposure: Option<ExposureConfig>,
+    pub instruments: Option<Vec<InstrumentConfig>>,
+    pub schedule: Option<ScheduleConfig>,
+    pub phase_grid: Option<PhaseGridConfig>,
+    pub resolution: Option<ResolutionConfig>,
 }
 
-impl Config {
+impl SimulationConfig {
+    /// Reads a TOML config file. Relative paths in it are still relative to the file.
+    pub(crate) fn read(config_path: &Path) -> Result<SimulationConfig, LatherError> {
+        let contents =
+            std::fs::read_to_string(config_path).map_err(|e| LatherError::io(config_path, e))?;
+        SimulationConfig::parse(&contents)
+            .map_err(|message| LatherError::parse(config_path, message))
+    }
+
+    /// Writes the config as TOML, which `Simulation::from_toml_str` reads back.
+    pub fn to_toml(&self) -> String {
+        // Going through a Value puts tables after plain values, which TOML requires
+        ::toml::Value::try_from(self)
+            .and_then(|value| ::toml::to_string(&value))
+            .expect("Every config can be written as TOML")
+    }
+
+    /// Parses a TOML config, or explains what is wrong with it.
+    pub(crate) fn parse(contents: &str) -> Result<SimulationConfig, String> {
+        ::toml::from_str(contents).map_err(|e| {
+            format!(
+                "Here's an example of a valid config:\n{}\nThe internal error was: {}",
+                ::toml::to_string_pretty(&SimulationConfig::example()).unwrap(),
+                e
+            )
+        })
+    }
+
     // This is only used to produce our pretty example if somebody feeds in a bad config
-    fn example() -> Config {
-        Config {
+    fn example() -> SimulationConfig {
+        SimulationConfig {
             seed: None,
             star: StarConfig {
                 grid_size: 1000,
@@ -60,10 +156,19 @@ impl Config {
                 limb_quadratic: 0.34,
                 target_fill_factor: Some(0.00),
                 minimum_fill_factor: None,
+                max_size_draws: None,
+                max_placement_attempts: None,
                 latitude_distribution: None,
                 longitude_distribution: None,
                 fillfactor_distribution: None,
+                spot_size: None,
                 lifetime_distribution: None,
+                spectral_model: None,
+                synthesis: None,
+                line_groups: None,
+                cache_dir: None,
+                interpolation: None,
+                relativistic_doppler: None,
             },
             spots: Some(vec![
                 SpotConfig {
@@ -83,6 +188,13 @@ impl Config {
                     lifetime: None,
                 },
             ]),
+            surface: None,
+            catalog: None,
+            exposure: None,
+            instruments: None,
+            schedule: None,
+            phase_grid: None,
+            resolution: None,
         }
     }
 }
@@ -90,113 +202,618 @@ impl Config {
 impl Simulation {
     /// Construct the simulation used in tests
     pub fn sun() -> Simulation {
+        Simulation::with_star(
+            Arc::new(StarBuilder::new().target_fill_factor(0.01).build().unwrap()),
+            DEFAULT_SEED,
+        )
+    }
+
+    /// Construct a simulation of an existing star with no spots. Simulations sharing a
+    /// star share its integrated quiet profiles, so an ensemble of spot populations only
+    /// needs to build the star once.
+    pub fn with_star(star: Arc<Star>, seed: u64) -> Simulation {
         Simulation {
-            star: Arc::new(Star::from_config(&StarConfig {
-                grid_size: 1000,
-                radius: 1.0,
-                period: 25.05,
-                inclination: 90.0,
-                temperature: 5778.0,
-                spot_temp_diff: 663.0,
-                limb_linear: 0.29,
-                limb_quadratic: 0.34,
-                target_fill_factor: Some(0.01),
-                minimum_fill_factor: None,
-                latitude_distribution: None,
-                longitude_distribution: None,
-                fillfactor_distribution: None,
-                lifetime_distribution: None,
-            })),
+            star,
             spots: Vec::new(),
-            generator: Arc::new(Mutex::new(StdRng::seed_from_u64(0x0123456789ABCDEFu64))),
+            surface: None,
+            catalog: None,
+            exposure: Exposure::default(),
+            instruments: Vec::new(),
+            schedule: None,
+            phase_grid_samples: None,
+            tolerance: None,
+            seed,
+            generated: None,
+            spot_generators: SpotGenerators::new(seed),
+            noise_generator: Arc::new(Mutex::new(rng::stream(seed, Stream::Noise))),
         }
     }
 
+    /// Start building a simulation of `star` from parameters instead of a config.
+    pub fn builder(star: impl Into<Arc<Star>>) -> SimulationBuilder {
+        SimulationBuilder::new(star)
+    }
+
     /// Construct a new Star from a TOML file.
-    pub fn from_config(config_path: &Path) -> Result<Simulation, String> {
-        let contents = std::fs::read_to_string(config_path).map_err(|_| {
-            format!(
-                "Tried to open a config file at {:?}, but it doesn't seem to exist",
-                config_path
-            )
-        })?;
+    pub fn from_config(config_path: &Path) -> Result<Simulation, LatherError> {
+        let config = SimulationConfig::read(config_path)?;
 
-        let config: Config = ::toml::from_str(&contents).map_err(|e| {
-            format!(
-                "{:?} is not a valid config file. Here's an example:\n{}\nThe internal error was: {}",
-                &config_path,
-                ::toml::to_string_pretty(&Config::example()).unwrap(),
-                e
-            )
+        // Surface maps, catalogs, and model spectra are found relative to the config file
+        let config_dir = config_path.parent().unwrap_or_else(|| Path::new(""));
+        Simulation::from_config_in(config, config_dir)
+    }
+
+    /// Construct a new simulation from the contents of a TOML config file. Relative paths
+    /// in the config are relative to the working directory.
+    pub fn from_toml_str(contents: &str) -> Result<Simulation, LatherError> {
+        let config = SimulationConfig::parse(contents).map_err(|message| LatherError::Parse {
+            path: None,
+            message,
         })?;
+        Simulation::new(config)
+    }
+
+    /// Construct a new simulation from a config. Relative paths in the config are relative
+    /// to the working directory.
+    pub fn new(config: SimulationConfig) -> Result<Simulation, LatherError> {
+        Simulation::from_config_in(config, Path::new(""))
+    }
 
-        let rng = match config.seed {
-            Some(SeedConfig::Number(num)) => StdRng::seed_from_u64(num),
+    /// Construct a new simulation from a config whose relative paths are relative to
+    /// `config_dir`.
+    pub(crate) fn from_config_in(
+        mut config: SimulationConfig,
+        config_dir: &Path,
+    ) -> Result<Simulation, LatherError> {
+        let seed = match config.seed {
+            Some(SeedConfig::Number(num)) => num,
             Some(SeedConfig::Text(t)) => {
+                // The seed drawn from entropy can be read back with `Simulation::seed`
                 if &t == "entropy" {
-                    eprintln!("Seeding simulation RNG from system-provided entropy");
-                    StdRng::from_entropy()
+                    rand::random()
                 } else {
-                    return Err(
+                    return Err(LatherError::Validation(
                     "Invalid rng seed specification, valid seeds are \"entropy\", or an integer"
-                        .to_string());
+                        .to_string()));
                 }
             }
-            None => StdRng::seed_from_u64(0x0123456789ABCDEFu64),
+            None => DEFAULT_SEED,
+        };
+
+        config.star.resolve_paths(config_dir);
+        let surface = match config.surface {
+            Some(surface_config) => Some(SurfaceMap::from_file(
+                &config_dir.join(&surface_config.path),
+                surface_config.nside,
+            )?),
+            None => None,
+        };
+        let catalog = match config.catalog {
+            Some(catalog_config) => Some(SpotCatalog::from_config(&catalog_config, config_dir)?),
+            None => None,
+        };
+        let exposure = match &config.exposure {
+            Some(exposure_config) => Exposure::from_config(exposure_config)?,
+            None => Exposure::default(),
+        };
+        let instruments = config
+            .instruments
+            .iter()
+            .flatten()
+            .map(Instrument::from_config)
+            .collect::<Result<Vec<_>, _>>()?;
+        let schedule = match &config.schedule {
+            Some(schedule_config) => Some(Schedule::from_config(schedule_config)?),
+            None => None,
         };
 
         let mut sim = Simulation {
-            star: Arc::new(Star::from_config(&config.star)),
+            star: Arc::new(Star::from_config(&config.star)?),
             spots: Vec::new(),
-            generator: Arc::new(Mutex::new(rng)),
+            surface,
+            catalog,
+            exposure,
+            instruments,
+            schedule,
+            phase_grid_samples: None,
+            tolerance: None,
+            seed,
+            generated: None,
+            spot_generators: SpotGenerators::new(seed),
+            noise_generator: Arc::new(Mutex::new(rng::stream(seed, Stream::Noise))),
         };
 
+        if let (Some(catalog), Some(range)) =
+            (&sim.catalog, sim.star.spectral_model.temperature_range())
+        {
+            // Spot regions are at the star's spot temperature, which the star has checked
+            let plage_temperature = catalog.temperature(&sim.star, true);
+            let has_plage = catalog.tracks.iter().any(|track| track.plage);
+            if has_plage && !(range.lower..=range.upper).contains(&plage_temperature) {
+                return Err(LatherError::Validation(format!(
+                    "The model spectra cover {} K to {} K, which does not include the catalog's \
+                     plage temperature {} K",
+                    range.lower, range.upper, plage_temperature
+                )));
+            }
+        }
         if let Some(spot_configs) = config.spots {
             for spot_config in spot_configs {
                 sim.spots
-                    .push(Spot::from_config(Arc::clone(&sim.star), &spot_config));
+                    .push(Spot::from_config(Arc::clone(&sim.star), &spot_config)?);
+            }
+        }
+        if let Some(resolution) = &config.resolution {
+            sim.set_tolerance(Some(Tolerance::from_config(resolution)?));
+        }
+        if let Some(phase_grid) = &config.phase_grid {
+            phase_grid::check_samples(phase_grid.samples)?;
+            sim.set_phase_grid(Some(phase_grid.samples));
+        }
+
+        Ok(sim)
+    }
+
+    /// Adds a spot to the star. Any randomly generated spots are discarded, and will be
+    /// regenerated around the new spot when they are next needed. Nothing changes if the
+    /// spot isn't valid.
+    pub fn add_spot(&mut self, config: &SpotConfig) -> Result<(), LatherError> {
+        let mut spot = Spot::from_config(Arc::clone(&self.star), config)?;
+        self.discard_generated_spots();
+        if let Some(tolerance) = &self.tolerance {
+            spot.strip_step = Some(choose_strip_step(&spot, tolerance));
+        }
+        self.spots.push(spot);
+        self.set_phase_grid(self.phase_grid_samples);
+        Ok(())
+    }
+
+    /// Chooses the strip spacing of each spot to keep its estimated discretization errors
+    /// within `tolerance`, instead of using the star's grid. Random spots are fitted as
+    /// they are generated. `None` goes back to the star's grid.
+    pub fn set_tolerance(&mut self, tolerance: Option<Tolerance>) {
+        self.tolerance = tolerance;
+        for spot in &mut self.spots {
+            spot.strip_step = tolerance.map(|t| choose_strip_step(spot, &t));
+            spot.phase_grid = None;
+        }
+        self.set_phase_grid(self.phase_grid_samples);
+    }
+
+    /// Observes the flux and CCF at each time (in days) like `observe_flux` and
+    /// `observe_rv`, along with estimates of their errors from the spacing of the strips
+    /// spots are integrated over. Catalog spots and surface maps are not included in the
+    /// estimates, and neither is the error of the quiet star's disk integral, which stays
+    /// on the star's grid; increase the star's `grid_size` to check that.
+    pub fn estimate_errors(
+        &self,
+        time: &[f64],
+        wavelength: Bounds,
+    ) -> Result<DiscretizationError, LatherError> {
+        let flux = self.observe_flux(time, wavelength)?;
+        let ccfs = self.observe_rv(time, wavelength)?;
+
+        let sub_times = self.sub_times(time);
+        let fine_spots: Vec<Spot> = self
+            .spots
+            .iter()
+            .map(|spot| Spot {
+                strip_step: Some(spot.strip_step() / 2.0),
+                phase_grid: None,
+                ..spot.clone()
+            })
+            .collect();
+        let fine_flux: Vec<f64> = self
+            .flux_bands(&fine_spots, &sub_times, &[wavelength])?
+            .into_iter()
+            .map(|flux| flux[0])
+            .collect();
+        let fine_ccfs: Vec<Vec<f64>> = self
+            .rv_bands(&fine_spots, &sub_times, &[wavelength])?
+            .into_iter()
+            .map(|mut ccfs| ccfs.swap_remove(0))
+            .collect();
+
+        Ok(DiscretizationError::new(
+            flux,
+            &fine_flux,
+            ccfs,
+            &fine_ccfs,
+            &self.star.profile_quiet.rv,
+        ))
+    }
+
+    /// Precomputes every immortal spot at `samples` phases of a rotation, so observations
+    /// interpolate between them instead of integrating over each spot at every time. This
+    /// makes long, densely sampled time series much cheaper. `None` goes back to
+    /// integrating at every time. Line groups are always integrated. A grid needs at least
+    /// 4 samples, which `SimulationBuilder::phase_grid` and configs check.
+    pub fn set_phase_grid(&mut self, samples: Option<usize>) {
+        if samples != self.phase_grid_samples {
+            for spot in &mut self.spots {
+                spot.phase_grid = None;
+            }
+        }
+        self.phase_grid_samples = samples;
+        if let Some(samples) = samples {
+            for spot in &mut self.spots {
+                if let (Mortality::Immortal, None) = (&spot.mortality, &spot.phase_grid) {
+                    spot.phase_grid = Some(Arc::new(PhaseGrid::new(spot, samples)));
+                }
             }
         }
+    }
+
+    /// The seed random spots and noise are drawn from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Reseeds the random spots and noise. Any randomly generated spots are discarded, and
+    /// will be regenerated from the new seed when they are next needed.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.discard_generated_spots();
+        self.seed = seed;
+        self.spot_generators = SpotGenerators::new(seed);
+        self.noise_generator = Arc::new(Mutex::new(rng::stream(seed, Stream::Noise)));
+    }
+
+    /// Removes the spot at `index` in `spots`. Randomly generated spots can't be removed,
+    /// and are discarded and regenerated around the remaining spots like with `add_spot`.
+    pub fn remove_spot(&mut self, index: usize) -> Result<Spot, LatherError> {
+        match self.spots.get(index) {
+            None => Err(LatherError::Validation(format!(
+                "There is no spot {}, because the simulation has {} spots",
+                index,
+                self.spots.len()
+            ))),
+            Some(spot) if spot.generated => Err(LatherError::Validation(format!(
+                "Spot {} was randomly generated, so it can't be removed",
+                index
+            ))),
+            Some(_) => {
+                let spot = self.spots.remove(index);
+                self.discard_generated_spots();
+                Ok(spot)
+            }
+        }
+    }
+
+    /// The window of time (in days) random spots have been generated over, if they have.
+    pub fn generated_window(&self) -> Option<Bounds> {
+        self.generated
+    }
+
+    /// Captures everything about the simulation, including how far its random number
+    /// generators have got, so it can be saved and later resumed exactly.
+    pub fn state(&self) -> SimulationState {
+        let [spot_sizes, spot_positions, spot_lifetimes] = self.spot_generators.positions();
+        let noise = self
+            .noise_generator
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_word_pos();
+        SimulationState {
+            format: STATE_FORMAT,
+            seed: self.seed,
+            star: self.star.config().clone(),
+            spots: self
+                .spots
+                .iter()
+                .map(|spot| SpotState {
+                    latitude: spot.latitude,
+                    longitude: spot.longitude,
+                    radius: spot.radius,
+                    temperature: spot.temperature,
+                    plage: spot.plage,
+                    lifetime: match spot.mortality {
+                        Mortality::Immortal => None,
+                        Mortality::Mortal(bounds) => Some(Lifetime {
+                            start: bounds.lower,
+                            end: bounds.upper,
+                        }),
+                    },
+                    generated: spot.generated,
+                    strip_step: spot.strip_step,
+                })
+                .collect(),
+            generated: self.generated,
+            generators: GeneratorPositions {
+                spot_sizes,
+                spot_positions,
+                spot_lifetimes,
+                noise,
+            },
+            exposure: self.exposure.to_config(),
+            phase_grid_samples: self.phase_grid_samples,
+            tolerance: self.tolerance,
+            surface: self.surface.as_ref().map(|surface| SurfaceState {
+                nside: surface.nside,
+                frames: surface.frames.clone(),
+            }),
+            catalog: self.catalog.clone(),
+            instruments: self.instruments.clone(),
+            schedule: self.schedule.clone(),
+        }
+    }
+
+    /// Restores a simulation from its state. It produces exactly the same observations,
+    /// noise, and random spots from then on as the simulation the state came from.
+    pub fn from_state(state: SimulationState) -> Result<Simulation, LatherError> {
+        let star = Arc::new(Star::from_config(&state.star)?);
+        let surface = match state.surface {
+            Some(surface) => Some(SurfaceMap::new(surface.nside, surface.frames)?),
+            None => None,
+        };
+        let spots = state
+            .spots
+            .into_iter()
+            .map(|spot| Spot {
+                star: Arc::clone(&star),
+                latitude: spot.latitude,
+                longitude: spot.longitude,
+                radius: spot.radius,
+                temperature: spot.temperature,
+                plage: spot.plage,
+                mortality: match spot.lifetime {
+                    Some(lifetime) => Mortal(Bounds::new(lifetime.start, lifetime.end)),
+                    None => Mortality::Immortal,
+                },
+                generated: spot.generated,
+                phase_grid: None,
+                strip_step: spot.strip_step,
+            })
+            .collect();
+        let positions = state.generators;
 
+        let mut sim = Simulation {
+            star,
+            spots,
+            surface,
+            catalog: state.catalog,
+            exposure: Exposure::from_config(&state.exposure)?,
+            instruments: state.instruments,
+            schedule: state.schedule,
+            phase_grid_samples: None,
+            tolerance: state.tolerance,
+            seed: state.seed,
+            generated: state.generated,
+            spot_generators: SpotGenerators::at(
+                state.seed,
+                [
+                    positions.spot_sizes,
+                    positions.spot_positions,
+                    positions.spot_lifetimes,
+                ],
+            ),
+            noise_generator: Arc::new(Mutex::new(rng::stream_at(
+                state.seed,
+                Stream::Noise,
+                positions.noise,
+            ))),
+        };
+        if let Some(samples) = state.phase_grid_samples {
+            phase_grid::check_samples(samples)?;
+        }
+        sim.set_phase_grid(state.phase_grid_samples);
         Ok(sim)
     }
 
-    pub fn add_spot(&mut self, config: &SpotConfig) {
-        self.spots
-            .push(Spot::from_config(Arc::clone(&self.star), config));
+    /// Saves the simulation's state to a TOML file, which `load_state` resumes from.
+    pub fn save_state(&self, path: &Path) -> Result<(), LatherError> {
+        self.state().write(path)
+    }
+
+    /// Resumes a simulation from a state file written by `save_state`.
+    pub fn load_state(path: &Path) -> Result<Simulation, LatherError> {
+        Simulation::from_state(SimulationState::read(path)?)
     }
 
     pub fn clear_spots(&mut self) {
         self.spots.clear();
+        self.generated = None;
     }
 
-    pub fn check_fill_factor(&mut self, time: f64) {
-        let mut current_fill_factor = self
-            .spots
+    /// Generates random spots to maintain the star's target fill factor over the window
+    /// of time (in days), replacing any previously generated spots. The spots only depend
+    /// on the seed, the manually added spots, and the start of the window, so the star is
+    /// the same no matter which times in the window are observed or in what order.
+    /// `ensure_spots` extends the window forward, or regenerates it from an earlier start.
+    ///
+    /// If the target fill factor can't be reached within the star's attempt limits, all
+    /// generated spots are discarded and the diagnostics are returned.
+    pub fn generate_spots(&mut self, window: Bounds) -> Result<(), GenerationError> {
+        self.discard_generated_spots();
+        self.spot_generators = SpotGenerators::new(self.seed);
+        self.check_fill_factor(window.lower)
+            .and_then(|_| {
+                self.generated = Some(Bounds::new(window.lower, window.lower));
+                self.extend_generated_spots(window.upper)
+            })
+            .inspect_err(|_| self.discard_generated_spots())
+    }
+
+    /// Makes sure random spots have been generated over the exposure at every time (in
+    /// days), starting at the earliest time. Observations never generate spots themselves,
+    /// so this (or `generate_spots`) must cover the times before a star with a target fill
+    /// factor can be observed at them. Later times extend the spots generated so far, but a
+    /// time before them means every random spot is generated again from that time, which
+    /// changes the spots at times already observed. To avoid that, pass the earliest time
+    /// first or call `generate_spots` with the whole campaign. Times which aren't finite
+    /// are ignored.
+    pub fn ensure_spots(&mut self, time: &[f64]) -> Result<(), GenerationError> {
+        let sub_times: Vec<f64> = self
+            .sub_times(time)
+            .into_iter()
+            .filter(|t| t.is_finite())
+            .collect();
+        let earliest = match sub_times.iter().cloned().reduce(f64::min) {
+            Some(earliest) => earliest,
+            None => return Ok(()),
+        };
+        let latest = sub_times.iter().cloned().fold(earliest, f64::max);
+        match self.generated {
+            Some(window) if window.lower <= earliest => self.extend_generated_spots(latest),
+            Some(window) => {
+                self.generate_spots(Bounds::new(earliest, f64::max(latest, window.upper)))
+            }
+            None => self.generate_spots(Bounds::new(earliest, latest)),
+        }
+    }
+
+    /// Continues generating random spots up to `end`, exactly as if they had been generated
+    /// all at once.
+    fn extend_generated_spots(&mut self, end: f64) -> Result<(), GenerationError> {
+        let window = self
+            .generated
+            .expect("Spots must be generated before the window can be extended");
+        let mut step = ((window.upper - window.lower) / GENERATION_STEP).round() as u64;
+        let mut last = window.upper;
+        loop {
+            let time = window.lower + (step + 1) as f64 * GENERATION_STEP;
+            if time > end {
+                break;
+            }
+            if let Err(e) = self.check_fill_factor(time) {
+                self.discard_generated_spots();
+                return Err(e);
+            }
+            last = time;
+            step += 1;
+        }
+        self.generated = Some(Bounds::new(window.lower, last));
+        Ok(())
+    }
+
+    /// Whether random spots have been generated over the exposure at every time (in days),
+    /// so they can be observed without `ensure_spots`.
+    pub fn spots_generated(&self, time: &[f64]) -> bool {
+        self.check_generated(&self.sub_times(time)).is_ok()
+    }
+
+    /// Checks that random spots have been generated at every time, so observations at them
+    /// include every spot.
+    fn check_generated(&self, sub_times: &[f64]) -> Result<(), LatherError> {
+        if self.star.target_fill_factor <= 0.0 {
+            return Ok(());
+        }
+        let covered = |time: f64| match self.generated {
+            Some(window) => time >= window.lower && time < window.upper + GENERATION_STEP,
+            None => false,
+        };
+        match sub_times.iter().find(|t| !covered(**t)) {
+            Some(time) => Err(LatherError::Validation(format!(
+                "Random spots haven't been generated at {} days, so spots must be generated with \
+                 ensure_spots or generate_spots before observing",
+                time
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn discard_generated_spots(&mut self) {
+        self.spots.retain(|s| !s.generated);
+        self.generated = None;
+    }
+
+    /// Generates the observation times (in days since the start of the campaign) from the
+    /// observing schedule in the config, or returns `None` if there is none.
+    pub fn scheduled_times(&self) -> Option<Vec<f64>> {
+        self.schedule.as_ref().map(|s| s.times())
+    }
+
+    /// Adds the noise of the named instrument to fluxes observed at `time`.
+    pub fn apply_flux_noise(
+        &self,
+        instrument: &str,
+        time: &[f64],
+        flux: &mut [f64],
+    ) -> Result<(), LatherError> {
+        let instrument = self.instrument(instrument)?;
+        check_noise_times(time)?;
+        let mut generator = self
+            .noise_generator
+            .lock()
+            // The generator is still usable after a panic elsewhere
+            .unwrap_or_else(|e| e.into_inner());
+        instrument.apply_flux_noise(time, flux, &mut *generator);
+        Ok(())
+    }
+
+    /// Adds the noise of the named instrument to CCFs observed at `time`.
+    pub fn apply_ccf_noise(
+        &self,
+        instrument: &str,
+        time: &[f64],
+        ccfs: &mut [Vec<f64>],
+    ) -> Result<(), LatherError> {
+        let instrument = self.instrument(instrument)?;
+        check_noise_times(time)?;
+        let mut generator = self
+            .noise_generator
+            .lock()
+            // The generator is still usable after a panic elsewhere
+            .unwrap_or_else(|e| e.into_inner());
+        instrument.apply_ccf_noise(time, &self.star.profile_quiet.rv, ccfs, &mut *generator);
+        Ok(())
+    }
+
+    fn instrument(&self, name: &str) -> Result<&Instrument, LatherError> {
+        self.instruments
             .iter()
-            .filter(|s| s.alive(time))
+            .find(|i| i.name == name)
+            .ok_or_else(|| {
+                LatherError::Validation(format!(
+                    "There is no instrument named {:?} in the config",
+                    name
+                ))
+            })
+    }
+
+    /// Adds random spots born at `time` until the star's target fill factor is reached.
+    fn check_fill_factor(&mut self, time: f64) -> Result<(), GenerationError> {
+        let alive = self.spots.iter().filter(|s| s.alive(time));
+        let mut current_fill_factor = alive
+            .clone()
             .map(|s| (s.radius * s.radius) / 2.0)
             .sum::<f64>();
+        let mut spots = alive.count();
 
-        let mut generator = self
-            .generator
-            .lock()
-            .expect("Simulation RNG lock was poisoned by another panic");
+        let generators = &mut self.spot_generators;
+        let mut collisions = 0;
 
         while current_fill_factor < self.star.target_fill_factor {
+            let mut size_draws = 0;
             let new_fill_factor = loop {
-                let possible_value =
-                    self.star.fillfactor_distribution.sample(&mut *generator) * 9.4e-6;
-                if possible_value < 0.001 {
-                    break possible_value;
+                size_draws += 1;
+                if let Some(fill_factor) = self.star.spot_size.sample(&mut generators.sizes) {
+                    break fill_factor;
+                }
+                if size_draws >= self.star.max_size_draws {
+                    return Err(GenerationError {
+                        failure: GenerationFailure::SizeDraws,
+                        time,
+                        target_fill_factor: self.star.target_fill_factor,
+                        fill_factor: current_fill_factor,
+                        spots,
+                        size_draws,
+                        rejected_sizes: size_draws,
+                        collisions,
+                    });
                 }
             };
 
-            let mut new_spot = Spot::from_config(
+            let mut new_spot = Spot::from_valid_config(
                 self.star.clone(),
                 &SpotConfig {
-                    latitude: self.star.latitude_distribution.sample(&mut *generator),
-                    longitude: self.star.longitude_distribution.sample(&mut *generator),
+                    latitude: self
+                        .star
+                        .latitude_distribution
+                        .sample(&mut generators.positions),
+                    longitude: self
+                        .star
+                        .longitude_distribution
+                        .sample(&mut generators.positions),
                     fill_factor: new_fill_factor,
                     plage: false,
                     temperature: None,
@@ -205,8 +822,12 @@ impl Simulation {
             );
             new_spot.mortality = Mortal(Bounds::new(
                 time,
-                time + self.star.lifetime_distribution.sample(&mut *generator),
+                time + self
+                    .star
+                    .lifetime_distribution
+                    .sample(&mut generators.lifetimes),
             ));
+            new_spot.generated = true;
 
             // TODO: This collision checking might be subpar
             let new_appear = time;
@@ -217,103 +838,442 @@ impl Simulation {
                 .filter(|s| s.alive(new_appear) || s.alive(new_disappear))
                 .any(|s| new_spot.collides_with(s));
 
-            if !collides {
+            if collides {
+                collisions += 1;
+                if collisions >= self.star.max_placement_attempts {
+                    return Err(GenerationError {
+                        failure: GenerationFailure::Placement,
+                        time,
+                        target_fill_factor: self.star.target_fill_factor,
+                        fill_factor: current_fill_factor,
+                        spots,
+                        size_draws,
+                        rejected_sizes: size_draws - 1,
+                        collisions,
+                    });
+                }
+            } else {
+                collisions = 0;
+                spots += 1;
                 current_fill_factor += (new_spot.radius * new_spot.radius) / 2.0;
+                if let Some(tolerance) = &self.tolerance {
+                    new_spot.strip_step = Some(choose_strip_step(&new_spot, tolerance));
+                }
                 self.spots.push(new_spot);
             }
         }
+
+        Ok(())
     }
 
     /// Computes the relative brightness of this system at each time (in days),
     /// when observed in the wavelength band between `wavelength_min` and `wavelength_max`.
-    pub fn observe_flux(&mut self, time: &[f64], wavelength: Bounds) -> Vec<f64> {
-        for t in time.iter() {
-            self.check_fill_factor(*t);
-        }
+    pub fn observe_flux(&self, time: &[f64], wavelength: Bounds) -> Result<Vec<f64>, LatherError> {
+        Ok(self
+            .observe_flux_bands(time, &[wavelength])?
+            .into_iter()
+            .map(|flux| flux[0])
+            .collect())
+    }
 
-        let star_intensity =
-            planck_integral(self.star.temperature, wavelength.lower, wavelength.upper);
-        for spot in &mut self.spots {
-            spot.intensity = planck_integral(spot.temperature, wavelength.lower, wavelength.upper)
-                / star_intensity;
-        }
+    /// Computes the relative brightness of this system at each time (in days), in each
+    /// of the wavelength bands. The geometry of each spot is only computed once per time,
+    /// so this is much faster than observing each band separately. The output is indexed
+    /// by time, then band.
+    pub fn observe_flux_bands(
+        &self,
+        time: &[f64],
+        bands: &[Bounds],
+    ) -> Result<Vec<Vec<f64>>, LatherError> {
+        let sub_times = self.sub_times(time);
+        self.check_generated(&sub_times)?;
+        self.flux_bands(&self.spots, &sub_times, bands)
+    }
 
-        time.par_iter()
+    /// Computes the flux in each band like `observe_flux_bands`, with `spots` in place of
+    /// the simulation's spots, at the times from `sub_times`.
+    fn flux_bands(
+        &self,
+        spots: &[Spot],
+        sub_times: &[f64],
+        bands: &[Bounds],
+    ) -> Result<Vec<Vec<f64>>, LatherError> {
+        let intensities: Vec<BandIntensities> = bands
+            .iter()
+            .map(|b| self.band_intensities(spots, *b))
+            .collect::<Result<_, _>>()?;
+
+        let flux: Vec<Vec<f64>> = sub_times
+            .par_iter()
             .map(|t| {
-                let spot_flux: f64 = self
-                    .spots
+                let mut spot_flux = vec![0.0; bands.len()];
+                for (i, spot) in spots.iter().enumerate().filter(|(_, s)| s.alive(*t)) {
+                    let covered = spot.limb_integral(*t);
+                    for (flux, band) in spot_flux.iter_mut().zip(intensities.iter()) {
+                        *flux += (1.0 - band.spots[i]) * covered;
+                    }
+                }
+                if let Some(catalog) = &self.catalog {
+                    for spot in catalog.spots_at(&self.star, *t) {
+                        let covered = spot.limb_integral(*t);
+                        for (flux, band) in spot_flux.iter_mut().zip(intensities.iter()) {
+                            *flux += (1.0 - band.catalog(spot.plage)) * covered;
+                        }
+                    }
+                }
+                if let Some(surface) = &self.surface {
+                    for (flux, band) in spot_flux.iter_mut().zip(intensities.iter()) {
+                        *flux += surface.get_flux(&self.star, *t, &band.surface);
+                    }
+                }
+                spot_flux
                     .iter()
-                    .filter(|s| s.alive(*t))
-                    .map(|s| s.get_flux(*t))
-                    .sum();
-                (self.star.flux_quiet - spot_flux) / self.star.flux_quiet
+                    .map(|flux| (self.star.flux_quiet - flux) / self.star.flux_quiet)
+                    .collect()
             })
-            .collect()
+            .collect();
+        Ok(self.average_exposures(flux))
     }
 
     /// Computes the radial velocity and line bisector of this system at each time (in days),
     /// when observed in the wavelength band between `wavelength_min` and `wavelength_max`.
-    pub fn observe_rv(&mut self, time: &[f64], wavelength: Bounds) -> Vec<Vec<f64>> {
-        for t in time.iter() {
-            self.check_fill_factor(*t);
-        }
+    pub fn observe_rv(
+        &self,
+        time: &[f64],
+        wavelength: Bounds,
+    ) -> Result<Vec<Vec<f64>>, LatherError> {
+        Ok(self
+            .observe_rv_bands(time, &[wavelength])?
+            .into_iter()
+            .map(|mut ccfs| ccfs.swap_remove(0))
+            .collect())
+    }
 
-        let star_intensity =
-            planck_integral(self.star.temperature, wavelength.lower, wavelength.upper);
-        for spot in &mut self.spots {
-            spot.intensity = planck_integral(spot.temperature, wavelength.lower, wavelength.upper)
-                / star_intensity;
-        }
+    /// Observes the flux at each time (in days) in the band, like `observe_flux`.
+    pub fn light_curve(&self, time: &[f64], band: Bounds) -> Result<LightCurve, LatherError> {
+        Ok(LightCurve {
+            time: time.to_vec(),
+            band,
+            flux: self.observe_flux(time, band)?,
+        })
+    }
+
+    /// Observes the CCF at each time (in days) in the band, like `observe_rv`.
+    pub fn ccf_series(&self, time: &[f64], band: Bounds) -> Result<CcfSeries, LatherError> {
+        Ok(CcfSeries {
+            time: time.to_vec(),
+            band,
+            rv: self.star.profile_quiet.rv.clone(),
+            ccfs: self.observe_rv(time, band)?,
+            quiet_ccf: self.star.integrated_ccf.clone(),
+        })
+    }
 
-        time.par_iter()
+    /// Computes the CCF of this system at each time (in days), in each of the wavelength
+    /// bands, such as the orders of an echelle spectrograph. The geometry of each spot is
+    /// only computed once per time, so this is much faster than observing each band
+    /// separately. The output is indexed by time, then band.
+    pub fn observe_rv_bands(
+        &self,
+        time: &[f64],
+        bands: &[Bounds],
+    ) -> Result<Vec<Vec<Vec<f64>>>, LatherError> {
+        let sub_times = self.sub_times(time);
+        self.check_generated(&sub_times)?;
+        self.rv_bands(&self.spots, &sub_times, bands)
+    }
+
+    /// Computes the CCF in each band like `observe_rv_bands`, with `spots` in place of
+    /// the simulation's spots, at the times from `sub_times`.
+    fn rv_bands(
+        &self,
+        spots: &[Spot],
+        sub_times: &[f64],
+        bands: &[Bounds],
+    ) -> Result<Vec<Vec<Vec<f64>>>, LatherError> {
+        let intensities: Vec<BandIntensities> = bands
+            .iter()
+            .map(|b| self.band_intensities(spots, *b))
+            .collect::<Result<_, _>>()?;
+
+        let ccfs = sub_times
+            .par_iter()
             .map(|t| {
-                let mut spots_profile = vec![0.0; CCF_LEN];
-                for spot in self.spots.iter().filter(|s| s.alive(*t)) {
-                    for (total, this) in spots_profile.iter_mut().zip(spot.get_ccf(*t).iter()) {
-                        *total += *this;
+                self.observe_profiles(
+                    spots,
+                    *t,
+                    &self.star.profile_quiet,
+                    &self.star.profile_spot,
+                    &self.star.integrated_ccf,
+                    &intensities,
+                )
+                .concat()
+            })
+            .collect();
+        Ok(self
+            .average_exposures(ccfs)
+            .into_iter()
+            .map(|ccfs| {
+                ccfs.chunks(self.star.profile_quiet.len())
+                    .map(|ccf| ccf.to_vec())
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Computes the CCF of each of the star's line groups at each time (in days), when
+    /// observed in the wavelength band. The output is indexed by time, then line group.
+    pub fn observe_line_groups(
+        &self,
+        time: &[f64],
+        wavelength: Bounds,
+    ) -> Result<Vec<Vec<Vec<f64>>>, LatherError> {
+        let sub_times = self.sub_times(time);
+        self.check_generated(&sub_times)?;
+        let intensities = [self.band_intensities(&self.spots, wavelength)?];
+
+        let ccfs = sub_times
+            .par_iter()
+            .map(|t| {
+                self.star
+                    .line_groups
+                    .iter()
+                    .flat_map(|group| {
+                        self.observe_profiles(
+                            &self.spots,
+                            *t,
+                            &group.profile_quiet,
+                            &group.profile_spot,
+                            &group.integrated_ccf,
+                            &intensities,
+                        )
+                        .swap_remove(0)
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(self
+            .average_exposures(ccfs)
+            .into_iter()
+            .map(|ccfs| {
+                let mut ccfs = ccfs.as_slice();
+                self.star
+                    .line_groups
+                    .iter()
+                    .map(|group| {
+                        let (ccf, rest) = ccfs.split_at(group.integrated_ccf.len());
+                        ccfs = rest;
+                        ccf.to_vec()
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Returns the wavelengths (in meters) of spectra produced by `observe_spectrum`,
+    /// or `None` if the star has no template spectra.
+    pub fn spectrum_wavelength(&self) -> Option<&[f64]> {
+        self.star
+            .synthesis
+            .as_ref()
+            .map(|s| s.wavelength.as_slice())
+    }
+
+    /// Computes the disk-integrated spectrum of this system at each time (in days),
+    /// by Doppler shifting the star's quiet and spot template spectra. Spot contrast
+    /// is computed over the wavelength range of the templates.
+    pub fn observe_spectrum(&self, time: &[f64]) -> Result<Vec<Vec<f64>>, LatherError> {
+        let synthesis = self.star.synthesis.as_ref().ok_or_else(|| {
+            LatherError::Validation(
+                "Observing spectra requires template spectra in the star's synthesis config"
+                    .to_string(),
+            )
+        })?;
+
+        let sub_times = self.sub_times(time);
+        self.check_generated(&sub_times)?;
+        let intensities = [self.band_intensities(&self.spots, synthesis.band())?];
+
+        let spectra = sub_times
+            .par_iter()
+            .map(|t| {
+                self.observe_profiles(
+                    &self.spots,
+                    *t,
+                    &synthesis.profile_quiet,
+                    &synthesis.profile_spot,
+                    &self.star.integrated_spectrum,
+                    &intensities,
+                )
+                .swap_remove(0)
+            })
+            .collect();
+        Ok(self.average_exposures(spectra))
+    }
+
+    /// The midpoints of the sub-exposures of every exposure, in order.
+    fn sub_times(&self, time: &[f64]) -> Vec<f64> {
+        time.iter()
+            .flat_map(|t| self.exposure.sub_times(*t, self.star.period))
+            .collect()
+    }
+
+    /// Averages consecutive observations at the times from `sub_times` into one per exposure.
+    /// Profiles are not normalized, so their average is weighted by the flux of each
+    /// sub-exposure.
+    fn average_exposures(&self, observations: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+        let count = self.exposure.count(self.star.period);
+        if count == 1 {
+            return observations;
+        }
+        observations
+            .chunks(count)
+            .map(|exposure| {
+                let mut average = vec![0.0; exposure[0].len()];
+                for observation in exposure {
+                    for (avg, value) in average.iter_mut().zip(observation.iter()) {
+                        *avg += value / count as f64;
                     }
                 }
+                average
+            })
+            .collect()
+    }
 
-                for (spot, star) in spots_profile
+    /// Computes the disk-integrated profile at `time` in each band, where `quiet` and
+    /// `active` are the profiles of the quiet photosphere and of spots, and `integrated`
+    /// is `quiet` integrated over the disk of the unspotted star. `spots` are observed in
+    /// place of the simulation's spots.
+    fn observe_profiles(
+        &self,
+        spots: &[Spot],
+        time: f64,
+        quiet: &Profile,
+        active: &Profile,
+        integrated: &[f64],
+        intensities: &[BandIntensities],
+    ) -> Vec<Vec<f64>> {
+        let mut profiles = vec![integrated.to_vec(); intensities.len()];
+
+        let mut quiet_covered = vec![0.0; quiet.len()];
+        let mut active_covered = vec![0.0; active.len()];
+        let mut subtract_spot = |spot: &Spot, intensity: &dyn Fn(&BandIntensities) -> f64| {
+            quiet_covered.iter_mut().for_each(|v| *v = 0.0);
+            active_covered.iter_mut().for_each(|v| *v = 0.0);
+            spot.add_profile_components(
+                time,
+                quiet,
+                active,
+                &mut quiet_covered,
+                &mut active_covered,
+            );
+            for (profile, band) in profiles.iter_mut().zip(intensities.iter()) {
+                let intensity = intensity(band);
+                for ((tot, q), a) in profile
                     .iter_mut()
-                    .zip(self.star.integrated_ccf.iter())
+                    .zip(quiet_covered.iter())
+                    .zip(active_covered.iter())
                 {
-                    *spot = *star - *spot;
+                    *tot -= q - intensity * a;
+                }
+            }
+        };
+
+        for (i, spot) in spots.iter().enumerate().filter(|(_, s)| s.alive(time)) {
+            subtract_spot(spot, &|band| band.spots[i]);
+        }
+        if let Some(catalog) = &self.catalog {
+            for spot in catalog.spots_at(&self.star, time) {
+                subtract_spot(&spot, &|band| band.catalog(spot.plage));
+            }
+        }
+        if let Some(surface) = &self.surface {
+            for (profile, band) in profiles.iter_mut().zip(intensities.iter()) {
+                let mut change = vec![0.0; quiet.len()];
+                surface.add_profile(&self.star, time, &band.surface, quiet, active, &mut change);
+                for (tot, change) in profile.iter_mut().zip(change.iter()) {
+                    *tot -= change;
                 }
+            }
+        }
 
-                spots_profile
-            })
-            .collect()
+        profiles
+    }
+
+    /// Computes the intensity of each of `spots`, catalog region, and surface map pixel
+    /// relative to the quiet photosphere in the wavelength band.
+    fn band_intensities(
+        &self,
+        spots: &[Spot],
+        wavelength: Bounds,
+    ) -> Result<BandIntensities, LatherError> {
+        let spots = spots
+            .iter()
+            .map(|spot| self.star.intensity(spot.temperature, wavelength))
+            .collect::<Result<_, _>>()?;
+
+        let surface = match &self.surface {
+            Some(surface) => surface
+                .frames
+                .iter()
+                .map(|frame| {
+                    frame
+                        .pixels
+                        .iter()
+                        .map(|pixel| self.star.intensity(pixel.temperature, wavelength))
+                        .collect()
+                })
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+
+        let catalog = |plage| match &self.catalog {
+            Some(catalog) => self
+                .star
+                .intensity(catalog.temperature(&self.star, plage), wavelength),
+            None => Ok(1.0),
+        };
+
+        Ok(BandIntensities {
+            spots,
+            catalog: catalog(false)?,
+            catalog_plage: catalog(true)?,
+            surface,
+        })
     }
 
     /// Draw the simulation in a row-major fashion, as it would be seen in the visible
     /// wavelength band, 4000-7000 Angstroms.
-    pub fn draw_bgr(&mut self, time: f64, image: &mut [u8]) {
+    pub fn draw_bgr(&self, time: f64, image: &mut [u8]) -> Result<(), LatherError> {
         // This is slow because the image is row-major, but we navigate the simulation in
         // a column-major fashion to follow the rotational symmetry
-        self.check_fill_factor(time);
+        self.check_generated(&[time])?;
 
         self.star.draw_bgr(image);
 
-        let star_intensity = planck_integral(self.star.temperature, 4000e-10, 7000e-10);
-        // Clone the spots and mutate a local copy so this function runs in parallel
-        let mut spots = self.spots.clone();
-        for spot in &mut spots {
-            spot.intensity = planck_integral(spot.temperature, 4000e-10, 7000e-10) / star_intensity;
-        }
+        let visible = Bounds::new(4000e-10, 7000e-10);
+        let catalog_spots = match &self.catalog {
+            Some(catalog) => catalog.spots_at(&self.star, time),
+            None => Vec::new(),
+        };
 
         let grid_interval = 2.0 / self.star.grid_size as f64;
 
-        for spot in spots.iter().filter(|s| s.alive(time)) {
-            let color = match &TEMP_TO_RGB.binary_search_by(|k| k.0.cmp(&(spot.temperature as u16)))
+        for spot in self
+            .spots
+            .iter()
+            .chain(catalog_spots.iter())
+            .filter(|s| s.alive(time))
+        {
+            let contrast = self.star.intensity(spot.temperature, visible)?;
+            let color = match TEMP_TO_RGB.binary_search_by(|k| k.0.cmp(&(spot.temperature as u16)))
             {
-                Ok(v) => TEMP_TO_RGB[*v].1,
-                Err(v) => TEMP_TO_RGB[*v].1,
+                Ok(v) => TEMP_TO_RGB[v].1,
+                Err(v) => TEMP_TO_RGB[v].1,
             };
             let color = [color[0] as f64, color[1] as f64, color[2] as f64];
 
             let bounds = BoundingShape::new(spot, time);
-            let mut current_z_bounds = None;
             if let Some(y_bounds) = bounds.y_bounds() {
                 for y in floatrange(
                     (y_bounds.lower / grid_interval).round() * grid_interval,
@@ -321,7 +1281,7 @@ impl Simulation {
                     grid_interval,
                 ) {
                     let y_index = ((y + 1.0) / 2.0 * 1000.0).round() as usize;
-                    if let Some(z_bounds) = bounds.z_bounds(y, &mut current_z_bounds) {
+                    if let Some(z_bounds) = bounds.z_bounds(y) {
                         for z in floatrange(
                             (z_bounds.lower / grid_interval).round() * grid_interval,
                             (z_bounds.upper / grid_interval).round() * grid_interval,
@@ -329,11 +1289,11 @@ impl Simulation {
                         ) {
                             let x = 1.0 - (y * y + z * z);
                             let x = f64::max(0.0, x);
-                            let intensity = self.star.limb_brightness(x) * spot.intensity;
+                            let intensity = self.star.limb_brightness(x) * contrast;
                             let z_index = ((-z + 1.0) / 2.0 * 1000.0).round() as usize;
-                            let index = (z_index * 1000 + y_index) as usize;
+                            let index = z_index * 1000 + y_index;
                             // opencv wants BGR, we have RGB
-                            image[3 * index + 0] = min(color[2] * intensity, 255.0) as u8;
+                            image[3 * index] = min(color[2] * intensity, 255.0) as u8;
                             image[3 * index + 1] = min(color[1] * intensity, 255.0) as u8;
                             image[3 * index + 2] = min(color[0] * intensity, 255.0) as u8;
                         }
@@ -341,6 +1301,7 @@ impl Simulation {
                 }
             }
         }
+        Ok(())
     }
 }
 
@@ -352,16 +1313,522 @@ fn min(a: f64, b: f64) -> f64 {
     }
 }
 
+/// Checks that noise isn't being drawn at a time which can't be put in order.
+fn check_noise_times(time: &[f64]) -> Result<(), LatherError> {
+    match time.iter().find(|t| !t.is_finite()) {
+        Some(t) => Err(LatherError::Validation(format!(
+            "Noise can only be drawn at finite times, but one is {}",
+            t
+        ))),
+        None => Ok(()),
+    }
+}
+
 //http://www.isthe.com/chongo/tech/astro/HR-temp-mass-table-byhrclass.html
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
     use super::*;
+    use crate::exposure::Supersample;
 
     #[test]
     fn example_config_is_valid() {
-        Simulation::from_config(&Path::new("examples/sun.toml")).unwrap();
-        Simulation::from_config(&Path::new("examples/random.toml")).unwrap();
+        Simulation::from_config(Path::new("examples/sun.toml")).unwrap();
+        Simulation::from_config(Path::new("examples/random.toml")).unwrap();
+        Simulation::from_config(Path::new("examples/surface.toml")).unwrap();
+        Simulation::from_config(Path::new("examples/catalog.toml")).unwrap();
+        Simulation::from_config(Path::new("examples/synthesis.toml")).unwrap();
+        Simulation::from_config(Path::new("examples/line_groups.toml")).unwrap();
+        Simulation::from_config(Path::new("examples/noise.toml")).unwrap();
+        Simulation::from_config(Path::new("examples/campaign.toml")).unwrap();
+        Simulation::from_config(Path::new("examples/trappist.toml")).unwrap();
+    }
+
+    #[test]
+    fn tabulated_example_needs_covered_bands() {
+        let sim = Simulation::from_config(Path::new("examples/trappist.toml")).unwrap();
+        // The spots face us half a rotation in
+        let flux = sim
+            .observe_flux(&[12.5], Bounds::new(6800e-10, 7400e-10))
+            .unwrap();
+        assert_ne!(flux[0], 1.0);
+        assert!(sim
+            .observe_flux(&[0.0], Bounds::new(9000e-10, 11000e-10))
+            .is_err());
+    }
+
+    #[test]
+    fn config_round_trips_through_toml() {
+        let band = Bounds::new(4000e-10, 5000e-10);
+        let times = [0.0, 1.0, 2.0];
+        let config = SimulationConfig::read(Path::new("examples/noise.toml")).unwrap();
+        let observe = |config: SimulationConfig| {
+            let mut sim = Simulation::from_config_in(config, Path::new("examples")).unwrap();
+            sim.ensure_spots(&times).unwrap();
+            sim.observe_flux(&times, band).unwrap()
+        };
+        let toml = config.to_toml();
+        assert_eq!(
+            observe(SimulationConfig::parse(&toml).unwrap()),
+            observe(config)
+        );
+    }
+
+    #[test]
+    fn observe_scheduled_campaign() {
+        let mut sim = Simulation::from_config(Path::new("examples/campaign.toml")).unwrap();
+        let times = sim.scheduled_times().unwrap();
+        assert!(times.len() > 50);
+        sim.ensure_spots(&times).unwrap();
+        let ccfs = sim
+            .observe_rv(&times, Bounds::new(4000e-10, 7000e-10))
+            .unwrap();
+        assert_eq!(ccfs.len(), times.len());
+    }
+
+    #[test]
+    fn invalid_spots_are_rejected() {
+        let mut sim = Simulation::sun();
+        let spots = sim.spots.len();
+        for fill_factor in [-0.01, 0.0, 0.5, 2.0, f64::NAN] {
+            assert!(sim
+                .add_spot(&SpotConfig::new(10.0, 0.0, fill_factor))
+                .is_err());
+        }
+        assert!(sim.add_spot(&SpotConfig::new(100.0, 0.0, 0.01)).is_err());
+        assert!(sim
+            .add_spot(&SpotConfig::new(10.0, 0.0, 0.01).with_temperature(f64::NAN))
+            .is_err());
+        assert_eq!(sim.spots.len(), spots);
+    }
+
+    #[test]
+    fn remove_and_reseed() {
+        let band = Bounds::new(4000e-10, 5000e-10);
+        let times = [0.0, 5.0];
+        let mut sim = Simulation::sun();
+        sim.add_spot(&SpotConfig::new(10.0, 0.0, 0.001)).unwrap();
+        sim.ensure_spots(&times).unwrap();
+        let generated = sim.spots.iter().position(|s| s.generated).unwrap();
+        assert!(sim.remove_spot(generated).is_err());
+        assert!(sim.remove_spot(sim.spots.len()).is_err());
+
+        let flux = sim.observe_flux(&times, band).unwrap();
+        assert_eq!(sim.remove_spot(0).unwrap().latitude, 10f64.to_radians());
+        assert!(sim.spots.is_empty());
+        assert!(sim.generated_window().is_none());
+
+        sim.set_seed(1);
+        sim.ensure_spots(&times).unwrap();
+        assert_ne!(flux, sim.observe_flux(&times, band).unwrap());
+        sim.set_seed(DEFAULT_SEED);
+        sim.add_spot(&SpotConfig::new(10.0, 0.0, 0.001)).unwrap();
+        sim.ensure_spots(&times).unwrap();
+        assert_eq!(flux, sim.observe_flux(&times, band).unwrap());
+    }
+
+    #[test]
+    fn resumes_from_saved_state() {
+        let band = Bounds::new(4000e-10, 5000e-10);
+        let (before, after) = ([0.0, 1.0, 2.0], [3.0, 7.5, 12.0]);
+        let mut sim = Simulation::from_config(Path::new("examples/noise.toml")).unwrap();
+        sim.set_tolerance(Some(Tolerance {
+            flux: 1e-4,
+            rv: 0.1,
+        }));
+        sim.ensure_spots(&before).unwrap();
+        let mut flux = sim.observe_flux(&before, band).unwrap();
+        sim.apply_flux_noise("TESS", &before, &mut flux).unwrap();
+
+        let path = std::env::temp_dir().join(format!("lather-state-{}.toml", std::process::id()));
+        sim.save_state(&path).unwrap();
+        let mut restored = Simulation::load_state(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let carry_on = |sim: &mut Simulation| {
+            sim.ensure_spots(&after).unwrap();
+            let mut flux = sim.observe_flux(&after, band).unwrap();
+            sim.apply_flux_noise("TESS", &after, &mut flux).unwrap();
+            let mut ccfs = sim.observe_rv(&after, band).unwrap();
+            sim.apply_ccf_noise("HARPS", &after, &mut ccfs).unwrap();
+            (flux, ccfs, sim.spots.len())
+        };
+        assert_eq!(carry_on(&mut restored), carry_on(&mut sim));
+        assert_eq!(restored.state().generators, sim.state().generators);
+
+        for example in &["examples/surface.toml", "examples/catalog.toml"] {
+            let sim = Simulation::from_config(Path::new(example)).unwrap();
+            let state = SimulationState::from_toml_str(&sim.state().to_toml()).unwrap();
+            let restored = Simulation::from_state(state).unwrap();
+            assert_eq!(
+                restored.observe_flux(&before, band).unwrap(),
+                sim.observe_flux(&before, band).unwrap()
+            );
+        }
+        assert!(SimulationState::from_toml_str("format = 0").is_err());
+    }
+
+    #[test]
+    fn noise_is_reproducible() {
+        let band = Bounds::new(4000e-10, 5000e-10);
+        let times = [0.0, 1.0, 2.0];
+        let noisy_flux = || {
+            let mut sim = Simulation::from_config(Path::new("examples/noise.toml")).unwrap();
+            sim.ensure_spots(&times).unwrap();
+            let mut flux = sim.observe_flux(&times, band).unwrap();
+            sim.apply_flux_noise("TESS", &times, &mut flux).unwrap();
+            flux
+        };
+        let flux = noisy_flux();
+        assert_eq!(flux, noisy_flux());
+
+        let mut sim = Simulation::from_config(Path::new("examples/noise.toml")).unwrap();
+        sim.ensure_spots(&times).unwrap();
+        assert_ne!(flux, sim.observe_flux(&times, band).unwrap());
+        let mut ccfs = sim.observe_rv(&times, band).unwrap();
+        sim.apply_ccf_noise("HARPS", &times, &mut ccfs).unwrap();
+        assert!(sim.apply_ccf_noise("ESPRESSO", &times, &mut ccfs).is_err());
+        assert!(sim
+            .apply_ccf_noise("HARPS", &[0.0, f64::NAN, 2.0], &mut ccfs)
+            .is_err());
+    }
+
+    #[test]
+    fn exposures_average_sub_exposures() {
+        let band = Bounds::new(4000e-10, 5000e-10);
+        let mut sim = Simulation::from_config(Path::new("examples/sun.toml")).unwrap();
+        let instantaneous = sim.observe_flux(&[2.75, 3.0, 3.25], band).unwrap();
+        let instantaneous_ccfs = sim.observe_rv(&[2.75, 3.0, 3.25], band).unwrap();
+
+        sim.exposure = Exposure {
+            duration: 0.75,
+            supersample: Supersample::Fixed(3),
+        };
+        let exposed = sim.observe_flux(&[3.0], band).unwrap();
+        let expected = instantaneous.iter().sum::<f64>() / 3.0;
+        assert!((exposed[0] - expected).abs() < 1e-12);
+
+        let exposed_ccf = &sim.observe_rv(&[3.0], band).unwrap()[0];
+        for (i, value) in exposed_ccf.iter().enumerate() {
+            let expected = instantaneous_ccfs.iter().map(|ccf| ccf[i]).sum::<f64>() / 3.0;
+            assert!((value - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn spots_do_not_depend_on_observation_order() {
+        let band = Bounds::new(4000e-10, 5000e-10);
+        let mut forward = Simulation::from_config(Path::new("examples/noise.toml")).unwrap();
+        let mut backward = Simulation::from_config(Path::new("examples/noise.toml")).unwrap();
+
+        // Spots are generated from the earliest time either way
+        forward.ensure_spots(&[5.0, 30.0]).unwrap();
+        let flux = forward.observe_flux(&[5.0, 30.0], band).unwrap();
+        forward.ensure_spots(&[10.0]).unwrap();
+        let ccf = forward.observe_rv(&[10.0], band).unwrap();
+        backward.generate_spots(Bounds::new(5.0, 5.0)).unwrap();
+        backward.ensure_spots(&[10.0]).unwrap();
+        let later_ccf = backward.observe_rv(&[10.0], band).unwrap();
+        backward.ensure_spots(&[30.0, 5.0]).unwrap();
+        let later_flux = backward.observe_flux(&[30.0, 5.0], band).unwrap();
+
+        assert_eq!(ccf, later_ccf);
+        assert_eq!(flux, vec![later_flux[1], later_flux[0]]);
+
+        let mut pregenerated = Simulation::from_config(Path::new("examples/noise.toml")).unwrap();
+        pregenerated
+            .generate_spots(Bounds::new(5.0, 100.0))
+            .unwrap();
+        let spots = pregenerated.spots.len();
+        assert!(spots > 0);
+        assert_eq!(pregenerated.observe_flux(&[5.0, 30.0], band).unwrap(), flux);
+        assert_eq!(pregenerated.spots.len(), spots);
+    }
+
+    #[test]
+    fn spots_are_generated_from_the_earliest_time() {
+        let mut sim = Simulation::from_config(Path::new("examples/noise.toml")).unwrap();
+        // Julian dates only generate spots over the days asked for
+        sim.ensure_spots(&[2_460_000.0, 2_460_010.0]).unwrap();
+        assert_eq!(sim.generated.unwrap().lower, 2_460_000.0);
+
+        // An earlier time starts the generated spots again from there
+        sim.ensure_spots(&[2_459_990.0]).unwrap();
+        assert_eq!(sim.generated.unwrap().lower, 2_459_990.0);
+        assert!(sim.generated.unwrap().upper >= 2_460_010.0);
+    }
+
+    #[test]
+    fn observing_needs_generated_spots() {
+        let band = Bounds::new(4000e-10, 5000e-10);
+        let mut sim = Simulation::from_config(Path::new("examples/noise.toml")).unwrap();
+        assert!(sim.observe_flux(&[1.0], band).is_err());
+        sim.generate_spots(Bounds::new(0.0, 10.0)).unwrap();
+        assert!(sim.observe_flux(&[0.0, 10.0], band).is_ok());
+        assert!(sim.spots_generated(&[0.0, 10.0]));
+        assert!(!sim.spots_generated(&[20.0]));
+        assert!(sim.observe_rv(&[20.0], band).is_err());
+        let mut image = vec![0; 1000 * 1000 * 3];
+        assert!(sim.draw_bgr(20.0, &mut image).is_err());
+        sim.ensure_spots(&[20.0]).unwrap();
+        assert!(sim.draw_bgr(20.0, &mut image).is_ok());
+    }
+
+    #[test]
+    fn observe_from_several_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Simulation>();
+
+        let band = Bounds::new(4000e-10, 5000e-10);
+        let times = [0.0, 5.0, 10.0];
+        let mut sim = Simulation::from_config(Path::new("examples/noise.toml")).unwrap();
+        sim.ensure_spots(&times).unwrap();
+        let flux = sim.observe_flux(&times, band).unwrap();
+        let sim = Arc::new(sim);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let sim = Arc::clone(&sim);
+                std::thread::spawn(move || sim.observe_flux(&times, band).unwrap())
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), flux);
+        }
+    }
+
+    #[test]
+    fn phase_grid_matches_integration() {
+        let mut sim = Simulation::sun();
+        Arc::get_mut(&mut sim.star).unwrap().target_fill_factor = 0.0;
+        sim.add_spot(&SpotConfig {
+            latitude: 10.0,
+            longitude: 45.0,
+            fill_factor: 0.01,
+            plage: false,
+            temperature: None,
+            lifetime: None,
+        })
+        .unwrap();
+        let band = Bounds::new(4000e-10, 5000e-10);
+        let time: Vec<f64> = (0..20).map(|i| 100.0 + i as f64 * 1.37).collect();
+        let flux = sim.observe_flux(&time, band).unwrap();
+        let ccfs = sim.observe_rv(&time, band).unwrap();
+
+        sim.set_phase_grid(Some(500));
+        assert!(sim.spots[0].phase_grid.is_some());
+        let grid_flux = sim.observe_flux(&time, band).unwrap();
+        let grid_ccfs = sim.observe_rv(&time, band).unwrap();
+        for (exact, interpolated) in flux.iter().zip(grid_flux.iter()) {
+            assert!((exact - interpolated).abs() < 1e-5);
+        }
+        for (exact, interpolated) in ccfs.iter().flatten().zip(grid_ccfs.iter().flatten()) {
+            assert!((exact - interpolated).abs() < 1e-4 * exact.abs());
+        }
+
+        sim.set_phase_grid(None);
+        assert!(sim.spots[0].phase_grid.is_none());
+    }
+
+    #[test]
+    fn tolerance_bounds_estimated_errors() {
+        let mut sim = Simulation::sun();
+        Arc::get_mut(&mut sim.star).unwrap().target_fill_factor = 0.0;
+        sim.add_spot(&SpotConfig {
+            latitude: 20.0,
+            longitude: 0.0,
+            fill_factor: 0.003,
+            plage: false,
+            temperature: None,
+            lifetime: None,
+        })
+        .unwrap();
+        let band = Bounds::new(4000e-10, 5000e-10);
+        let time = [0.0, 2.0, 4.0];
+
+        sim.set_tolerance(Some(Tolerance {
+            flux: 1e-6,
+            rv: 0.05,
+        }));
+        let estimate = sim.estimate_errors(&time, band).unwrap();
+        assert_eq!(estimate.flux, sim.observe_flux(&time, band).unwrap());
+        for (flux_error, rv_error) in estimate.flux_error.iter().zip(estimate.rv_error.iter()) {
+            assert!(*flux_error < 1e-5);
+            assert!(*rv_error < 0.5);
+        }
+        assert!(sim.spots[0].strip_step.is_some());
+
+        sim.set_tolerance(None);
+        assert!(sim.spots[0].strip_step.is_none());
+    }
+
+    #[test]
+    fn typed_results_match_observations() {
+        let band = Bounds::new(4000e-10, 7000e-10);
+        let time = [22.0, 28.0, 37.0];
+        let star = StarBuilder::new().grid_size(200).build().unwrap();
+        let quiet = Simulation::builder(star).build().unwrap();
+        assert!(quiet
+            .ccf_series(&time, band)
+            .unwrap()
+            .rv_shifts()
+            .iter()
+            .all(|rv| rv.abs() < 1e-6));
+
+        let mut sim = Simulation::with_star(Arc::clone(&quiet.star), DEFAULT_SEED);
+        sim.add_spot(&SpotConfig::new(0.0, 0.0, 0.01)).unwrap();
+        let light_curve = sim.light_curve(&time, band).unwrap();
+        assert_eq!(light_curve.flux, sim.observe_flux(&time, band).unwrap());
+        let series = sim.ccf_series(&time, band).unwrap();
+        assert_eq!(series.ccfs, sim.observe_rv(&time, band).unwrap());
+        // The spot is on the approaching half of the disk before it crosses the meridian,
+        // so the star looks redshifted, then blueshifted after, then it isn't visible
+        let shifts = series.rv_shifts();
+        assert!(shifts[0] > 1.0);
+        assert!(shifts[1] < -1.0);
+        assert_eq!(shifts[2], 0.0);
+    }
+
+    #[test]
+    fn ensemble_shares_star() {
+        let star = Simulation::sun().star;
+        let mut first = Simulation::with_star(Arc::clone(&star), 1);
+        let mut second = Simulation::with_star(Arc::clone(&star), 2);
+        first.generate_spots(Bounds::new(0.0, 10.0)).unwrap();
+        second.generate_spots(Bounds::new(0.0, 10.0)).unwrap();
+
+        assert!(Arc::ptr_eq(&first.star, &second.star));
+        assert_ne!(first.spots[0].latitude, second.spots[0].latitude);
+    }
+
+    #[test]
+    fn oversized_spots_give_up() {
+        let mut sim = Simulation::sun();
+        let star = Arc::get_mut(&mut sim.star).unwrap();
+        star.spot_size.distribution =
+            crate::distributions::Distribution::Uniform(rand_distr::Uniform::new(1000.0, 2000.0));
+        star.max_size_draws = 50;
+
+        let error = sim.generate_spots(Bounds::new(0.0, 10.0)).unwrap_err();
+        assert_eq!(error.failure, GenerationFailure::SizeDraws);
+        assert_eq!(error.size_draws, 50);
+        assert_eq!(error.spots, 0);
+        assert!(sim.spots.is_empty());
+        assert!(sim
+            .observe_flux(&[1.0], Bounds::new(4000e-10, 5000e-10))
+            .is_err());
+    }
+
+    #[test]
+    fn crowded_star_gives_up() {
+        let mut sim = Simulation::sun();
+        let star = Arc::get_mut(&mut sim.star).unwrap();
+        star.target_fill_factor = 0.9;
+        star.spot_size.distribution =
+            crate::distributions::Distribution::Uniform(rand_distr::Uniform::new(100.0, 106.0));
+        star.max_placement_attempts = 20;
+
+        let error = sim.generate_spots(Bounds::new(0.0, 10.0)).unwrap_err();
+        assert_eq!(error.failure, GenerationFailure::Placement);
+        assert_eq!(error.collisions, 20);
+        assert!(error.fill_factor < 0.9);
+        assert!(error.spots > 0);
+        assert!(sim.spots.is_empty());
+    }
+
+    #[test]
+    fn bands_match_separate_observations() {
+        let bands = [
+            Bounds::new(4000e-10, 5000e-10),
+            Bounds::new(7000e-10, 8000e-10),
+        ];
+        let times = [0.0, 3.0, 30.0];
+        let sim = Simulation::from_config(Path::new("examples/sun.toml")).unwrap();
+        let flux = sim.observe_flux_bands(&times, &bands).unwrap();
+        let ccfs = sim.observe_rv_bands(&times, &bands).unwrap();
+        for (b, band) in bands.iter().enumerate() {
+            let separate_flux = sim.observe_flux(&times, *band).unwrap();
+            let separate_ccfs = sim.observe_rv(&times, *band).unwrap();
+            for t in 0..times.len() {
+                assert!((flux[t][b] - separate_flux[t]).abs() < 1e-12);
+                for (a, b) in ccfs[t][b].iter().zip(separate_ccfs[t].iter()) {
+                    assert!((a - b).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn line_groups_respond_differently() {
+        let sim = Simulation::from_config(Path::new("examples/line_groups.toml")).unwrap();
+        let groups = sim
+            .observe_line_groups(&[0.0, 12.5], Bounds::new(4000e-10, 7000e-10))
+            .unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].len(), 2);
+
+        // With the spot hidden, each group is its quiet CCF
+        for (ccf, group) in groups[1].iter().zip(sim.star.line_groups.iter()) {
+            assert_eq!(ccf, &group.integrated_ccf);
+        }
+        let change = |g: usize| -> f64 {
+            groups[0][g]
+                .iter()
+                .zip(sim.star.line_groups[g].integrated_ccf.iter())
+                .map(|(a, b)| (a - b).abs())
+                .sum()
+        };
+        assert!(change(0) > 0.0 && change(1) > 0.0);
+        assert!(change(0) != change(1));
+    }
+
+    #[test]
+    fn synthesized_spectrum_sees_spots() {
+        let sim = Simulation::from_config(Path::new("examples/synthesis.toml")).unwrap();
+        let len = sim.spectrum_wavelength().unwrap().len();
+        // The spot faces the observer at the first time, and is hidden at the second
+        let spectra = sim.observe_spectrum(&[0.0, 12.5]).unwrap();
+        assert_eq!(spectra[0].len(), len);
+        assert!(spectra[0] != sim.star.integrated_spectrum);
+        assert_eq!(spectra[1], sim.star.integrated_spectrum);
+
+        assert!(Simulation::sun().observe_spectrum(&[0.0]).is_err());
+    }
+
+    #[test]
+    fn catalog_regions_come_and_go() {
+        let sim = Simulation::from_config(Path::new("examples/catalog.toml")).unwrap();
+        let flux = sim
+            .observe_flux(&[-1.0, 3.0, 30.0], Bounds::new(4000e-10, 7000e-10))
+            .unwrap();
+        assert_eq!(flux[0], 1.0);
+        assert_ne!(flux[1], 1.0);
+        assert_eq!(flux[2], 1.0);
+    }
+
+    #[test]
+    fn catalog_plages_brighten_the_star() {
+        let mut sim = Simulation::from_config(Path::new("examples/catalog.toml")).unwrap();
+        if let Some(catalog) = &mut sim.catalog {
+            catalog.tracks.retain(|track| track.plage);
+        }
+        let flux = sim
+            .observe_flux(&[3.0], Bounds::new(4000e-10, 7000e-10))
+            .unwrap();
+        assert!(flux[0] > 1.0);
+    }
+
+    #[test]
+    fn surface_map_dims_the_star() {
+        let sim = Simulation::from_config(Path::new("examples/surface.toml")).unwrap();
+        let flux = sim
+            .observe_flux(&[0.0, 12.5, 24.0], Bounds::new(4000e-10, 7000e-10))
+            .unwrap();
+        // The active region is on the far side of the star half a rotation later
+        assert!(flux[0] < 1.0);
+        assert_eq!(flux[1], 1.0);
+        // And has grown by the time it comes back around
+        assert!(flux[2] < flux[0]);
     }
 }
 
@@ -0,0 +1,306 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bounds::Bounds;
+use crate::error::LatherError;
+use crate::planck::planck_integral;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ModelSpectrumConfig {
+    pub temperature: f64,
+    pub path: PathBuf,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "name")]
+pub enum SpectralModelConfig {
+    #[serde(rename = "blackbody")]
+    Blackbody,
+    #[serde(rename = "tabulated")]
+    Tabulated { models: Vec<ModelSpectrumConfig> },
+}
+
+impl SpectralModelConfig {
+    /// Makes any relative paths in this config relative to `dir` instead.
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        if let SpectralModelConfig::Tabulated { models } = self {
+            for model in models {
+                model.path = dir.join(&model.path);
+            }
+        }
+    }
+}
+
+/// A model spectrum for a single effective temperature, with wavelengths in
+/// meters and flux in arbitrary but consistent units.
+#[derive(Debug, Clone)]
+pub struct ModelSpectrum {
+    pub temperature: f64,
+    pub wavelength: Vec<f64>,
+    pub flux: Vec<f64>,
+}
+
+impl ModelSpectrum {
+    pub fn new(
+        temperature: f64,
+        wavelength: Vec<f64>,
+        flux: Vec<f64>,
+    ) -> Result<Self, LatherError> {
+        if wavelength.len() != flux.len() || wavelength.len() < 2 {
+            return Err(LatherError::Validation(format!(
+                "The {} K model spectrum must have at least two points and the same number of wavelengths and fluxes",
+                temperature
+            )));
+        }
+        if !temperature.is_finite() || wavelength.iter().chain(flux.iter()).any(|x| !x.is_finite())
+        {
+            return Err(LatherError::Validation(format!(
+                "The {} K model spectrum must have a finite temperature, wavelengths, and fluxes",
+                temperature
+            )));
+        }
+        if wavelength.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(LatherError::Validation(format!(
+                "The wavelengths of the {} K model spectrum must be strictly increasing",
+                temperature
+            )));
+        }
+        Ok(ModelSpectrum {
+            temperature,
+            wavelength,
+            flux,
+        })
+    }
+
+    /// Loads a model spectrum from a text file of whitespace-separated wavelength (m)
+    /// and flux columns. Lines starting with `#` are ignored.
+    pub fn from_file(temperature: f64, path: &Path) -> Result<Self, LatherError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| LatherError::io(path, e))?;
+
+        let mut wavelength = Vec::new();
+        let mut flux = Vec::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace().map(|f| f.parse::<f64>());
+            match (fields.next(), fields.next()) {
+                (Some(Ok(w)), Some(Ok(f))) => {
+                    wavelength.push(w);
+                    flux.push(f);
+                }
+                _ => {
+                    return Err(LatherError::parse(
+                        path,
+                        format!(
+                            "Line {} should be `wavelength flux`, but is {:?}",
+                            line_number + 1,
+                            line
+                        ),
+                    ))
+                }
+            }
+        }
+
+        ModelSpectrum::new(temperature, wavelength, flux)
+    }
+
+    /// Integrates the flux over the wavelength band with the trapezoid rule. The band
+    /// must be inside the spectrum's wavelength range.
+    pub fn integral(&self, band: Bounds) -> Result<f64, LatherError> {
+        let lower = self.wavelength[0];
+        let upper = self.wavelength[self.wavelength.len() - 1];
+        if band.lower < lower || band.upper > upper {
+            return Err(LatherError::Validation(format!(
+                "The {} K model spectrum covers {} m to {} m, which does not include the band {} m to {} m",
+                self.temperature, lower, upper, band.lower, band.upper
+            )));
+        }
+
+        let interpolate = |w: f64| {
+            let i = self
+                .wavelength
+                .partition_point(|x| *x < w)
+                .clamp(1, self.wavelength.len() - 1);
+            let fraction =
+                (w - self.wavelength[i - 1]) / (self.wavelength[i] - self.wavelength[i - 1]);
+            self.flux[i - 1] + fraction * (self.flux[i] - self.flux[i - 1])
+        };
+
+        let (lower, upper) = (band.lower, band.upper);
+        if lower >= upper {
+            return Ok(0.0);
+        }
+
+        let mut points = vec![(lower, interpolate(lower))];
+        points.extend(
+            self.wavelength
+                .iter()
+                .zip(self.flux.iter())
+                .filter(|(w, _)| **w > lower && **w < upper)
+                .map(|(w, f)| (*w, *f)),
+        );
+        points.push((upper, interpolate(upper)));
+
+        Ok(points
+            .windows(2)
+            .map(|p| 0.5 * (p[1].0 - p[0].0) * (p[0].1 + p[1].1))
+            .sum())
+    }
+}
+
+/// How the emergent spectrum of the photosphere and its features depends on temperature.
+#[derive(Debug, Clone, Default)]
+pub enum SpectralModel {
+    #[default]
+    Blackbody,
+    /// Model spectra sorted by temperature, linearly interpolated in between
+    Tabulated(Vec<ModelSpectrum>),
+}
+
+impl SpectralModel {
+    pub fn from_config(config: &SpectralModelConfig) -> Result<Self, LatherError> {
+        match config {
+            SpectralModelConfig::Blackbody => Ok(SpectralModel::Blackbody),
+            SpectralModelConfig::Tabulated { models } => {
+                let spectra = models
+                    .iter()
+                    .map(|m| ModelSpectrum::from_file(m.temperature, &m.path))
+                    .collect::<Result<Vec<_>, _>>()?;
+                SpectralModel::tabulated(spectra)
+            }
+        }
+    }
+
+    pub fn tabulated(mut spectra: Vec<ModelSpectrum>) -> Result<Self, LatherError> {
+        if spectra.is_empty() {
+            return Err(LatherError::Validation(
+                "A tabulated spectral model needs at least one model spectrum".to_string(),
+            ));
+        }
+        spectra.sort_by(|a, b| a.temperature.total_cmp(&b.temperature));
+        if spectra
+            .windows(2)
+            .any(|s| s[0].temperature == s[1].temperature)
+        {
+            return Err(LatherError::Validation(
+                "A tabulated spectral model has two model spectra with the same temperature"
+                    .to_string(),
+            ));
+        }
+        Ok(SpectralModel::Tabulated(spectra))
+    }
+
+    /// Returns the range of temperatures this model can describe, if it is limited.
+    pub fn temperature_range(&self) -> Option<Bounds> {
+        match self {
+            SpectralModel::Blackbody => None,
+            SpectralModel::Tabulated(spectra) => Some(Bounds::new(
+                spectra[0].temperature,
+                spectra[spectra.len() - 1].temperature,
+            )),
+        }
+    }
+
+    /// Computes the emergent flux integrated over the wavelength band for a
+    /// surface at `temperature`. Tabulated models must cover both the temperature and
+    /// the band.
+    pub fn integral(&self, temperature: f64, band: Bounds) -> Result<f64, LatherError> {
+        match self {
+            SpectralModel::Blackbody => Ok(planck_integral(temperature, band.lower, band.upper)),
+            SpectralModel::Tabulated(spectra) => {
+                let range = self.temperature_range().unwrap();
+                if !(range.lower..=range.upper).contains(&temperature) {
+                    return Err(LatherError::Validation(format!(
+                        "The model spectra cover {} K to {} K, which does not include {} K",
+                        range.lower, range.upper, temperature
+                    )));
+                }
+                let above = spectra
+                    .partition_point(|s| s.temperature < temperature)
+                    .max(1);
+                let (cool, hot) = match spectra.get(above) {
+                    Some(hot) => (&spectra[above - 1], hot),
+                    // A single model spectrum, at exactly this temperature
+                    None => return spectra[0].integral(band),
+                };
+                let fraction =
+                    (temperature - cool.temperature) / (hot.temperature - cool.temperature);
+                let cool_integral = cool.integral(band)?;
+                Ok(cool_integral + fraction * (hot.integral(band)? - cool_integral))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blackbody_spectrum(temperature: f64) -> ModelSpectrum {
+        let wavelength: Vec<f64> = (0..=3000).map(|i| 3000e-10 + i as f64 * 2e-10).collect();
+        let flux = wavelength
+            .windows(2)
+            .map(|w| planck_integral(temperature, w[0], w[1]) / (w[1] - w[0]))
+            .chain(std::iter::once(0.0))
+            .collect::<Vec<_>>();
+        let wavelength = wavelength
+            .windows(2)
+            .map(|w| 0.5 * (w[0] + w[1]))
+            .chain(std::iter::once(9000e-10))
+            .collect();
+        ModelSpectrum::new(temperature, wavelength, flux).unwrap()
+    }
+
+    #[test]
+    fn tabulated_blackbody_matches() {
+        let model =
+            SpectralModel::tabulated(vec![blackbody_spectrum(5000.0), blackbody_spectrum(6000.0)])
+                .unwrap();
+        let band = Bounds::new(4000e-10, 7000e-10);
+        for &temperature in &[5000.0, 6000.0] {
+            let expected = planck_integral(temperature, band.lower, band.upper);
+            let actual = model.integral(temperature, band).unwrap();
+            assert!((actual - expected).abs() / expected < 1e-4);
+        }
+    }
+
+    #[test]
+    fn interpolates_in_temperature() {
+        let flat = |temperature, value| {
+            ModelSpectrum::new(temperature, vec![0.0, 1.0], vec![value, value]).unwrap()
+        };
+        let model = SpectralModel::tabulated(vec![flat(6000.0, 3.0), flat(5000.0, 1.0)]).unwrap();
+        let band = Bounds::new(0.0, 1.0);
+        assert_eq!(model.integral(5500.0, band).unwrap(), 2.0);
+        assert_eq!(model.integral(5000.0, band).unwrap(), 1.0);
+        assert_eq!(model.integral(6000.0, band).unwrap(), 3.0);
+        assert_eq!(
+            model.integral(5000.0, Bounds::new(0.25, 0.75)).unwrap(),
+            0.5
+        );
+    }
+
+    #[test]
+    fn rejects_what_the_model_does_not_cover() {
+        let flat =
+            |temperature| ModelSpectrum::new(temperature, vec![0.0, 1.0], vec![1.0, 1.0]).unwrap();
+        let model = SpectralModel::tabulated(vec![flat(5000.0), flat(6000.0)]).unwrap();
+        assert!(model.integral(4000.0, Bounds::new(0.0, 1.0)).is_err());
+        assert!(model.integral(7000.0, Bounds::new(0.0, 1.0)).is_err());
+        assert!(model.integral(5500.0, Bounds::new(0.5, 2.0)).is_err());
+    }
+
+    #[test]
+    fn rejects_unsorted_wavelengths() {
+        assert!(ModelSpectrum::new(5000.0, vec![1.0, 0.0], vec![1.0, 1.0]).is_err());
+    }
+
+    #[test]
+    fn rejects_nan() {
+        assert!(ModelSpectrum::new(f64::NAN, vec![0.0, 1.0], vec![1.0, 1.0]).is_err());
+        assert!(ModelSpectrum::new(5000.0, vec![0.0, 1.0], vec![1.0, f64::NAN]).is_err());
+    }
+}
@@ -4,17 +4,21 @@ use serde::{Deserialize, Serialize};
 
 use crate::boundingshape::BoundingShape;
 use crate::bounds::Bounds;
+use crate::error::LatherError;
 use crate::linspace::floatrange;
+use crate::phase_grid::PhaseGrid;
+use crate::profile::Profile;
 use crate::solar_ccfs::CCF_LEN;
 use crate::star::Star;
 
-#[derive(Debug, Deserialize, Serialize)]
+/// When a spot appears and disappears, in days
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct Lifetime {
-    start: f64,
-    end: f64,
+    pub start: f64,
+    pub end: f64,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct SpotConfig {
     pub latitude: f64,
     pub longitude: f64,
@@ -35,7 +39,12 @@ pub struct Spot {
     pub temperature: f64,
     pub plage: bool,
     pub mortality: Mortality,
-    pub intensity: f64,
+    /// Whether this spot was generated randomly to maintain the star's fill factor
+    pub generated: bool,
+    /// Precomputed integrals over a rotation, only valid for immortal spots
+    pub phase_grid: Option<Arc<PhaseGrid>>,
+    /// The spacing of the strips this spot is integrated over, instead of the star's grid
+    pub strip_step: Option<f64>,
 }
 
 impl std::fmt::Debug for Spot {
@@ -47,28 +56,97 @@ impl std::fmt::Debug for Spot {
             .field("temperature", &self.temperature)
             .field("plage", &self.plage)
             .field("mortality", &self.mortality)
-            .field("intensity", &self.intensity)
+            .field("generated", &self.generated)
+            .field("phase_grid", &self.phase_grid.is_some())
+            .field("strip_step", &self.strip_step)
             .finish()
     }
 }
 
-#[derive(Debug, Clone)]
+impl SpotConfig {
+    /// An immortal spot at the latitude and longitude (in degrees) that covers
+    /// `fill_factor` of the star, at the star's spot temperature.
+    pub fn new(latitude: f64, longitude: f64, fill_factor: f64) -> SpotConfig {
+        SpotConfig {
+            latitude,
+            longitude,
+            fill_factor,
+            plage: false,
+            temperature: None,
+            lifetime: None,
+        }
+    }
+
+    /// Sets the temperature of the spot in Kelvin.
+    pub fn with_temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Makes the spot only exist between `start` and `end`, in days.
+    pub fn with_lifetime(mut self, start: f64, end: f64) -> Self {
+        self.lifetime = Some(Lifetime { start, end });
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub enum Mortality {
+    #[default]
     Immortal,
     Mortal(Bounds),
 }
 
-impl Default for Mortality {
-    fn default() -> Self {
-        Mortality::Immortal
-    }
-}
-
 impl Spot {
     /// Create a new spot on `star` and at the coordinates specified, where
     /// latitude is 0 at the equator and both latitude and longitude are
     /// measured in degrees.
-    pub fn from_config(star: Arc<Star>, config: &SpotConfig) -> Spot {
+    pub fn from_config(star: Arc<Star>, config: &SpotConfig) -> Result<Spot, LatherError> {
+        let invalid = |message: String| Err(LatherError::Validation(message));
+        if !(-90.0..=90.0).contains(&config.latitude) || !config.longitude.is_finite() {
+            return invalid(format!(
+                "A spot must be at a latitude from -90 to 90 degrees and a finite longitude, \
+                 but is at {} and {}",
+                config.latitude, config.longitude
+            ));
+        }
+        // Spots covering half of the star or more don't fit on one side of it
+        if !(config.fill_factor > 0.0 && config.fill_factor < 0.5) {
+            return invalid(format!(
+                "A spot's fill factor must be more than 0 and less than 0.5, but is {}",
+                config.fill_factor
+            ));
+        }
+        if let Some(temperature) = config.temperature {
+            if !(temperature > 0.0 && temperature.is_finite()) {
+                return invalid(format!(
+                    "A spot's temperature must be more than 0 K, but is {}",
+                    temperature
+                ));
+            }
+            if let Some(range) = star.spectral_model.temperature_range() {
+                if !(range.lower..=range.upper).contains(&temperature) {
+                    return invalid(format!(
+                        "The model spectra cover {} K to {} K, which does not include the \
+                         spot temperature {} K",
+                        range.lower, range.upper, temperature
+                    ));
+                }
+            }
+        }
+        if let Some(lifetime) = &config.lifetime {
+            if lifetime.start.is_nan() || lifetime.end.is_nan() {
+                return invalid(format!(
+                    "A spot's lifetime must start and end at a time, but is {:?}",
+                    lifetime
+                ));
+            }
+        }
+        Ok(Spot::from_valid_config(star, config))
+    }
+
+    /// Creates a spot like `from_config`, from a config known to be valid.
+    pub(crate) fn from_valid_config(star: Arc<Star>, config: &SpotConfig) -> Spot {
         if config.plage {
             println!("Warning: you've configured a spot as a plage but this doesn't actually do anything yet");
         }
@@ -95,66 +173,130 @@ impl Spot {
                     Mortality::Mortal(Bounds::new(lifetime.start, lifetime.end))
                 })
                 .unwrap_or(Mortality::Immortal),
-            intensity: 0.0,
+            generated: false,
+            phase_grid: None,
+            strip_step: None,
         }
     }
 
-    pub fn get_flux(&self, time: f64) -> f64 {
+    /// Returns the flux this spot blocks, when its brightness relative to the quiet
+    /// photosphere is `intensity`.
+    pub fn get_flux(&self, time: f64, intensity: f64) -> f64 {
+        (1.0 - intensity) * self.limb_integral(time)
+    }
+
+    /// Returns the limb-darkened area this spot covers, in the same units as
+    /// `Star::flux_quiet`. This does not depend on the spot's intensity.
+    pub fn limb_integral(&self, time: f64) -> f64 {
+        if let Some(grid) = &self.phase_grid {
+            return grid.limb_integral(time);
+        }
         let bounds = BoundingShape::new(self, time);
         if let Some(y_bounds) = bounds.y_bounds() {
-            let mut current_z_bounds = None;
-
-            let limb_integral: f64 = floatrange(
-                y_bounds.lower,
-                y_bounds.upper,
-                2.0 / self.star.grid_size as f64,
-            )
-            .map(|y| {
-                if let Some(z_bounds) = bounds.z_bounds(y, &mut current_z_bounds) {
-                    self.star.limb_integral(&z_bounds, y)
-                } else {
-                    0.0
-                }
-            })
-            .sum();
-            (1.0 - self.intensity) * limb_integral
+            let limb_integral: f64 = floatrange(y_bounds.lower, y_bounds.upper, self.strip_step())
+                .map(|y| {
+                    if let Some(z_bounds) = bounds.z_bounds(y) {
+                        self.star.limb_integral(&z_bounds, y)
+                    } else {
+                        0.0
+                    }
+                })
+                .sum();
+            limb_integral * self.strip_weight()
         } else {
             0.0
         }
     }
 
-    pub fn get_ccf(&self, time: f64) -> [f64; CCF_LEN] {
+    pub fn get_ccf(&self, time: f64, intensity: f64) -> [f64; CCF_LEN] {
         let mut profile = [0.0; CCF_LEN];
-        let mut quiet_shifted = [0.0; CCF_LEN];
-        let mut active_shifted = [0.0; CCF_LEN];
+        self.add_profile(
+            time,
+            &self.star.profile_quiet,
+            &self.star.profile_spot,
+            intensity,
+            &mut profile,
+        );
+        profile
+    }
+
+    /// Adds the change this spot makes to the disk-integrated `quiet` profile
+    /// to `profile`, where `active` is the profile of the spot itself and `intensity`
+    /// is its brightness relative to the quiet photosphere.
+    pub fn add_profile(
+        &self,
+        time: f64,
+        quiet: &Profile,
+        active: &Profile,
+        intensity: f64,
+        profile: &mut [f64],
+    ) {
+        let mut quiet_covered = vec![0.0; quiet.len()];
+        let mut active_covered = vec![0.0; active.len()];
+        self.add_profile_components(time, quiet, active, &mut quiet_covered, &mut active_covered);
+        for ((tot, q), a) in profile
+            .iter_mut()
+            .zip(quiet_covered.iter())
+            .zip(active_covered.iter())
+        {
+            *tot += q - intensity * a;
+        }
+    }
+
+    /// Adds the `quiet` and `active` profiles integrated over the area this spot covers
+    /// to `quiet_covered` and `active_covered`. These do not depend on the spot's intensity,
+    /// and the change the spot makes to the disk-integrated profile is
+    /// `quiet_covered - intensity * active_covered`.
+    pub fn add_profile_components(
+        &self,
+        time: f64,
+        quiet: &Profile,
+        active: &Profile,
+        quiet_covered: &mut [f64],
+        active_covered: &mut [f64],
+    ) {
+        if let Some(grid) = &self.phase_grid {
+            if std::ptr::eq(quiet, &self.star.profile_quiet)
+                && std::ptr::eq(active, &self.star.profile_spot)
+            {
+                grid.add_profile_components(time, quiet_covered, active_covered);
+                return;
+            }
+        }
+        let mut quiet_shifted = vec![0.0; quiet.len()];
+        let mut active_shifted = vec![0.0; active.len()];
         let bounds = BoundingShape::new(self, time);
-        let mut current_z_bounds = None;
         if let Some(y_bounds) = bounds.y_bounds() {
-            for y in floatrange(
-                y_bounds.lower,
-                y_bounds.upper,
-                2.0 / self.star.grid_size as f64,
-            ) {
-                self.star
-                    .profile_quiet
-                    .shift_into(y * self.star.equatorial_velocity, &mut quiet_shifted);
-                self.star
-                    .profile_spot
-                    .shift_into(y * self.star.equatorial_velocity, &mut active_shifted);
-
-                if let Some(z_bounds) = bounds.z_bounds(y, &mut current_z_bounds) {
-                    let limb_integral = self.star.limb_integral(&z_bounds, y);
-                    for ((tot, qshift), ashift) in profile
-                        .iter_mut()
-                        .zip(quiet_shifted.iter())
-                        .zip(active_shifted.iter())
-                    {
-                        *tot += (qshift - self.intensity * ashift) * limb_integral;
+            let weight = self.strip_weight();
+            for y in floatrange(y_bounds.lower, y_bounds.upper, self.strip_step()) {
+                quiet.shift_into(y * self.star.equatorial_velocity, &mut quiet_shifted);
+                active.shift_into(y * self.star.equatorial_velocity, &mut active_shifted);
+
+                if let Some(z_bounds) = bounds.z_bounds(y) {
+                    let limb_integral = self.star.limb_integral(&z_bounds, y) * weight;
+                    for (tot, qshift) in quiet_covered.iter_mut().zip(quiet_shifted.iter()) {
+                        *tot += qshift * limb_integral;
+                    }
+                    for (tot, ashift) in active_covered.iter_mut().zip(active_shifted.iter()) {
+                        *tot += ashift * limb_integral;
                     }
                 }
             }
         };
-        profile
+    }
+
+    /// The spacing of the strips this spot is integrated over.
+    pub fn strip_step(&self) -> f64 {
+        self.strip_step.unwrap_or(2.0 / self.star.grid_size as f64)
+    }
+
+    /// Strips are summed without their width, like the quiet star's, so strips of other
+    /// widths are scaled to match the star's grid.
+    fn strip_weight(&self) -> f64 {
+        match self.strip_step {
+            Some(step) => step * self.star.grid_size as f64 / 2.0,
+            None => 1.0,
+        }
     }
 
     /// Returns `true` if this spot currently exists.
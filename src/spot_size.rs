@@ -0,0 +1,229 @@
+use std::convert::TryFrom;
+use std::path::Path;
+
+use rand::Rng;
+use rand_distr::LogNormal;
+use serde::{Deserialize, Serialize};
+
+use crate::bounds::Bounds;
+use crate::distributions::{Distribution, DistributionConfig};
+use crate::error::LatherError;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum SizeUnit {
+    /// Area in millionths of the visible hemisphere (MSH)
+    #[serde(rename = "msh")]
+    MicroHemispheres,
+    /// Angular radius on the stellar surface in degrees
+    #[serde(rename = "degrees")]
+    Degrees,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub enum SizePreset {
+    /// The lognormal distribution of sunspot umbral areas from Bogdan et al. (1988)
+    #[serde(rename = "bogdan1988")]
+    Bogdan1988,
+    /// The size distribution used before sizes had units, a lognormal in units of 9.4 MSH
+    #[serde(rename = "legacy")]
+    Legacy,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum SpotSizeConfig {
+    Preset {
+        preset: SizePreset,
+    },
+    Custom {
+        unit: SizeUnit,
+        distribution: DistributionConfig,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+}
+
+impl SpotSizeConfig {
+    /// Makes any relative paths in this config relative to `dir` instead.
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        if let SpotSizeConfig::Custom { distribution, .. } = self {
+            distribution.resolve_paths(dir);
+        }
+    }
+}
+
+/// The distribution of sizes of randomly generated spots. Sizes outside of `bounds`
+/// are rejected, which truncates the distribution.
+#[derive(Debug)]
+pub struct SpotSize {
+    pub unit: SizeUnit,
+    pub distribution: Distribution,
+    /// Samples are multiplied by this before being compared to `bounds`
+    pub scale: f64,
+    pub bounds: Bounds,
+}
+
+impl SpotSize {
+    /// Builds the spot size distribution from the star's config. `fillfactor_distribution`
+    /// is the older way of configuring sizes, as a distribution in units of 9.4 MSH.
+    pub fn from_config(
+        config: Option<&SpotSizeConfig>,
+        fillfactor_distribution: Option<&DistributionConfig>,
+    ) -> Result<SpotSize, LatherError> {
+        match (config, fillfactor_distribution) {
+            (Some(_), Some(_)) => Err(LatherError::Validation(
+                "The star config has both a spot_size and a fillfactor_distribution, but only one can be used"
+                    .to_string(),
+            )),
+            (None, legacy) => Ok(SpotSize::legacy(match legacy {
+                Some(distribution) => Distribution::try_from(distribution.clone())?,
+                None => legacy_distribution(),
+            })),
+            (Some(SpotSizeConfig::Preset { preset }), None) => Ok(match preset {
+                SizePreset::Bogdan1988 => SpotSize::bogdan1988(),
+                SizePreset::Legacy => SpotSize::legacy(legacy_distribution()),
+            }),
+            (
+                Some(SpotSizeConfig::Custom {
+                    unit,
+                    distribution,
+                    min,
+                    max,
+                }),
+                None,
+            ) => {
+                let min = min.unwrap_or(0.0);
+                let max = max.unwrap_or(match unit {
+                    SizeUnit::MicroHemispheres => 1000.0,
+                    SizeUnit::Degrees => SpotSize::fill_factor_to_degrees(0.001),
+                });
+                if min.is_nan() || max.is_nan() || min < 0.0 || min >= max {
+                    return Err(LatherError::Validation(format!(
+                        "The spot size bounds must satisfy 0 <= min < max, but are {} and {}",
+                        min, max
+                    )));
+                }
+                Ok(SpotSize {
+                    unit: *unit,
+                    distribution: Distribution::try_from(distribution.clone())?,
+                    scale: 1.0,
+                    bounds: Bounds::new(min, max),
+                })
+            }
+        }
+    }
+
+    /// Sizes in units of 9.4 MSH, truncated at 1000 MSH.
+    pub fn legacy(distribution: Distribution) -> SpotSize {
+        SpotSize {
+            unit: SizeUnit::MicroHemispheres,
+            distribution,
+            scale: 9.4,
+            bounds: Bounds::new(0.0, 1000.0),
+        }
+    }
+
+    /// Bogdan et al. (1988) fit dN/dA = C exp(-(ln A - ln <A>)^2 / (2 ln sigma_A)) to sunspot
+    /// umbral areas A, with <A> = 0.62 MSH and sigma_A = 3.80. As a probability density in A
+    /// that is a lognormal whose log mean is shifted up by ln sigma_A.
+    pub fn bogdan1988() -> SpotSize {
+        let log_variance = 3.8f64.ln();
+        SpotSize {
+            unit: SizeUnit::MicroHemispheres,
+            distribution: Distribution::LogNormal(
+                LogNormal::new(0.62f64.ln() + log_variance, log_variance.sqrt()).unwrap(),
+            ),
+            scale: 1.0,
+            bounds: Bounds::new(0.0, 1000.0),
+        }
+    }
+
+    /// Draws one size, returning its fill factor, or `None` if it falls outside of the bounds.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<f64> {
+        let size = self.distribution.sample(rng) * self.scale;
+        if size >= self.bounds.lower && size < self.bounds.upper {
+            Some(self.fill_factor(size))
+        } else {
+            None
+        }
+    }
+
+    /// Converts a size in this distribution's unit to the fraction of the star it covers.
+    pub fn fill_factor(&self, size: f64) -> f64 {
+        match self.unit {
+            SizeUnit::MicroHemispheres => size * 1e-6,
+            // A small cap of angular radius r covers r^2 / 2 of the star in this model
+            SizeUnit::Degrees => size.to_radians().powi(2) / 2.0,
+        }
+    }
+
+    fn fill_factor_to_degrees(fill_factor: f64) -> f64 {
+        (2.0 * fill_factor).sqrt().to_degrees()
+    }
+}
+
+/// The default size distribution before sizes had units
+fn legacy_distribution() -> Distribution {
+    Distribution::LogNormal(LogNormal::new(0.5, 4.0).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn units_agree() {
+        let msh = SpotSize::legacy(Distribution::LogNormal(LogNormal::new(0.0, 1.0).unwrap()));
+        let degrees = SpotSize {
+            unit: SizeUnit::Degrees,
+            ..SpotSize::legacy(Distribution::LogNormal(LogNormal::new(0.0, 1.0).unwrap()))
+        };
+        let radius = SpotSize::fill_factor_to_degrees(msh.fill_factor(300.0));
+        assert!((degrees.fill_factor(radius) - 300e-6).abs() < 1e-15);
+    }
+
+    #[test]
+    fn bogdan_median() {
+        let size = SpotSize::bogdan1988();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut areas: Vec<f64> = (0..10001)
+            .filter_map(|_| size.sample(&mut rng))
+            .map(|f| f / 1e-6)
+            .collect();
+        areas.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        // The median of the density is <A> sigma_A
+        let median = areas[areas.len() / 2];
+        assert!((median / (0.62 * 3.8) - 1.0).abs() < 0.05);
+        assert!(areas.iter().all(|a| *a < 1000.0));
+    }
+
+    #[test]
+    fn truncation_is_explicit() {
+        let config: SpotSizeConfig = toml::from_str(
+            "unit = \"degrees\"\nmin = 1.0\nmax = 2.0\ndistribution = {name = \"uniform\", min = 0.0, max = 3.0}",
+        )
+        .unwrap();
+        let size = SpotSize::from_config(Some(&config), None).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let samples: Vec<Option<f64>> = (0..1000).map(|_| size.sample(&mut rng)).collect();
+        assert!(samples.iter().any(|s| s.is_none()));
+        for fill_factor in samples.into_iter().flatten() {
+            let radius = SpotSize::fill_factor_to_degrees(fill_factor);
+            assert!((1.0..2.0).contains(&radius));
+        }
+    }
+
+    #[test]
+    fn rejects_both_configs() {
+        let config = SpotSizeConfig::Preset {
+            preset: SizePreset::Bogdan1988,
+        };
+        let legacy = DistributionConfig::Normal {
+            mean: 1.0,
+            std_dev: 0.1,
+        };
+        assert!(SpotSize::from_config(Some(&config), Some(&legacy)).is_err());
+    }
+}
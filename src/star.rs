@@ -1,18 +1,30 @@
+use std::convert::TryFrom;
 use std::f64::consts;
+use std::path::{Path, PathBuf};
 
-use rand_distr::{LogNormal, Uniform};
+use rand_distr::Uniform;
 use serde::{Deserialize, Serialize};
 
 use crate::bounds::Bounds;
 use crate::distributions::{Distribution, DistributionConfig};
+use crate::error::LatherError;
+use crate::integration_cache::{integrate_cached, IntegrationKey};
+use crate::line_group::{LineGroup, LineGroupConfig};
 use crate::linspace::linspace;
-use crate::profile::Profile;
+use crate::profile::{Doppler, Interpolation, Profile};
 use crate::solar_ccfs::{CCF_QUIET, RV};
+use crate::spectral_model::{SpectralModel, SpectralModelConfig};
+use crate::spot_size::{SpotSize, SpotSizeConfig};
+use crate::synthesis::{Synthesis, SynthesisConfig};
 
 const SOLAR_RADIUS: f64 = 6.96e8;
 const DAYS_TO_SECONDS: f64 = 86400.0;
 
-#[derive(Deserialize, Serialize)]
+/// The width and height in pixels of images drawn with `draw_bgr`, which are BGR with
+/// one byte per channel.
+pub const IMAGE_SIZE: usize = 1000;
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct StarConfig {
     pub grid_size: usize,
     pub radius: f64,
@@ -24,10 +36,56 @@ pub struct StarConfig {
     pub limb_quadratic: f64,
     pub minimum_fill_factor: Option<f64>,
     pub target_fill_factor: Option<f64>,
+    pub max_size_draws: Option<usize>,
+    pub max_placement_attempts: Option<usize>,
     pub latitude_distribution: Option<DistributionConfig>,
     pub longitude_distribution: Option<DistributionConfig>,
     pub fillfactor_distribution: Option<DistributionConfig>,
+    pub spot_size: Option<SpotSizeConfig>,
     pub lifetime_distribution: Option<DistributionConfig>,
+    pub spectral_model: Option<SpectralModelConfig>,
+    pub synthesis: Option<SynthesisConfig>,
+    pub line_groups: Option<Vec<LineGroupConfig>>,
+    /// A directory to keep integrated quiet stars in, which are reused by any star with
+    /// the same rotation, grid size, limb darkening, and profiles. Building a star fails
+    /// if its integral can't be written there.
+    pub cache_dir: Option<PathBuf>,
+    /// How profiles are interpolated when they are Doppler shifted, linear by default
+    pub interpolation: Option<Interpolation>,
+    /// Use the exact relativistic Doppler shift instead of the first-order one
+    pub relativistic_doppler: Option<bool>,
+}
+
+impl StarConfig {
+    /// Makes any relative paths in this config relative to `dir` instead.
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        if let Some(spectral_model) = &mut self.spectral_model {
+            spectral_model.resolve_paths(dir);
+        }
+        if let Some(synthesis) = &mut self.synthesis {
+            synthesis.resolve_paths(dir);
+        }
+        for line_group in self.line_groups.iter_mut().flatten() {
+            line_group.resolve_paths(dir);
+        }
+        for distribution in [
+            &mut self.latitude_distribution,
+            &mut self.longitude_distribution,
+            &mut self.fillfactor_distribution,
+            &mut self.lifetime_distribution,
+        ]
+        .iter_mut()
+        .filter_map(|d| d.as_mut())
+        {
+            distribution.resolve_paths(dir);
+        }
+        if let Some(spot_size) = &mut self.spot_size {
+            spot_size.resolve_paths(dir);
+        }
+        if let Some(cache_dir) = &mut self.cache_dir {
+            *cache_dir = dir.join(&*cache_dir);
+        }
+    }
 }
 
 /// A star that can host spots
@@ -42,14 +100,24 @@ pub struct Star {
     pub flux_quiet: f64,
     pub equatorial_velocity: f64,
     pub target_fill_factor: f64,
+    /// Give up drawing sizes for a random spot after this many are too large
+    pub max_size_draws: usize,
+    /// Give up placing random spots after this many collisions in a row
+    pub max_placement_attempts: usize,
     pub integrated_ccf: Vec<f64>,
     pub profile_spot: Profile,
     pub profile_quiet: Profile,
     pub latitude_distribution: Distribution,
     pub longitude_distribution: Distribution,
-    pub fillfactor_distribution: Distribution,
+    pub spot_size: SpotSize,
     pub lifetime_distribution: Distribution,
+    pub spectral_model: SpectralModel,
+    pub synthesis: Option<Synthesis>,
+    pub integrated_spectrum: Vec<f64>,
+    pub line_groups: Vec<LineGroup>,
     image: std::sync::Mutex<Option<Vec<u8>>>,
+    /// The config this star was built from, for saving the simulation's state
+    config: StarConfig,
 }
 
 impl std::fmt::Debug for Star {
@@ -65,70 +133,153 @@ impl std::fmt::Debug for Star {
             .field("flux_quiet", &self.flux_quiet)
             .field("equatorial_velocity", &self.equatorial_velocity)
             .field("target_fill_factor", &self.target_fill_factor)
+            .field("max_size_draws", &self.max_size_draws)
+            .field("max_placement_attempts", &self.max_placement_attempts)
             .field("latitude_distribution", &self.latitude_distribution)
             .field("longitude_distribution", &self.longitude_distribution)
-            .field("fillfactor_distribution", &self.fillfactor_distribution)
+            .field("spot_size", &self.spot_size)
             .field("lifetime_distribution", &self.lifetime_distribution)
+            .field("spectral_model", &self.spectral_model)
             .finish()
     }
 }
 
 impl Star {
-    pub fn from_config(config: &StarConfig) -> Star {
-        let sqrt = f64::sqrt;
+    /// The config this star was built from. Rebuilding it from this gives an identical star.
+    pub fn config(&self) -> &StarConfig {
+        &self.config
+    }
+
+    pub fn from_config(config: &StarConfig) -> Result<Star, LatherError> {
+        if config.grid_size == 0 {
+            return Err(LatherError::Validation(
+                "The grid size must be at least 1".to_string(),
+            ));
+        }
+        for &(name, value) in &[("radius", config.radius), ("period", config.period)] {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(LatherError::Validation(format!(
+                    "The star's {} must be positive, but is {}",
+                    name, value
+                )));
+            }
+        }
+        let target_fill_factor = config
+            .minimum_fill_factor
+            .or(config.target_fill_factor)
+            .unwrap_or(0.0);
+        if !(0.0..1.0).contains(&target_fill_factor) {
+            return Err(LatherError::Validation(format!(
+                "The target fill factor must be at least 0 and less than 1, but is {}",
+                target_fill_factor
+            )));
+        }
+        let spectral_model = match &config.spectral_model {
+            Some(c) => SpectralModel::from_config(c)?,
+            None => SpectralModel::Blackbody,
+        };
+        if let Some(range) = spectral_model.temperature_range() {
+            let spot_temperature = config.temperature - config.spot_temp_diff;
+            for &temperature in &[config.temperature, spot_temperature] {
+                if temperature < range.lower || temperature > range.upper {
+                    return Err(LatherError::Validation(format!(
+                        "The model spectra cover {} K to {} K, which does not include {} K",
+                        range.lower, range.upper, temperature
+                    )));
+                }
+            }
+        }
 
         let edge_velocity =
             (2.0 * consts::PI * config.radius * SOLAR_RADIUS) / (config.period * DAYS_TO_SECONDS);
         let equatorial_velocity = edge_velocity * (config.inclination.to_radians()).sin();
 
-        let profile_quiet = Profile::new(RV.to_vec(), CCF_QUIET.to_vec());
-        let mut integrated_ccf = vec![0.0; profile_quiet.len()];
-        let mut flux_quiet = 0.0;
+        let interpolation = config.interpolation.unwrap_or_default();
+        let doppler = if config.relativistic_doppler.unwrap_or(false) {
+            Doppler::Relativistic
+        } else {
+            Doppler::Classical
+        };
+        if let Interpolation::Lanczos { lobes: 0 } = interpolation {
+            return Err(LatherError::Validation(
+                "Lanczos interpolation needs at least 1 lobe".to_string(),
+            ));
+        }
+        let configure = |profile: Profile| {
+            profile
+                .with_interpolation(interpolation)
+                .with_doppler(doppler)
+        };
 
-        let mut ccf_shifted = vec![0.0; profile_quiet.len()];
-        for y in linspace(-1.0, 1.0, config.grid_size) {
-            profile_quiet.shift_into(y * equatorial_velocity, &mut ccf_shifted);
-            let z_bound = sqrt(1.0 - y.powi(2));
-            if z_bound < std::f64::EPSILON {
-                continue;
-            }
-            let limb_integral = limb_integral(
-                &Bounds::new(-z_bound, z_bound),
-                y,
-                config.limb_linear,
-                config.limb_quadratic,
-            );
-            for (tot, shifted) in integrated_ccf.iter_mut().zip(ccf_shifted.iter()) {
-                *tot += *shifted * limb_integral;
+        let profile_quiet = configure(Profile::new(RV.to_vec(), CCF_QUIET.to_vec()));
+        let (integrated_ccf, flux_quiet) =
+            integrate_disk(&profile_quiet, equatorial_velocity, config)?;
+        if !(flux_quiet.is_finite() && flux_quiet > 0.0) {
+            return Err(LatherError::Numerical(format!(
+                "The quiet star's flux should be positive, but is {}. Check the limb darkening \
+                 coefficients.",
+                flux_quiet
+            )));
+        }
+
+        let synthesis = match &config.synthesis {
+            Some(c) => {
+                let mut synthesis = Synthesis::from_config(
+                    c,
+                    config.temperature,
+                    config.temperature - config.spot_temp_diff,
+                )?;
+                // Synthesized spectra are on a log wavelength grid
+                let doppler = match doppler {
+                    Doppler::Relativistic => Doppler::Logarithmic,
+                    doppler => doppler,
+                };
+                for profile in &mut [&mut synthesis.profile_quiet, &mut synthesis.profile_spot] {
+                    profile.interpolation = interpolation;
+                    profile.doppler = doppler;
+                }
+                Some(synthesis)
             }
-            flux_quiet += limb_integral;
+            None => None,
+        };
+        let integrated_spectrum = match &synthesis {
+            Some(s) => integrate_disk(&s.profile_quiet, equatorial_velocity, config)?.0,
+            None => Vec::new(),
+        };
+
+        let mut line_groups = Vec::new();
+        for line_group in config.line_groups.iter().flatten() {
+            let (profile_quiet, profile_spot) = line_group.load_profiles()?;
+            let (profile_quiet, profile_spot) = (configure(profile_quiet), configure(profile_spot));
+            line_groups.push(LineGroup {
+                name: line_group.name.clone(),
+                integrated_ccf: integrate_disk(&profile_quiet, equatorial_velocity, config)?.0,
+                profile_quiet,
+                profile_spot,
+            });
         }
 
-        let latitude_distribution = config
-            .latitude_distribution
-            .clone()
-            .map(|c| Distribution::from(c))
-            .unwrap_or_else(|| Distribution::Uniform(Uniform::new(-30.0, 30.0)));
-
-        let longitude_distribution = config
-            .longitude_distribution
-            .clone()
-            .map(|c| Distribution::from(c))
-            .unwrap_or_else(|| Distribution::Uniform(Uniform::new(0.0, 360.0)));
-
-        let fillfactor_distribution = config
-            .fillfactor_distribution
-            .clone()
-            .map(|c| Distribution::from(c))
-            .unwrap_or_else(|| Distribution::LogNormal(LogNormal::new(0.5, 4.0).unwrap()));
-
-        let lifetime_distribution = config
-            .lifetime_distribution
-            .clone()
-            .map(|c| Distribution::from(c))
-            .unwrap_or_else(|| Distribution::Uniform(Uniform::new(10.0, 20.0)));
-
-        Star {
+        let latitude_distribution = match &config.latitude_distribution {
+            Some(distribution) => Distribution::try_from(distribution.clone())?,
+            None => Distribution::Uniform(Uniform::new(-30.0, 30.0)),
+        };
+
+        let longitude_distribution = match &config.longitude_distribution {
+            Some(distribution) => Distribution::try_from(distribution.clone())?,
+            None => Distribution::Uniform(Uniform::new(0.0, 360.0)),
+        };
+
+        let spot_size = SpotSize::from_config(
+            config.spot_size.as_ref(),
+            config.fillfactor_distribution.as_ref(),
+        )?;
+
+        let lifetime_distribution = match &config.lifetime_distribution {
+            Some(distribution) => Distribution::try_from(distribution.clone())?,
+            None => Distribution::Uniform(Uniform::new(10.0, 20.0)),
+        };
+
+        Ok(Star {
             period: config.period,
             // Config units are degrees, but we use radians internally
             inclination: config.inclination.to_radians(),
@@ -139,19 +290,31 @@ impl Star {
             grid_size: config.grid_size,
             flux_quiet,
             equatorial_velocity,
-            target_fill_factor: config
-                .minimum_fill_factor
-                .or(config.target_fill_factor)
-                .unwrap_or(0.0),
+            target_fill_factor,
+            max_size_draws: config.max_size_draws.unwrap_or(1000),
+            max_placement_attempts: config.max_placement_attempts.unwrap_or(1000),
             integrated_ccf,
-            profile_spot: Profile::new(crate::RV.to_vec(), crate::CCF_SPOT.to_vec()),
+            profile_spot: configure(Profile::new(crate::RV.to_vec(), crate::CCF_SPOT.to_vec())),
             profile_quiet,
             latitude_distribution,
             longitude_distribution,
-            fillfactor_distribution,
+            spot_size,
             lifetime_distribution,
+            spectral_model,
+            synthesis,
+            integrated_spectrum,
+            line_groups,
             image: std::sync::Mutex::new(None),
-        }
+            config: config.clone(),
+        })
+    }
+
+    /// Computes the brightness of a surface at `temperature` relative to the
+    /// quiet photosphere, when observed in the wavelength band. This fails if the star's
+    /// spectral model doesn't cover the temperature or the band.
+    pub fn intensity(&self, temperature: f64, wavelength: Bounds) -> Result<f64, LatherError> {
+        Ok(self.spectral_model.integral(temperature, wavelength)?
+            / self.spectral_model.integral(self.temperature, wavelength)?)
     }
 
     pub fn limb_integral(&self, z_bounds: &Bounds, y: f64) -> f64 {
@@ -163,22 +326,22 @@ impl Star {
     }
 
     pub fn draw_bgr(&self, image: &mut [u8]) {
-        let mut cache = self.image.lock().unwrap();
+        let mut cache = self.image.lock().unwrap_or_else(|e| e.into_inner());
 
         if let Some(ref cached) = *cache {
-            image.copy_from_slice(&cached);
+            image.copy_from_slice(cached);
             return;
         }
 
-        let color = match &TEMP_TO_RGB.binary_search_by(|k| k.0.cmp(&(self.temperature as u16))) {
-            Ok(v) => TEMP_TO_RGB[*v].1,
-            Err(v) => TEMP_TO_RGB[*v].1,
+        let color = match TEMP_TO_RGB.binary_search_by(|k| k.0.cmp(&(self.temperature as u16))) {
+            Ok(v) => TEMP_TO_RGB[v].1,
+            Err(v) => TEMP_TO_RGB[v].1,
         };
         let color = [color[0] as f64, color[1] as f64, color[2] as f64];
 
         let mut i = 0;
-        for z in linspace(1.0, -1.0, 1000) {
-            for y in linspace(1.0, -1.0, 1000) {
+        for z in linspace(1.0, -1.0, IMAGE_SIZE) {
+            for y in linspace(1.0, -1.0, IMAGE_SIZE) {
                 let intensity = if (y.powi(2) + z.powi(2)) <= 1.0 {
                     let x = f64::max(0.0, 1.0 - (z.powi(2) + y.powi(2)));
                     self.limb_brightness(x)
@@ -186,7 +349,7 @@ impl Star {
                     0.0
                 };
                 // opencv wants these in BGR but the image array has them in RGB
-                image[i + 0] = (color[2] * intensity) as u8;
+                image[i] = (color[2] * intensity) as u8;
                 image[i + 1] = (color[1] * intensity) as u8;
                 image[i + 2] = (color[0] * intensity) as u8;
                 i += 3;
@@ -197,6 +360,56 @@ impl Star {
     }
 }
 
+/// Integrates `profile` over the visible disk, giving the quiet star's profile and flux.
+/// Results are cached in memory and in the config's `cache_dir`, since stars in an
+/// ensemble often only differ in their spots.
+fn integrate_disk(
+    profile: &Profile,
+    equatorial_velocity: f64,
+    config: &StarConfig,
+) -> Result<(Vec<f64>, f64), LatherError> {
+    let key = IntegrationKey::new(
+        profile,
+        equatorial_velocity,
+        config.grid_size,
+        config.limb_linear,
+        config.limb_quadratic,
+    );
+    integrate_cached(key, config.cache_dir.as_deref(), || {
+        integrate_disk_uncached(profile, equatorial_velocity, config)
+    })
+}
+
+fn integrate_disk_uncached(
+    profile: &Profile,
+    equatorial_velocity: f64,
+    config: &StarConfig,
+) -> (Vec<f64>, f64) {
+    let mut integrated = vec![0.0; profile.len()];
+    let mut flux = 0.0;
+
+    let mut shifted = vec![0.0; profile.len()];
+    for y in linspace(-1.0, 1.0, config.grid_size) {
+        profile.shift_into(y * equatorial_velocity, &mut shifted);
+        let z_bound = (1.0 - y.powi(2)).sqrt();
+        if z_bound < f64::EPSILON {
+            continue;
+        }
+        let limb_integral = limb_integral(
+            &Bounds::new(-z_bound, z_bound),
+            y,
+            config.limb_linear,
+            config.limb_quadratic,
+        );
+        for (tot, shifted) in integrated.iter_mut().zip(shifted.iter()) {
+            *tot += *shifted * limb_integral;
+        }
+        flux += limb_integral;
+    }
+
+    (integrated, flux)
+}
+
 pub fn min(a: f64, b: f64) -> f64 {
     if a < b {
         a
@@ -0,0 +1,148 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::bounds::Bounds;
+use crate::catalog::SpotCatalog;
+use crate::convergence::Tolerance;
+use crate::error::LatherError;
+use crate::exposure::ExposureConfig;
+use crate::noise::Instrument;
+use crate::schedule::Schedule;
+use crate::spot::Lifetime;
+use crate::star::StarConfig;
+use crate::surface::Frame;
+
+/// The version of the state format written by this version of lather. It changes whenever
+/// a saved state would no longer restore to the same simulation.
+pub const STATE_FORMAT: u32 = 1;
+
+/// Everything about a simulation at one point in a run: the star, every spot, and how far
+/// each random number generator has got. A simulation restored from its state produces
+/// exactly the same observations, noise, and random spots as the original would have.
+///
+/// Surface maps and catalogs are saved in full, but the spectra, line profiles, and
+/// empirical distributions in the star's config are saved as paths to their files.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SimulationState {
+    pub format: u32,
+    /// Saved as text, because TOML integers are signed
+    #[serde(with = "text")]
+    pub seed: u64,
+    pub star: StarConfig,
+    pub spots: Vec<SpotState>,
+    /// The window of time (in days) random spots have been generated over, if they have
+    pub generated: Option<Bounds>,
+    pub generators: GeneratorPositions,
+    pub exposure: ExposureConfig,
+    pub phase_grid_samples: Option<usize>,
+    pub tolerance: Option<Tolerance>,
+    pub surface: Option<SurfaceState>,
+    pub catalog: Option<SpotCatalog>,
+    pub instruments: Vec<Instrument>,
+    pub schedule: Option<Schedule>,
+}
+
+/// A spot exactly as it is in the simulation, rather than as it was configured.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SpotState {
+    /// Latitude in radians
+    pub latitude: f64,
+    /// Longitude in radians
+    pub longitude: f64,
+    /// Radius as a fraction of the star's radius
+    pub radius: f64,
+    pub temperature: f64,
+    pub plage: bool,
+    /// When the spot appears and disappears, or `None` if it never does
+    pub lifetime: Option<Lifetime>,
+    pub generated: bool,
+    pub strip_step: Option<f64>,
+}
+
+/// The frames of a surface map, whose pixel centers are recomputed when it is restored.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SurfaceState {
+    pub nside: usize,
+    pub frames: Vec<Frame>,
+}
+
+/// How many words each generator has drawn from its stream. These are saved as text,
+/// because they are 128 bit integers.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct GeneratorPositions {
+    #[serde(with = "text")]
+    pub spot_sizes: u128,
+    #[serde(with = "text")]
+    pub spot_positions: u128,
+    #[serde(with = "text")]
+    pub spot_lifetimes: u128,
+    #[serde(with = "text")]
+    pub noise: u128,
+}
+
+impl SimulationState {
+    /// Reads a state saved with `Simulation::save_state`.
+    pub fn read(path: &Path) -> Result<SimulationState, LatherError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| LatherError::io(path, e))?;
+        SimulationState::parse(&contents).map_err(|message| LatherError::parse(path, message))
+    }
+
+    /// Writes the state to a TOML file.
+    pub fn write(&self, path: &Path) -> Result<(), LatherError> {
+        std::fs::write(path, self.to_toml()).map_err(|e| LatherError::io(path, e))
+    }
+
+    /// Writes the state as TOML, which `from_toml_str` reads back.
+    pub fn to_toml(&self) -> String {
+        // Going through a Value puts tables after plain values, which TOML requires
+        ::toml::Value::try_from(self)
+            .and_then(|value| ::toml::to_string(&value))
+            .expect("Every state can be written as TOML")
+    }
+
+    /// Parses a state from TOML written by `to_toml`.
+    pub fn from_toml_str(contents: &str) -> Result<SimulationState, LatherError> {
+        SimulationState::parse(contents).map_err(|message| LatherError::Parse {
+            path: None,
+            message,
+        })
+    }
+
+    fn parse(contents: &str) -> Result<SimulationState, String> {
+        let state: SimulationState = ::toml::from_str(contents)
+            .map_err(|e| format!("It isn't a saved simulation state: {}", e))?;
+        if state.format != STATE_FORMAT {
+            return Err(format!(
+                "It was saved in state format {}, but this version of lather reads format {}",
+                state.format, STATE_FORMAT
+            ));
+        }
+        Ok(state)
+    }
+}
+
+/// Serializes integers as strings, for those which don't fit in a TOML integer.
+mod text {
+    use super::*;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,359 @@
+use std::f64::consts;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::LatherError;
+use crate::point::Point;
+use crate::profile::Profile;
+use crate::star::Star;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SurfaceConfig {
+    pub path: String,
+    pub nside: usize,
+}
+
+/// The kind of surface feature a pixel belongs to, which determines which
+/// line profile is used for it.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Region {
+    Quiet,
+    Spot,
+    Plage,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Pixel {
+    pub index: usize,
+    pub temperature: f64,
+    pub region: Region,
+}
+
+/// The state of the surface from `time` until the start of the next frame.
+/// Pixels which are not listed are quiet photosphere at the star's temperature.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Frame {
+    pub time: f64,
+    pub pixels: Vec<Pixel>,
+}
+
+/// An equal-area pixelization of the stellar surface, using the HEALPix
+/// RING ordering scheme, which can change over time.
+#[derive(Debug, Clone)]
+pub struct SurfaceMap {
+    pub nside: usize,
+    pub frames: Vec<Frame>,
+    centers: Vec<(f64, f64)>,
+}
+
+impl SurfaceMap {
+    /// Creates a map from frames, which are sorted by time.
+    pub fn new(nside: usize, mut frames: Vec<Frame>) -> Result<SurfaceMap, LatherError> {
+        if nside == 0 {
+            return Err(LatherError::Validation(
+                "Surface map nside must be at least 1".to_string(),
+            ));
+        }
+        if frames.is_empty() {
+            return Err(LatherError::Validation(
+                "Surface map must contain at least one frame".to_string(),
+            ));
+        }
+        let npix = 12 * nside * nside;
+        for frame in &frames {
+            if !frame.time.is_finite() {
+                return Err(LatherError::Validation(format!(
+                    "Surface map frame times must be finite, but one is {}",
+                    frame.time
+                )));
+            }
+            if let Some(pixel) = frame.pixels.iter().find(|p| !p.temperature.is_finite()) {
+                return Err(LatherError::Validation(format!(
+                    "Surface map pixel {} has a temperature of {} K",
+                    pixel.index, pixel.temperature
+                )));
+            }
+            if let Some(pixel) = frame.pixels.iter().find(|p| p.index >= npix) {
+                return Err(LatherError::Validation(format!(
+                    "Surface map pixel {} is out of range for nside {}, which has {} pixels",
+                    pixel.index, nside, npix
+                )));
+            }
+        }
+        frames.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Ok(SurfaceMap {
+            nside,
+            frames,
+            centers: (0..npix).map(|p| pix2ang_ring(nside, p)).collect(),
+        })
+    }
+
+    /// Loads a map from a text file of whitespace-separated columns:
+    /// time (days), pixel index, temperature (K) and region (quiet, spot, or plage).
+    /// Lines starting with `#` are ignored.
+    pub fn from_file(path: &Path, nside: usize) -> Result<SurfaceMap, LatherError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| LatherError::io(path, e))?;
+
+        let mut frames: Vec<Frame> = Vec::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad_line = || {
+                LatherError::parse(
+                    path,
+                    format!(
+                        "Line {} should be `time pixel temperature region`, but is {:?}",
+                        line_number + 1,
+                        line
+                    ),
+                )
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 4 {
+                return Err(bad_line());
+            }
+            let finite = |field: &str| {
+                field
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(bad_line)
+            };
+            let time = finite(fields[0])?;
+            let index: usize = fields[1].parse().map_err(|_| bad_line())?;
+            let temperature = finite(fields[2])?;
+            let region = match fields[3] {
+                "quiet" => Region::Quiet,
+                "spot" => Region::Spot,
+                "plage" => Region::Plage,
+                _ => return Err(bad_line()),
+            };
+
+            let pixel = Pixel {
+                index,
+                temperature,
+                region,
+            };
+            match frames.iter_mut().find(|f| f.time == time) {
+                Some(frame) => frame.pixels.push(pixel),
+                None => frames.push(Frame {
+                    time,
+                    pixels: vec![pixel],
+                }),
+            }
+        }
+
+        SurfaceMap::new(nside, frames)
+    }
+
+    /// Returns the number of pixels on the whole sphere.
+    pub fn npix(&self) -> usize {
+        12 * self.nside * self.nside
+    }
+
+    /// Returns the index of the frame which describes the surface at `time`.
+    /// Times before the first frame use the first frame.
+    pub fn frame_index(&self, time: f64) -> usize {
+        self.frames
+            .iter()
+            .rposition(|f| f.time <= time)
+            .unwrap_or(0)
+    }
+
+    /// Returns the center of a pixel in the observer's frame at `time`, where
+    /// the observer looks down the x axis.
+    fn position(&self, star: &Star, index: usize, time: f64) -> Point {
+        let (colatitude, longitude) = self.centers[index];
+        let phase = (time % star.period) / star.period * 2.0 * consts::PI;
+        let theta = phase + longitude;
+        Point {
+            x: colatitude.sin() * theta.cos(),
+            y: colatitude.sin() * theta.sin(),
+            z: colatitude.cos(),
+        }
+        .rotated_y(star.inclination - consts::FRAC_PI_2)
+    }
+
+    /// Projected, limb-darkened area of a pixel at `time`, in the same units as
+    /// `Star::flux_quiet`. Returns `None` if the pixel is on the far side of the star.
+    fn weight(&self, star: &Star, index: usize, time: f64) -> Option<(f64, Point)> {
+        let position = self.position(star, index, time);
+        if position.x <= 0.0 {
+            return None;
+        }
+        let pixel_area = 4.0 * consts::PI / self.npix() as f64;
+        let grid_interval = 2.0 / star.grid_size as f64;
+        let weight = star.limb_brightness(position.x) * position.x * pixel_area / grid_interval;
+        Some((weight, position))
+    }
+
+    /// Computes the loss of flux relative to a quiet star at `time`, where
+    /// `intensities` holds the relative intensity of each pixel in the current frame.
+    pub fn get_flux(&self, star: &Star, time: f64, intensities: &[Vec<f64>]) -> f64 {
+        let frame_index = self.frame_index(time);
+        self.frames[frame_index]
+            .pixels
+            .iter()
+            .zip(intensities[frame_index].iter())
+            .filter_map(|(pixel, intensity)| {
+                self.weight(star, pixel.index, time)
+                    .map(|(weight, _)| (1.0 - intensity) * weight)
+            })
+            .sum()
+    }
+
+    /// Adds the change in the disk-integrated `quiet` profile at `time` to `profile`, where
+    /// `active` is the profile of spots and plages and `intensities` holds the relative
+    /// intensity of each pixel in the current frame.
+    pub fn add_profile(
+        &self,
+        star: &Star,
+        time: f64,
+        intensities: &[Vec<f64>],
+        quiet: &Profile,
+        active: &Profile,
+        profile: &mut [f64],
+    ) {
+        let mut quiet_shifted = vec![0.0; quiet.len()];
+        let mut active_shifted = vec![0.0; active.len()];
+
+        let frame_index = self.frame_index(time);
+        for (pixel, intensity) in self.frames[frame_index]
+            .pixels
+            .iter()
+            .zip(intensities[frame_index].iter())
+        {
+            if let Some((weight, position)) = self.weight(star, pixel.index, time) {
+                let velocity = position.y * star.equatorial_velocity;
+                quiet.shift_into(velocity, &mut quiet_shifted);
+                let active_profile = match pixel.region {
+                    Region::Quiet => quiet,
+                    Region::Spot | Region::Plage => active,
+                };
+                active_profile.shift_into(velocity, &mut active_shifted);
+
+                for ((tot, qshift), ashift) in profile
+                    .iter_mut()
+                    .zip(quiet_shifted.iter())
+                    .zip(active_shifted.iter())
+                {
+                    *tot += (qshift - intensity * ashift) * weight;
+                }
+            }
+        }
+    }
+}
+
+/// Converts a HEALPix RING-scheme pixel index to the (colatitude, longitude)
+/// of its center, in radians.
+pub fn pix2ang_ring(nside: usize, pixel: usize) -> (f64, f64) {
+    let nside_f = nside as f64;
+    let npix = 12 * nside * nside;
+    let ncap = 2 * nside * (nside - 1);
+
+    let (z, phi) = if pixel < ncap {
+        // North polar cap
+        let ring = ((1.0 + (1.0 + 2.0 * pixel as f64).sqrt()) / 2.0).floor() as usize;
+        let j = pixel + 1 - 2 * ring * (ring - 1);
+        let z = 1.0 - (ring * ring) as f64 / (3.0 * nside_f * nside_f);
+        let phi = (j as f64 - 0.5) * consts::PI / (2.0 * ring as f64);
+        (z, phi)
+    } else if pixel < npix - ncap {
+        // Equatorial belt
+        let offset = pixel - ncap;
+        let ring = offset / (4 * nside) + nside;
+        let j = offset % (4 * nside) + 1;
+        let shift = if (ring + nside) % 2 == 1 { 1.0 } else { 0.5 };
+        let z = 4.0 / 3.0 - 2.0 * ring as f64 / (3.0 * nside_f);
+        let phi = (j as f64 - shift) * consts::PI / (2.0 * nside_f);
+        (z, phi)
+    } else {
+        // South polar cap
+        let offset = npix - pixel;
+        let ring = ((1.0 + (2.0 * offset as f64 - 1.0).sqrt()) / 2.0).floor() as usize;
+        let j = 4 * ring + 1 - (offset - 2 * ring * (ring - 1));
+        let z = -1.0 + (ring * ring) as f64 / (3.0 * nside_f * nside_f);
+        let phi = (j as f64 - 0.5) * consts::PI / (2.0 * ring as f64);
+        (z, phi)
+    };
+
+    (z.acos(), phi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_centers_are_balanced() {
+        for &nside in &[1, 2, 4, 8] {
+            let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+            for p in 0..12 * nside * nside {
+                let (theta, phi) = pix2ang_ring(nside, p);
+                assert!((0.0..=consts::PI).contains(&theta));
+                assert!((0.0..2.0 * consts::PI).contains(&phi));
+                x += theta.sin() * phi.cos();
+                y += theta.sin() * phi.sin();
+                z += theta.cos();
+            }
+            assert!(x.abs() < 1e-9 && y.abs() < 1e-9 && z.abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn known_pixel_centers() {
+        // The first pixel of nside = 1 is in the northern ring at z = 2/3
+        let (theta, phi) = pix2ang_ring(1, 0);
+        assert!((theta.cos() - 2.0 / 3.0).abs() < 1e-12);
+        assert!((phi - consts::FRAC_PI_4).abs() < 1e-12);
+
+        let (theta, _) = pix2ang_ring(1, 11);
+        assert!((theta.cos() + 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn frames_step_in_time() {
+        let frame = |time| Frame {
+            time,
+            pixels: Vec::new(),
+        };
+        let map = SurfaceMap::new(2, vec![frame(10.0), frame(0.0)]).unwrap();
+        assert_eq!(map.frame_index(-5.0), 0);
+        assert_eq!(map.frame_index(5.0), 0);
+        assert_eq!(map.frame_index(10.0), 1);
+        assert_eq!(map.frame_index(100.0), 1);
+    }
+
+    #[test]
+    fn out_of_range_pixel() {
+        let frame = Frame {
+            time: 0.0,
+            pixels: vec![Pixel {
+                index: 48,
+                temperature: 5000.0,
+                region: Region::Spot,
+            }],
+        };
+        assert!(SurfaceMap::new(2, vec![frame]).is_err());
+    }
+
+    #[test]
+    fn nan_times_are_rejected() {
+        let frame = |time| Frame {
+            time,
+            pixels: Vec::new(),
+        };
+        assert!(SurfaceMap::new(2, vec![frame(0.0), frame(f64::NAN)]).is_err());
+
+        let path = std::env::temp_dir().join(format!("lather-nan-{}.txt", std::process::id()));
+        std::fs::write(&path, "0.0 1 4500 spot\nnan 2 4500 spot\n").unwrap();
+        let map = SurfaceMap::from_file(&path, 2);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(map, Err(LatherError::Parse { .. })));
+    }
+}
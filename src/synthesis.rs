@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bounds::Bounds;
+use crate::error::LatherError;
+use crate::profile::Profile;
+use crate::spectral_model::ModelSpectrum;
+
+const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SynthesisConfig {
+    pub quiet: PathBuf,
+    pub spot: PathBuf,
+}
+
+impl SynthesisConfig {
+    /// Makes any relative paths in this config relative to `dir` instead.
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        self.quiet = dir.join(&self.quiet);
+        self.spot = dir.join(&self.spot);
+    }
+}
+
+/// High-resolution template spectra for the quiet photosphere and spots, resampled
+/// onto a grid which is uniform in log wavelength. On that grid a Doppler shift is a
+/// uniform translation, so the spectra can be shifted exactly like a CCF `Profile`.
+#[derive(Debug)]
+pub struct Synthesis {
+    pub wavelength: Vec<f64>,
+    pub profile_quiet: Profile,
+    pub profile_spot: Profile,
+}
+
+impl Synthesis {
+    pub fn new(quiet: &ModelSpectrum, spot: &ModelSpectrum) -> Result<Synthesis, LatherError> {
+        let first = f64::max(quiet.wavelength[0], spot.wavelength[0]);
+        let last = f64::min(
+            quiet.wavelength[quiet.wavelength.len() - 1],
+            spot.wavelength[spot.wavelength.len() - 1],
+        );
+        if first >= last || first <= 0.0 {
+            return Err(LatherError::Validation(
+                "The quiet and spot template spectra must cover an overlapping range of positive wavelengths"
+                    .to_string(),
+            ));
+        }
+
+        // Keep the sampling of the quiet template
+        let len = quiet
+            .wavelength
+            .iter()
+            .filter(|w| **w >= first && **w <= last)
+            .count()
+            .max(2);
+        let log_step = (last / first).ln() / (len - 1) as f64;
+        let wavelength: Vec<f64> = (0..len)
+            .map(|i| first * (i as f64 * log_step).exp())
+            .collect();
+        // Velocity offset of each wavelength from the first
+        let velocity: Vec<f64> = (0..len)
+            .map(|i| SPEED_OF_LIGHT * i as f64 * log_step)
+            .collect();
+
+        let resample = |spectrum: &ModelSpectrum| -> Vec<f64> {
+            wavelength
+                .iter()
+                .map(|w| {
+                    let i = spectrum
+                        .wavelength
+                        .partition_point(|x| x < w)
+                        .clamp(1, spectrum.wavelength.len() - 1);
+                    let fraction = (w - spectrum.wavelength[i - 1])
+                        / (spectrum.wavelength[i] - spectrum.wavelength[i - 1]);
+                    spectrum.flux[i - 1] + fraction * (spectrum.flux[i] - spectrum.flux[i - 1])
+                })
+                .collect()
+        };
+
+        Ok(Synthesis {
+            profile_quiet: Profile::new(velocity.clone(), resample(quiet)),
+            profile_spot: Profile::new(velocity, resample(spot)),
+            wavelength,
+        })
+    }
+
+    /// Loads the quiet and spot templates from text files of whitespace-separated
+    /// wavelength (m) and flux columns.
+    pub fn from_config(
+        config: &SynthesisConfig,
+        temperature: f64,
+        spot_temperature: f64,
+    ) -> Result<Synthesis, LatherError> {
+        let quiet = ModelSpectrum::from_file(temperature, &config.quiet)?;
+        let spot = ModelSpectrum::from_file(spot_temperature, &config.spot)?;
+        Synthesis::new(&quiet, &spot)
+    }
+
+    /// The wavelength range covered by the synthesized spectra.
+    pub fn band(&self) -> Bounds {
+        Bounds::new(
+            self.wavelength[0],
+            self.wavelength[self.wavelength.len() - 1],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resampled_grid_is_log_uniform() {
+        let wavelength: Vec<f64> = (0..1000).map(|i| 5000e-10 + i as f64 * 0.01e-10).collect();
+        let flux: Vec<f64> = wavelength
+            .iter()
+            .map(|w| 1.0 - 1e10 * (w - 5000e-10))
+            .collect();
+        let quiet = ModelSpectrum::new(5778.0, wavelength.clone(), flux.clone()).unwrap();
+        let spot = ModelSpectrum::new(5115.0, wavelength, flux).unwrap();
+        let synthesis = Synthesis::new(&quiet, &spot).unwrap();
+
+        let ratios: Vec<f64> = synthesis
+            .wavelength
+            .windows(2)
+            .map(|w| w[1] / w[0])
+            .collect();
+        for ratio in &ratios {
+            assert!((ratio - ratios[0]).abs() < 1e-12);
+        }
+        // A linear spectrum survives linear resampling
+        for (w, f) in synthesis
+            .wavelength
+            .iter()
+            .zip(synthesis.profile_quiet.ccf.iter())
+        {
+            assert!((f - (1.0 - 1e10 * (w - 5000e-10))).abs() < 1e-9);
+        }
+    }
+}